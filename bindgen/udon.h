@@ -0,0 +1,29 @@
+/* Bindings to Udon externs. Generated by wasdon-bindgen, do not edit. */
+
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* A handle to an Udon object owned by the translator. */
+typedef int32_t udon_handle;
+
+/* Send the synced variables of this behaviour to the other players. */
+__attribute__((import_module("wasdon"), import_name("request_serialization")))
+void udon_request_serialization(void);
+
+__attribute__((import_module("udon"), import_name("SystemString.__ToUpper__SystemString")))
+size_t system_string_to_upper(const char *this_ptr, size_t this_len, char *out_ptr, size_t out_cap);
+
+__attribute__((import_module("udon"), import_name("UnityEngineDebug.__Log__SystemObject_UnityEngineObject__SystemVoid")))
+void unity_engine_debug_log_system_object_unity_engine_object(udon_handle arg0, udon_handle arg1);
+
+__attribute__((import_module("udon"), import_name("UnityEngineDebug.__Log__SystemObject__SystemVoid")))
+void unity_engine_debug_log_system_object(udon_handle arg0);
+
+__attribute__((import_module("udon"), import_name("UnityEngineGameObject.__ctor__SystemString__UnityEngineGameObject")))
+udon_handle unity_engine_game_object_new(const char *arg0_ptr, size_t arg0_len);
+
+__attribute__((import_module("udon"), import_name("UnityEngineTransform.__get_childCount__SystemInt32")))
+int32_t unity_engine_transform_get_child_count(udon_handle this);
@@ -0,0 +1,91 @@
+//! Bindings to Udon externs. Generated by wasdon-bindgen, do not edit.
+
+#![no_std]
+
+/// A handle to an Udon object owned by the translator.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handle(pub i32);
+
+pub mod intrinsics {
+    #[link(wasm_import_module = "wasdon")]
+    extern "C" {
+        #[link_name = "request_serialization"]
+        fn raw_request_serialization();
+    }
+
+    /// Send the synced variables of this behaviour to the other players.
+    #[inline]
+    pub fn request_serialization() {
+        unsafe { raw_request_serialization() }
+    }
+}
+
+pub mod raw {
+    #[allow(unused_imports)]
+    use super::Handle;
+
+    #[link(wasm_import_module = "udon")]
+    extern "C" {
+        #[link_name = "SystemString.__ToUpper__SystemString"]
+        pub fn system_string_to_upper(this_ptr: *const u8, this_len: usize, out_ptr: *mut u8, out_cap: usize) -> usize;
+        #[link_name = "UnityEngineDebug.__Log__SystemObject_UnityEngineObject__SystemVoid"]
+        pub fn unity_engine_debug_log_system_object_unity_engine_object(arg0: Handle, arg1: Handle);
+        #[link_name = "UnityEngineDebug.__Log__SystemObject__SystemVoid"]
+        pub fn unity_engine_debug_log_system_object(arg0: Handle);
+        #[link_name = "UnityEngineGameObject.__ctor__SystemString__UnityEngineGameObject"]
+        pub fn unity_engine_game_object_new(arg0_ptr: *const u8, arg0_len: usize) -> Handle;
+        #[link_name = "UnityEngineTransform.__get_childCount__SystemInt32"]
+        pub fn unity_engine_transform_get_child_count(this: Handle) -> i32;
+    }
+}
+
+pub mod system_string {
+    #[allow(unused_imports)]
+    use super::Handle;
+
+    /// `SystemString.__ToUpper__SystemString`
+    #[inline]
+    pub fn to_upper(this: &str, out: &mut [u8]) -> usize {
+        unsafe { super::raw::system_string_to_upper(this.as_ptr(), this.len(), out.as_mut_ptr(), out.len()) }
+    }
+}
+
+pub mod unity_engine_debug {
+    #[allow(unused_imports)]
+    use super::Handle;
+
+    /// `UnityEngineDebug.__Log__SystemObject_UnityEngineObject__SystemVoid`
+    #[inline]
+    pub fn log_system_object_unity_engine_object(arg0: Handle, arg1: Handle) {
+        unsafe { super::raw::unity_engine_debug_log_system_object_unity_engine_object(arg0, arg1) }
+    }
+
+    /// `UnityEngineDebug.__Log__SystemObject__SystemVoid`
+    #[inline]
+    pub fn log_system_object(arg0: Handle) {
+        unsafe { super::raw::unity_engine_debug_log_system_object(arg0) }
+    }
+}
+
+pub mod unity_engine_game_object {
+    #[allow(unused_imports)]
+    use super::Handle;
+
+    /// `UnityEngineGameObject.__ctor__SystemString__UnityEngineGameObject`
+    #[inline]
+    pub fn new(arg0: &str) -> Handle {
+        unsafe { super::raw::unity_engine_game_object_new(arg0.as_ptr(), arg0.len()) }
+    }
+}
+
+pub mod unity_engine_transform {
+    #[allow(unused_imports)]
+    use super::Handle;
+
+    /// `UnityEngineTransform.__get_childCount__SystemInt32`
+    #[inline]
+    pub fn get_child_count(this: Handle) -> i32 {
+        unsafe { super::raw::unity_engine_transform_get_child_count(this) }
+    }
+}
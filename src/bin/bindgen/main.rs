@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use wasdon::udon::{bindgen, extern_db::ExternDatabase};
+
+fn usage() -> anyhow::Error {
+    anyhow::anyhow!(
+        "Usage: wasdon-bindgen <extern database> <output directory> [--crate-name <name>] [--c-header <path>]"
+    )
+}
+
+fn write(path: &PathBuf, contents: &str) -> anyhow::Result<()> {
+    std::fs::write(path, contents)
+        .map_err(|err| anyhow::anyhow!("Failed to write {}: {}", path.display(), err))?;
+
+    log::info!("Wrote {}", path.display());
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    #[cfg(feature = "std")]
+    drop(env_logger::try_init());
+
+    let mut args = std::env::args().skip(1);
+
+    let mut positional = Vec::new();
+    let mut crate_name = "udon-sys".to_string();
+    let mut c_header = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--crate-name" => crate_name = args.next().ok_or_else(usage)?,
+            "--c-header" => c_header = Some(PathBuf::from(args.next().ok_or_else(usage)?)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let [extern_db, out_dir] = positional.as_slice() else {
+        return Err(usage());
+    };
+
+    let src = std::fs::read_to_string(extern_db)
+        .map_err(|err| anyhow::anyhow!("Failed to read {}: {}", extern_db, err))?;
+
+    let extern_db = ExternDatabase::from_file(extern_db, &src)?;
+
+    log::info!("Loaded {} externs", extern_db.len());
+
+    let out_dir = PathBuf::from(out_dir);
+    std::fs::create_dir_all(out_dir.join("src"))
+        .map_err(|err| anyhow::anyhow!("Failed to create {}: {}", out_dir.display(), err))?;
+
+    write(
+        &out_dir.join("Cargo.toml"),
+        &bindgen::generate_cargo_toml(&crate_name),
+    )?;
+    write(
+        &out_dir.join("src").join("lib.rs"),
+        &bindgen::generate_rust(&extern_db),
+    )?;
+
+    if let Some(c_header) = c_header {
+        write(&c_header, &bindgen::generate_c_header(&extern_db))?;
+    }
+
+    Ok(())
+}
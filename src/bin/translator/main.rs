@@ -1,39 +1,210 @@
-use std::{fs::File, io::Read};
+use std::path::Path;
 
-use wasdon::{core::InterpretableAs, udon::uasm::Uasm};
+use wasdon::{
+    core::mangle::demangle_names,
+    core::names::ModuleNames,
+    core::runtime,
+    core::source_map::{DebugLines, SourceMap},
+    core::wasm2uasm::ModuleContext,
+    udon::asset,
+    udon::extern_db::ExternDatabase,
+    udon::uasm::linker::Linker,
+    udon::uasm::printer::{UasmPrinter, UasmStyle},
+};
+
+fn load_extern_db(path: &str) -> anyhow::Result<ExternDatabase> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("Failed to read {}: {}", path, err))?;
+
+    ExternDatabase::from_file(path, &src)
+}
+
+fn write(path: &Path, contents: &str) -> anyhow::Result<()> {
+    std::fs::write(path, contents)
+        .map_err(|err| anyhow::anyhow!("Failed to write {}: {}", path.display(), err))?;
+
+    log::info!("Wrote {}", path.display());
+
+    Ok(())
+}
+
+/// Get the path of the asset at `path` in its Unity project, which the GUID of the asset is derived from.
+fn asset_project_path(path: &Path) -> anyhow::Result<String> {
+    // the asset may not exist yet, unlike its folder
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    let folder = folder
+        .canonicalize()
+        .map_err(|err| anyhow::anyhow!("Failed to find {}: {}", folder.display(), err))?;
+    let path = folder.join(path.file_name().unwrap_or_default());
+
+    asset::project_path(&path.to_string_lossy()).ok_or_else(|| {
+        anyhow::anyhow!(
+            "The asset {} is not in the Assets or Packages folder of a Unity project",
+            path.display()
+        )
+    })
+}
 
 fn main() -> anyhow::Result<()> {
+    // the mangled names in the error are printed as they are in the module
+    run().map_err(|err| anyhow::anyhow!(demangle_names(&format!("{:#}", err))))
+}
+
+fn run() -> anyhow::Result<()> {
     #[cfg(feature = "std")]
     drop(env_logger::try_init());
 
     log::info!("Starting translator");
 
-    let args = std::env::args().collect::<Vec<_>>();
+    let mut args = std::env::args().skip(1);
+
+    let mut input_wasm = None;
+    let mut extern_db = None;
+    let mut debug = false;
+    let mut source_map_path = None;
+    let mut output_path = None;
+    let mut asset_path = None;
+    let mut script_meta_path = None;
+    let mut printer = UasmPrinter::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--externs" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("No extern database specified"))?;
+                extern_db = Some(load_extern_db(&path)?);
+            }
+            "--debug" => debug = true,
+            "--source-map" => {
+                source_map_path = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("No source map path specified"))?,
+                );
+            }
+            "--output" | "-o" => {
+                output_path = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("No output path specified"))?,
+                );
+            }
+            "--asset" => {
+                asset_path = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("No asset path specified"))?,
+                );
+            }
+            "--script-meta" => {
+                script_meta_path = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("No script meta path specified"))?,
+                );
+            }
+            "--compact" => printer = printer.with_style(UasmStyle::Compact),
+            "--comments" => printer = printer.with_comments(true),
+            _ if arg.starts_with('-') => anyhow::bail!("Unknown option: {}", arg),
+            _ => {
+                if let Some(input_wasm) = &input_wasm {
+                    anyhow::bail!("More than one input file: {} and {}", input_wasm, arg)
+                }
+                input_wasm = Some(arg);
+            }
+        }
+    }
 
-    let input_wasm = args
-        .get(1)
-        .ok_or_else(|| anyhow::anyhow!("No input file specified"))?;
+    let input_wasm = input_wasm.ok_or_else(|| anyhow::anyhow!("No input file specified"))?;
 
-    let mut wasm = Vec::new();
-    File::open(input_wasm)
-        .unwrap()
-        .read_to_end(&mut wasm)
-        .unwrap();
+    // the Udon Assembly is written next to the input by default
+    let output_path = output_path.unwrap_or_else(|| {
+        Path::new(&input_wasm)
+            .with_extension("uasm")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    let wasm = std::fs::read(&input_wasm)
+        .map_err(|err| anyhow::anyhow!("Failed to read {}: {}", input_wasm, err))?;
 
     let wasm_entry = wasdon::wasm::parser::WasmEntry::new(wasm.as_slice(), 0);
 
     let mut wasm_parser = wasdon::wasm::parser::WasmParser::from(wasm_entry);
 
-    log::info!("data: {:x?}", &wasm);
     log::info!("data size: {:?}", &wasm.len());
 
-    let mut parsed_data = wasm_parser.parse_all()?;
+    let parsed_data = wasm_parser.parse_all()?;
 
     log::info!("{:?}", &parsed_data);
 
-    let uasm_units = parsed_data.interpret_all()?;
+    if let Some(extern_db) = &extern_db {
+        extern_db.validate_imports(&parsed_data)?;
+    }
+
+    let context = ModuleContext::new(&parsed_data, extern_db.as_ref())?;
+    let uasm_units = parsed_data.interpret_all(&context)?;
 
     log::info!("Units<Uasm>: {:?}", &uasm_units);
 
+    let mut linker = Linker::new();
+    linker.add_units(uasm_units);
+    runtime::helpers()?
+        .into_iter()
+        .for_each(|helper| linker.add_helper(helper));
+
+    let mut uasm = linker.link()?;
+    uasm.resolve_aliases()?;
+
+    if debug {
+        ModuleNames::new(&parsed_data)?.apply(&mut uasm);
+    }
+
+    uasm.resolve_addresses()?;
+
+    if let Some(extern_db) = &extern_db {
+        extern_db.validate_uasm(&uasm)?;
+    }
+
+    if cfg!(debug_assertions) {
+        wasdon::udon::uasm::validate(&uasm, extern_db.as_ref())?;
+    }
+
+    let (uasm_text, uasm_lines) = printer.print_with_lines(&uasm);
+    write(Path::new(&output_path), &uasm_text)?;
+
+    if let Some(path) = &asset_path {
+        let path = Path::new(path);
+        let name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("No asset name in {}", path.display()))?;
+        let project_path = asset_project_path(path)?;
+
+        let script_meta = match &script_meta_path {
+            Some(script_meta_path) => {
+                Some(std::fs::read_to_string(script_meta_path).map_err(|err| {
+                    anyhow::anyhow!("Failed to read {}: {}", script_meta_path, err)
+                })?)
+            }
+            None => None,
+        };
+        let script_guid = match &script_meta {
+            Some(script_meta) => asset::read_meta_guid(script_meta)?,
+            None => asset::UDON_ASSEMBLY_PROGRAM_ASSET_GUID,
+        };
+
+        write(path, &asset::generate_asset(&name, &uasm_text, script_guid))?;
+
+        let mut meta_path = path.as_os_str().to_owned();
+        meta_path.push(".meta");
+        write(Path::new(&meta_path), &asset::generate_meta(&project_path))?;
+    }
+
+    if let Some(path) = &source_map_path {
+        let source_map = SourceMap::new(&uasm, &uasm_lines, &DebugLines::new(&parsed_data)?);
+        write(Path::new(path), &source_map.to_string())?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,217 @@
+//! Constant evaluation of the initializers of globals, including the extended constant expressions.
+
+use ::alloc::vec::Vec;
+
+use crate::core::wasm2uasm::GlobalIndexSpace;
+use crate::udon::uasm::data::UasmValue;
+
+/// A value known at translate time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstValue {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Null,
+}
+
+impl From<ConstValue> for UasmValue {
+    fn from(value: ConstValue) -> UasmValue {
+        match value {
+            ConstValue::I32(value) => UasmValue::Int(value as i64),
+            ConstValue::I64(value) => UasmValue::Int(value),
+            ConstValue::F32(value) => UasmValue::Float(value),
+            ConstValue::F64(value) => UasmValue::Double(value),
+            ConstValue::Null => UasmValue::Null,
+        }
+    }
+}
+
+/// Evaluate a constant expression.
+///
+/// It is `None` if the value is only known at run time, as it reads an imported global
+/// or takes the address of a function with `ref.func`.
+pub fn eval(
+    expr: &wasmparser::ConstExpr<'_>,
+    globals: &GlobalIndexSpace,
+) -> anyhow::Result<Option<ConstValue>> {
+    use wasmparser::Operator;
+
+    let mut stack = Vec::new();
+
+    for operator in expr.get_operators_reader() {
+        let operator = operator
+            .map_err(|err| anyhow::anyhow!("Failed to parse constant expression: {:?}", err))?;
+
+        let value = match operator {
+            Operator::I32Const { value } => ConstValue::I32(value),
+            Operator::I64Const { value } => ConstValue::I64(value),
+            Operator::F32Const { value } => ConstValue::F32(f32::from_bits(value.bits())),
+            Operator::F64Const { value } => ConstValue::F64(f64::from_bits(value.bits())),
+            Operator::RefNull { .. } => ConstValue::Null,
+            Operator::RefFunc { .. } => return Ok(None),
+            Operator::GlobalGet { global_index } => match globals.get(global_index)?.value {
+                Some(value) => value,
+                None => return Ok(None),
+            },
+            Operator::I32Add | Operator::I32Sub | Operator::I32Mul => {
+                let (lhs, rhs) = pop_pair(&mut stack)?;
+                let (ConstValue::I32(lhs), ConstValue::I32(rhs)) = (lhs, rhs) else {
+                    anyhow::bail!("Type mismatch in constant expression: {:?}", operator)
+                };
+
+                ConstValue::I32(match operator {
+                    Operator::I32Add => lhs.wrapping_add(rhs),
+                    Operator::I32Sub => lhs.wrapping_sub(rhs),
+                    _ => lhs.wrapping_mul(rhs),
+                })
+            }
+            Operator::I64Add | Operator::I64Sub | Operator::I64Mul => {
+                let (lhs, rhs) = pop_pair(&mut stack)?;
+                let (ConstValue::I64(lhs), ConstValue::I64(rhs)) = (lhs, rhs) else {
+                    anyhow::bail!("Type mismatch in constant expression: {:?}", operator)
+                };
+
+                ConstValue::I64(match operator {
+                    Operator::I64Add => lhs.wrapping_add(rhs),
+                    Operator::I64Sub => lhs.wrapping_sub(rhs),
+                    _ => lhs.wrapping_mul(rhs),
+                })
+            }
+            Operator::End => break,
+            operator => anyhow::bail!("Unsupported constant expression: {:?}", operator),
+        };
+
+        stack.push(value);
+    }
+
+    match stack.as_slice() {
+        [value] => Ok(Some(*value)),
+        _ => anyhow::bail!("Invalid constant expression leaving {} values", stack.len()),
+    }
+}
+
+fn pop_pair(stack: &mut Vec<ConstValue>) -> anyhow::Result<(ConstValue, ConstValue)> {
+    let rhs = stack.pop();
+    let lhs = stack.pop();
+
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Ok((lhs, rhs)),
+        _ => anyhow::bail!("Stack underflow in constant expression"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::wasm2uasm::ModuleContext;
+    use crate::udon::uasm::Uasm;
+    use crate::wasm::parser::{WasmEntry, WasmParser};
+    use ::alloc::{string::ToString, vec};
+
+    fn eval_bytes(expr: &[u8], globals: &GlobalIndexSpace) -> anyhow::Result<Option<ConstValue>> {
+        eval(&wasmparser::ConstExpr::new(expr, 0), globals)
+    }
+
+    #[test]
+    fn folds_extended_constants() {
+        let globals = GlobalIndexSpace::default();
+
+        // (i32.mul (i32.add (i32.const 2) (i32.const 3)) (i32.const 4))
+        let expr = [0x41, 0x02, 0x41, 0x03, 0x6A, 0x41, 0x04, 0x6C, 0x0B];
+        assert_eq!(
+            eval_bytes(&expr, &globals).unwrap(),
+            Some(ConstValue::I32(20))
+        );
+
+        // (i64.sub (i64.const -1) (i64.const 1))
+        let expr = [0x42, 0x7F, 0x42, 0x01, 0x7D, 0x0B];
+        assert_eq!(
+            eval_bytes(&expr, &globals).unwrap(),
+            Some(ConstValue::I64(-2))
+        );
+
+        // (i32.add (i32.const 0x7FFFFFFF) (i32.const 1)) wraps around
+        let expr = [0x41, 0xFF, 0xFF, 0xFF, 0xFF, 0x07, 0x41, 0x01, 0x6A, 0x0B];
+        assert_eq!(
+            eval_bytes(&expr, &globals).unwrap(),
+            Some(ConstValue::I32(i32::MIN))
+        );
+
+        // (ref.func 0) is initialized at startup
+        assert_eq!(eval_bytes(&[0xD2, 0x00, 0x0B], &globals).unwrap(), None);
+    }
+
+    #[test]
+    fn initializes_from_imported_globals_at_startup() {
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        // (import "udon" "speed" (global i32))
+        wasm.extend([0x02, 0x0F, 0x01, 0x04]);
+        wasm.extend(b"udon");
+        wasm.push(0x05);
+        wasm.extend(b"speed");
+        wasm.extend([0x03, 0x7F, 0x00]);
+        // (global (mut i32) (i32.add (global.get 0) (i32.const 1)))
+        // (global i64 (i64.mul (i64.const 6) (i64.const 7)))
+        wasm.extend([0x06, 0x11, 0x02]);
+        wasm.extend([0x7F, 0x01, 0x23, 0x00, 0x41, 0x01, 0x6A, 0x0B]);
+        wasm.extend([0x7E, 0x00, 0x42, 0x06, 0x42, 0x07, 0x7E, 0x0B]);
+
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+
+        let globals = GlobalIndexSpace::new(&parsed).unwrap();
+        assert_eq!(globals.get(1).unwrap().value, None);
+        assert_eq!(globals.get(2).unwrap().value, Some(ConstValue::I64(42)));
+        assert!(globals.is_initialized_at_startup());
+
+        let units = parsed
+            .interpret_all::<Uasm, _>(&ModuleContext::new(&parsed, None).unwrap())
+            .unwrap();
+        let init = units
+            .iter()
+            .map(|unit| unit.to_string())
+            .find(|unit| unit.contains("__INIT__globals:"))
+            .unwrap();
+        assert!(init.contains("PUSH, speed"));
+        assert!(init.contains(
+            "EXTERN, \"SystemInt32.__op_Addition__SystemInt32_SystemInt32__SystemInt32\""
+        ));
+        assert!(init.contains("JUMP_INDIRECT, __INIT__globals__RET"));
+
+        let start = units
+            .iter()
+            .map(|unit| unit.to_string())
+            .find(|unit| unit.contains("_start:"))
+            .unwrap();
+        assert!(start.contains("JUMP, __INIT__globals"));
+    }
+
+    #[test]
+    fn initializes_function_references_at_startup() {
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        // (func)
+        wasm.extend([0x01, 0x04, 0x01, 0x60, 0x00, 0x00]);
+        wasm.extend([0x03, 0x02, 0x01, 0x00]);
+        // (global funcref (ref.func 0))
+        wasm.extend([0x06, 0x06, 0x01, 0x70, 0x00, 0xD2, 0x00, 0x0B]);
+        wasm.extend([0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B]);
+
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+
+        let context = ModuleContext::new(&parsed, None).unwrap();
+        assert_eq!(context.globals.get(0).unwrap().value, None);
+        assert!(context.globals.is_initialized_at_startup());
+
+        let units = parsed.interpret_all::<Uasm, _>(&context).unwrap();
+        let init = units
+            .iter()
+            .map(|unit| unit.to_string())
+            .find(|unit| unit.contains("__INIT__globals:"))
+            .unwrap();
+        // the address of the function
+        assert!(init.contains("__G_K0: %SystemUInt32, __F__0"));
+        assert!(init.contains("PUSH, __G_K0\n    PUSH, __G__0\n    COPY"));
+    }
+}
@@ -0,0 +1,195 @@
+use ::alloc::vec::Vec;
+
+use crate::udon::extern_db::ExternDatabase;
+use crate::udon::uasm::data::UasmType;
+use crate::udon::uasm::ExternSignature;
+
+/// How a value crosses the boundary between the guest and an extern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lowering {
+    /// passed as it is (numbers and `externref`)
+    Direct,
+    /// passed as an `i32` handle of the handle table
+    Handle,
+    /// passed as UTF-8 bytes in the linear memory (see [`crate::core::string::Utf8Strings`])
+    Utf8,
+    /// passed as an `i32` or an `i64` converted from or into the Udon type with `SystemConvert`
+    /// (booleans and the integers other than `SystemInt32` and `SystemInt64`),
+    /// or reinterpreted for `SystemUInt32` and `SystemUInt64` so that their upper half is kept
+    Convert,
+}
+
+/// How the parameters and the result of an extern import are lowered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportAbi {
+    /// the instance of an instance extern
+    pub instance: Option<Lowering>,
+    pub params: Vec<Lowering>,
+    pub ret: Option<Lowering>,
+}
+
+impl ImportAbi {
+    /// Match the wasm type of an import against the extern it names.
+    pub fn new(
+        signature: &ExternSignature,
+        instance: bool,
+        ty: &wasmparser::FuncType,
+    ) -> anyhow::Result<ImportAbi> {
+        use wasmparser::ValType;
+
+        let mut wasm_params = ty.params();
+
+        let mut lower_param = |udon_ty: &UasmType| -> anyhow::Result<Lowering> {
+            let (lowering, rest) = match (udon_ty, wasm_params) {
+                (UasmType::String, [ValType::I32, ValType::I32, rest @ ..]) => {
+                    (Lowering::Utf8, rest)
+                }
+                (udon_ty, [wasm_ty, rest @ ..]) => (lower_value(udon_ty, *wasm_ty)?, rest),
+                (udon_ty, []) => anyhow::bail!(
+                    "Missing parameter of type {} in import of {}",
+                    udon_ty.udon_name(),
+                    signature
+                ),
+            };
+            wasm_params = rest;
+
+            Ok(lowering)
+        };
+
+        let instance = if instance {
+            Some(lower_param(&UasmType::from_udon_name(&signature.class))?)
+        } else {
+            None
+        };
+
+        let params = signature
+            .params
+            .iter()
+            .map(&mut lower_param)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let ret = match (&signature.ret, wasm_params, ty.results()) {
+            (None, [], []) => None,
+            (Some(UasmType::String), [ValType::I32, ValType::I32], [ValType::I32]) => {
+                Some(Lowering::Utf8)
+            }
+            (Some(ret), [], [wasm_ty]) => Some(lower_value(ret, *wasm_ty)?),
+            _ => anyhow::bail!(
+                "Wrong arity for import of {}: {} params and {} results",
+                signature,
+                ty.params().len(),
+                ty.results().len()
+            ),
+        };
+
+        Ok(ImportAbi {
+            instance,
+            params,
+            ret,
+        })
+    }
+
+    /// Whether a value is passed as a handle, so that the handle table is needed.
+    pub fn uses_handle_table(&self) -> bool {
+        self.instance
+            .iter()
+            .chain(self.params.iter())
+            .chain(self.ret.iter())
+            .any(|lowering| *lowering == Lowering::Handle)
+    }
+
+    /// Find how the values of the import `name` are lowered,
+    /// taking whether the extern takes an instance from `extern_db` if it is loaded
+    /// and inferring it from the type of the import otherwise.
+    pub fn resolve(
+        name: &str,
+        ty: &wasmparser::FuncType,
+        extern_db: Option<&ExternDatabase>,
+    ) -> anyhow::Result<ImportAbi> {
+        match extern_db {
+            Some(extern_db) => extern_db.validate_import(name, ty),
+            None => ImportAbi::infer(&ExternSignature::parse(name)?, ty),
+        }
+    }
+
+    /// Match the wasm type of an import against the extern it names,
+    /// telling whether the extern takes an instance from the number of parameters.
+    pub fn infer(
+        signature: &ExternSignature,
+        ty: &wasmparser::FuncType,
+    ) -> anyhow::Result<ImportAbi> {
+        match (
+            ImportAbi::new(signature, false, ty),
+            ImportAbi::new(signature, true, ty),
+        ) {
+            (Ok(abi), Err(_)) | (Err(_), Ok(abi)) => Ok(abi),
+            (Ok(_), Ok(_)) => anyhow::bail!(
+                "Cannot tell whether {} takes an instance from the type of its import",
+                signature
+            ),
+            (Err(err), Err(_)) => Err(err),
+        }
+    }
+}
+
+/// Lower a single wasm value of `wasm_ty` passed where Udon expects `udon_ty`.
+fn lower_value(udon_ty: &UasmType, wasm_ty: wasmparser::ValType) -> anyhow::Result<Lowering> {
+    use wasmparser::ValType;
+
+    let lowering = match (udon_ty, wasm_ty) {
+        (udon_ty, _) if udon_ty.is_value_type() => anyhow::bail!(
+            "{} is a value type, which cannot be passed to or from the guest",
+            udon_ty.udon_name()
+        ),
+        (udon_ty, ValType::ExternRef) if udon_ty.is_reference() => Lowering::Direct,
+        (udon_ty, ValType::I32) if udon_ty.is_reference() && udon_ty != &UasmType::String => {
+            Lowering::Handle
+        }
+        (
+            UasmType::Boolean
+            | UasmType::Byte
+            | UasmType::SByte
+            | UasmType::Char
+            | UasmType::Int16
+            | UasmType::UInt16
+            | UasmType::UInt32,
+            ValType::I32,
+        )
+        | (UasmType::UInt64, ValType::I64) => Lowering::Convert,
+        (udon_ty, wasm_ty) if UasmType::try_from(wasm_ty).ok().as_ref() == Some(udon_ty) => {
+            Lowering::Direct
+        }
+        _ => anyhow::bail!("{} cannot be passed as {:?}", udon_ty.udon_name(), wasm_ty),
+    };
+
+    Ok(lowering)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::string::ToString;
+    use wasmparser::{FuncType, ValType};
+
+    use super::*;
+
+    #[test]
+    fn rejects_the_value_types() {
+        let signature =
+            ExternSignature::parse("UnityEngineTransform.__get_position__UnityEngineVector3")
+                .unwrap();
+        let ty = FuncType::new([ValType::I32], [ValType::I32]);
+
+        let err = ImportAbi::new(&signature, true, &ty).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "UnityEngineVector3 is a value type, which cannot be passed to or from the guest"
+        );
+
+        let signature =
+            ExternSignature::parse("UnityEngineTransform.__get_parent__UnityEngineTransform")
+                .unwrap();
+        let abi = ImportAbi::new(&signature, true, &ty).unwrap();
+        assert_eq!(abi.instance, Some(Lowering::Handle));
+        assert_eq!(abi.ret, Some(Lowering::Handle));
+    }
+}
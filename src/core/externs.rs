@@ -0,0 +1,207 @@
+//! The externs called by the lowered code and the runtime, built from their types.
+
+use ::alloc::{boxed::Box, format, vec, vec::Vec};
+
+use crate::udon::uasm::data::UasmType;
+use crate::udon::uasm::signature::ExternSignature;
+
+fn array(element: UasmType) -> UasmType {
+    UasmType::Array(Box::new(element))
+}
+
+/// The operator `op` of `ty` (e.g. `SystemInt32.__op_Addition__SystemInt32_SystemInt32__SystemInt32`).
+pub(crate) fn operator(
+    ty: &UasmType,
+    op: &str,
+    params: Vec<UasmType>,
+    ret: UasmType,
+) -> ExternSignature {
+    ExternSignature::new(ty.udon_name(), format!("op_{op}"), params, Some(ret))
+}
+
+/// The operator `op` of `ty` taking two operands of `ty` and returning `ty` (e.g. `Addition`).
+pub(crate) fn binary(ty: &UasmType, op: &str) -> ExternSignature {
+    operator(ty, op, vec![ty.clone(), ty.clone()], ty.clone())
+}
+
+/// The operator `op` comparing two operands of `ty` (e.g. `LessThan`).
+pub(crate) fn comparison(ty: &UasmType, op: &str) -> ExternSignature {
+    operator(ty, op, vec![ty.clone(), ty.clone()], UasmType::Boolean)
+}
+
+/// The operator `op` shifting `ty` by a `SystemInt32` (e.g. `LeftShift`).
+pub(crate) fn shift(ty: &UasmType, op: &str) -> ExternSignature {
+    operator(ty, op, vec![ty.clone(), UasmType::Int32], ty.clone())
+}
+
+/// The negation of `ty`.
+pub(crate) fn negation(ty: &UasmType) -> ExternSignature {
+    operator(ty, "UnaryMinus", vec![ty.clone()], ty.clone())
+}
+
+/// The `SystemConvert` method converting `from` into `to`.
+pub(crate) fn convert(from: &UasmType, to: &UasmType) -> ExternSignature {
+    let to_name = to.udon_name();
+    let method = to_name.strip_prefix("System").unwrap_or(&to_name);
+
+    ExternSignature::new(
+        "SystemConvert",
+        format!("To{method}"),
+        vec![from.clone()],
+        Some(to.clone()),
+    )
+}
+
+/// The method `method` of `System.Math` taking `arity` operands of `ty` and returning `ty` (e.g. `Sqrt`).
+pub(crate) fn math(method: &str, ty: &UasmType, arity: usize) -> ExternSignature {
+    ExternSignature::new(
+        "SystemMath",
+        method,
+        vec![ty.clone(); arity],
+        Some(ty.clone()),
+    )
+}
+
+/// `System.BitConverter.GetBytes` of `ty`, which gives the bytes of the value in memory.
+pub(crate) fn get_bytes(ty: &UasmType) -> ExternSignature {
+    ExternSignature::new(
+        "SystemBitConverter",
+        "GetBytes",
+        vec![ty.clone()],
+        Some(array(UasmType::Byte)),
+    )
+}
+
+/// The `System.BitConverter` method reading a `ty` from the bytes at an index (e.g. `ToUInt32`).
+pub(crate) fn from_bytes(ty: &UasmType) -> ExternSignature {
+    let name = ty.udon_name();
+    let method = name.strip_prefix("System").unwrap_or(&name);
+
+    ExternSignature::new(
+        "SystemBitConverter",
+        format!("To{method}"),
+        vec![array(UasmType::Byte), UasmType::Int32],
+        Some(ty.clone()),
+    )
+}
+
+/// The constructor of an array of `element` taking its length.
+pub(crate) fn array_ctor(element: UasmType) -> ExternSignature {
+    let ty = array(element);
+    ExternSignature::new(
+        ty.udon_name().into_owned(),
+        "ctor",
+        vec![UasmType::Int32],
+        Some(ty),
+    )
+}
+
+/// The method of an array of `element` getting the element at an index.
+pub(crate) fn array_get(element: UasmType) -> ExternSignature {
+    ExternSignature::new(
+        array(element.clone()).udon_name(),
+        "Get",
+        vec![UasmType::Int32],
+        Some(element),
+    )
+}
+
+/// The method of an array of `element` setting the element at an index.
+pub(crate) fn array_set(element: UasmType) -> ExternSignature {
+    ExternSignature::new(
+        array(element.clone()).udon_name(),
+        "Set",
+        vec![UasmType::Int32, element],
+        None,
+    )
+}
+
+/// `System.Object[].GetValue`, which takes an index.
+pub(crate) fn object_array_get_value() -> ExternSignature {
+    ExternSignature::new(
+        array(UasmType::Object).udon_name(),
+        "GetValue",
+        vec![UasmType::Int32],
+        Some(UasmType::Object),
+    )
+}
+
+/// `System.Object[].SetValue`, which takes the value and then the index.
+pub(crate) fn object_array_set_value() -> ExternSignature {
+    ExternSignature::new(
+        array(UasmType::Object).udon_name(),
+        "SetValue",
+        vec![UasmType::Object, UasmType::Int32],
+        None,
+    )
+}
+
+/// `System.Array.Copy(source, sourceIndex, destination, destinationIndex, length)`
+pub(crate) fn array_copy() -> ExternSignature {
+    let array = UasmType::Other("SystemArray".into());
+
+    ExternSignature::new(
+        array.udon_name().into_owned(),
+        "Copy",
+        vec![
+            array.clone(),
+            UasmType::Int32,
+            array,
+            UasmType::Int32,
+            UasmType::Int32,
+        ],
+        None,
+    )
+}
+
+/// `UnityEngine.Debug.LogError(object)`
+pub(crate) fn log_error() -> ExternSignature {
+    ExternSignature::new("UnityEngineDebug", "LogError", vec![UasmType::Object], None)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn names_the_externs() {
+        assert_eq!(
+            binary(&UasmType::Int32, "Addition").to_string(),
+            "SystemInt32.__op_Addition__SystemInt32_SystemInt32__SystemInt32"
+        );
+        assert_eq!(
+            shift(&UasmType::Int64, "LeftShift").to_string(),
+            "SystemInt64.__op_LeftShift__SystemInt64_SystemInt32__SystemInt64"
+        );
+        assert_eq!(
+            convert(&UasmType::Object, &UasmType::Byte).to_string(),
+            "SystemConvert.__ToByte__SystemObject__SystemByte"
+        );
+        assert_eq!(
+            math("Min", &UasmType::Int32, 2).to_string(),
+            "SystemMath.__Min__SystemInt32_SystemInt32__SystemInt32"
+        );
+        assert_eq!(
+            get_bytes(&UasmType::Int64).to_string(),
+            "SystemBitConverter.__GetBytes__SystemInt64__SystemByteArray"
+        );
+        assert_eq!(
+            from_bytes(&UasmType::UInt32).to_string(),
+            "SystemBitConverter.__ToUInt32__SystemByteArray_SystemInt32__SystemUInt32"
+        );
+        assert_eq!(
+            array_ctor(UasmType::Object).to_string(),
+            "SystemObjectArray.__ctor__SystemInt32__SystemObjectArray"
+        );
+        assert_eq!(
+            object_array_set_value().to_string(),
+            "SystemObjectArray.__SetValue__SystemObject_SystemInt32__SystemVoid"
+        );
+        assert_eq!(
+            array_copy().to_string(),
+            "SystemArray.__Copy__SystemArray_SystemInt32_SystemArray_SystemInt32_SystemInt32__SystemVoid"
+        );
+    }
+}
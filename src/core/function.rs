@@ -0,0 +1,2614 @@
+//! Lowering of the function bodies (see `docs/function.md`).
+
+use ::alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use ::core::slice;
+use hashbrown::{HashMap, HashSet};
+
+use crate::core::extern_abi::{ImportAbi, Lowering};
+use crate::core::externs;
+use crate::core::handle_table::HandleTable;
+use crate::core::intrinsic::{Intrinsic, INTRINSIC_IMPORT_MODULE};
+use crate::core::memory::LinearMemory;
+use crate::core::runtime::{code, copy, jump, jump_if_false, jump_indirect, push, HALT_ADDRESS};
+use crate::core::string::Utf8Strings;
+use crate::core::wasm2uasm::{
+    function_label, function_name, generate_variable_name, local_var, result_var,
+    return_address_var, FunctionIndexSpace, GlobalIndexSpace, VarInfo,
+};
+use crate::core::ParsedData;
+use crate::udon::extern_db::ExternDatabase;
+use crate::udon::uasm::data::{
+    UasmCodeLabel, UasmCodeSection, UasmData, UasmDataAttribute, UasmDataSection, UasmInstruction,
+    UasmOpcode, UasmType, UasmValue, UasmVarName, UasmVariable,
+};
+use crate::udon::uasm::{ExternSignature, Uasm};
+use crate::udon::EXTERN_IMPORT_MODULE;
+
+/// Builds the code and the variables of a unit, such as the body of a function or a subroutine run at startup.
+///
+/// The names it generates start with its scope (e.g. `F0`, see `docs/variable.md`),
+/// and the instructions are laid out in blocks falling through into the next one.
+#[derive(Debug)]
+pub struct CodeBuilder {
+    scope: String,
+    export: bool,
+    blocks: Vec<(UasmCodeLabel, Vec<UasmInstruction>)>,
+    variables: Vec<UasmVariable>,
+    declared: HashSet<UasmVarName>,
+    constants: HashMap<(UasmType, String), UasmVarName>,
+    temps: HashMap<(UasmType, usize), UasmVarName>,
+    /// the number of temporaries of each type used by the current operator
+    temps_in_use: HashMap<UasmType, usize>,
+    labels: usize,
+    return_sites: usize,
+    /// the offset of the current operator in the module
+    offset: Option<usize>,
+}
+
+impl CodeBuilder {
+    /// Start building the code at `label`.
+    pub fn new(scope: &str, label: UasmCodeLabel) -> CodeBuilder {
+        CodeBuilder {
+            scope: scope.into(),
+            export: false,
+            blocks: vec![(label, Vec::new())],
+            variables: Vec::new(),
+            declared: HashSet::new(),
+            constants: HashMap::new(),
+            temps: HashMap::new(),
+            temps_in_use: HashMap::new(),
+            labels: 0,
+            return_sites: 0,
+            offset: None,
+        }
+    }
+
+    /// Export the label the code starts at.
+    pub fn with_export(self, export: bool) -> CodeBuilder {
+        CodeBuilder { export, ..self }
+    }
+
+    /// Attach the offset of the operator being lowered to the next instructions.
+    ///
+    /// The temporaries of the previous operator are reused.
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = Some(offset);
+        self.temps_in_use.clear();
+    }
+
+    /// Push the call of the extern `signature` (see [`ExternSignature::call`]).
+    pub fn call(
+        &mut self,
+        signature: &ExternSignature,
+        instance: Option<&UasmVarName>,
+        args: &[UasmVarName],
+        result: Option<&UasmVarName>,
+    ) -> anyhow::Result<()> {
+        self.push(signature.call(instance, args, result)?);
+
+        Ok(())
+    }
+
+    pub fn push(&mut self, instructions: impl IntoIterator<Item = UasmInstruction>) {
+        let offset = self.offset;
+        let (_, block) = self
+            .blocks
+            .last_mut()
+            .expect("the code starts with a block");
+
+        block.extend(instructions.into_iter().map(|instruction| match offset {
+            Some(offset) => instruction.with_offset(offset),
+            None => instruction,
+        }));
+    }
+
+    /// Declare a variable of this unit, unless it's already declared.
+    pub fn declare(&mut self, name: &UasmVarName, ty: UasmType, value: UasmValue) {
+        if self.declared.insert(name.clone()) {
+            self.variables
+                .push(UasmVariable::new(name.clone(), ty).with_value(value));
+        }
+    }
+
+    /// Get a variable of the scope, declaring it with `ty`.
+    pub fn var(&mut self, info: VarInfo, ty: UasmType) -> UasmVarName {
+        let name = UasmVarName::new(generate_variable_name(info).into());
+        self.declare(&name, ty, UasmValue::Null);
+
+        name
+    }
+
+    /// Get a temporary which isn't used by the current operator yet.
+    pub fn temp(&mut self, ty: UasmType) -> UasmVarName {
+        let in_use = self.temps_in_use.entry(ty.clone()).or_default();
+        let key = (ty.clone(), *in_use);
+        *in_use += 1;
+
+        if let Some(name) = self.temps.get(&key) {
+            return name.clone();
+        }
+
+        let info = VarInfo::Temp {
+            scope: self.scope.clone(),
+            index: self.temps.len(),
+        };
+        let name = self.var(info, ty);
+        self.temps.insert(key, name.clone());
+
+        name
+    }
+
+    /// Get a variable holding `value`, shared by the whole unit.
+    pub fn constant(&mut self, ty: UasmType, value: UasmValue) -> UasmVarName {
+        let key = (ty.clone(), value.to_string());
+        if let Some(name) = self.constants.get(&key) {
+            return name.clone();
+        }
+
+        let name = UasmVarName::new(
+            generate_variable_name(VarInfo::Const {
+                scope: self.scope.clone(),
+                index: self.constants.len(),
+            })
+            .into(),
+        );
+        self.declare(&name, ty, value);
+        self.constants.insert(key, name.clone());
+
+        name
+    }
+
+    /// Get a new label, which is placed with [`CodeBuilder::start_block`].
+    pub fn new_label(&mut self) -> UasmCodeLabel {
+        let label = UasmCodeLabel::new(
+            generate_variable_name(VarInfo::Label {
+                scope: self.scope.clone(),
+                index: self.labels,
+            })
+            .into(),
+        );
+        self.labels += 1;
+
+        label
+    }
+
+    /// Place `label` after the instructions pushed so far.
+    pub fn start_block(&mut self, label: UasmCodeLabel) {
+        self.blocks.push((label, Vec::new()));
+    }
+
+    /// Jump to the subroutine at `label`, which returns to the address in `return_address`.
+    ///
+    /// The next instructions run once it returns.
+    pub fn call_subroutine(
+        &mut self,
+        label: &UasmCodeLabel,
+        return_address: &UasmVarName,
+    ) -> anyhow::Result<()> {
+        self.call_with(|address| {
+            let mut instructions = copy(address, return_address).to_vec();
+            instructions.push(jump(label));
+
+            Ok(instructions)
+        })
+    }
+
+    /// Push the call of a subroutine built by `call` from the variable holding the address to return to.
+    ///
+    /// The next instructions run once it returns.
+    pub fn call_with(
+        &mut self,
+        call: impl FnOnce(&UasmVarName) -> anyhow::Result<Vec<UasmInstruction>>,
+    ) -> anyhow::Result<()> {
+        let return_site = self.new_label();
+        let address = UasmVarName::new(
+            generate_variable_name(VarInfo::ReturnSite {
+                scope: self.scope.clone(),
+                index: self.return_sites,
+            })
+            .into(),
+        );
+        self.return_sites += 1;
+        self.declare(
+            &address,
+            UasmType::UInt32,
+            UasmValue::Address(return_site.clone()),
+        );
+
+        self.push(call(&address)?);
+        self.start_block(return_site);
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<Uasm> {
+        let mut data_section = UasmDataSection::new();
+        for variable in self.variables {
+            data_section.push_data(&UasmData {
+                attribute: UasmDataAttribute::default(),
+                variable,
+            });
+        }
+
+        let mut code = code(self.blocks)?;
+        if let Some((_, block)) = code.blocks_mut().next() {
+            block.set_export(self.export);
+        }
+
+        Ok(Uasm::new(
+            Some(data_section),
+            Some(UasmCodeSection::new(code)),
+        ))
+    }
+}
+
+/// What the body of a function needs to know about the module.
+pub struct FunctionContext<'a> {
+    pub function_index: u32,
+    pub functions: &'a FunctionIndexSpace,
+    pub globals: &'a GlobalIndexSpace,
+    pub memory: Option<&'a LinearMemory>,
+    /// the externs known to UdonVM, if loaded
+    pub extern_db: Option<&'a ExternDatabase>,
+}
+
+/// Lower the body of a function defined in the module.
+///
+/// The operand stack is held in a variable per depth and type, so that the values of a block end up
+/// in the same variables whichever branch is taken.
+pub fn lower_function(
+    context: FunctionContext<'_>,
+    body: &wasmparser::FunctionBody<'_>,
+) -> anyhow::Result<Uasm> {
+    let function_index = context.function_index;
+    let err = |err| {
+        anyhow::anyhow!(
+            "Failed to parse the body of function {}: {:?}",
+            function_index,
+            err
+        )
+    };
+
+    let ty = context.functions.get(function_index)?.ty.clone();
+
+    let mut locals = ty
+        .params()
+        .iter()
+        .map(|ty| UasmType::try_from(*ty))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    for local in body.get_locals_reader().map_err(err)? {
+        let (count, ty) = local.map_err(err)?;
+        for _ in 0..count {
+            locals.push(UasmType::try_from(ty)?);
+        }
+    }
+
+    let results = ty
+        .results()
+        .iter()
+        .map(|ty| UasmType::try_from(*ty))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut lowering = FunctionLowering {
+        code: CodeBuilder::new(
+            &function_name(function_index),
+            function_label(function_index),
+        ),
+        context,
+        locals,
+        stack: Vec::new(),
+        frames: vec![Frame {
+            kind: FrameKind::Function,
+            height: 0,
+            params: Vec::new(),
+            results,
+            label: None,
+            else_label: None,
+        }],
+        unreachable: false,
+        skipped: 0,
+    };
+    lowering.enter(ty.params().len());
+
+    let mut reader = body.get_operators_reader().map_err(err)?;
+    while !reader.eof() {
+        let (operator, offset) = reader.read_with_offset().map_err(err)?;
+
+        lowering.code.set_offset(offset);
+        lowering.lower(&operator).map_err(|err| {
+            anyhow::anyhow!(
+                "{} (function {} at offset {:#x})",
+                err,
+                function_index,
+                offset
+            )
+        })?;
+    }
+
+    lowering.code.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Function,
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+/// A block of the function being lowered.
+#[derive(Debug)]
+struct Frame {
+    kind: FrameKind,
+    /// the height of the operand stack below the parameters of the block
+    height: usize,
+    params: Vec<UasmType>,
+    results: Vec<UasmType>,
+    /// the label a branch to the block jumps to, which is the start of a loop and the end of the other blocks
+    label: Option<UasmCodeLabel>,
+    else_label: Option<UasmCodeLabel>,
+}
+
+impl Frame {
+    /// the types of the values a branch to the block carries
+    fn branch_types(&self) -> &[UasmType] {
+        match self.kind {
+            FrameKind::Loop => &self.params,
+            _ => &self.results,
+        }
+    }
+}
+
+struct FunctionLowering<'a> {
+    code: CodeBuilder,
+    context: FunctionContext<'a>,
+    locals: Vec<UasmType>,
+    /// the types of the values on the operand stack
+    stack: Vec<UasmType>,
+    frames: Vec<Frame>,
+    /// whether the current operator can't be reached, e.g. after a `br`
+    unreachable: bool,
+    /// the number of blocks opened in unreachable code
+    skipped: usize,
+}
+
+/// the zero of a type, which is the initial value of a local
+fn zero(ty: &UasmType) -> UasmValue {
+    match ty {
+        UasmType::Int32 | UasmType::Int64 => UasmValue::Int(0),
+        UasmType::Single => UasmValue::Float(0.0),
+        UasmType::Double => UasmValue::Double(0.0),
+        _ => UasmValue::Null,
+    }
+}
+
+/// the unsigned integer type of the same size as `ty`
+fn unsigned(ty: &UasmType) -> UasmType {
+    match ty {
+        UasmType::Int32 => UasmType::UInt32,
+        _ => UasmType::UInt64,
+    }
+}
+
+impl FunctionLowering<'_> {
+    fn scope(&self) -> String {
+        function_name(self.context.function_index)
+    }
+
+    /// Declare the locals and reset the ones which aren't parameters, as a function can be called again.
+    fn enter(&mut self, params: usize) {
+        let function_index = self.context.function_index;
+
+        for (local_index, ty) in self.locals.clone().into_iter().enumerate() {
+            let local = local_var(function_index, local_index);
+            self.code.declare(&local, ty.clone(), UasmValue::Null);
+
+            if local_index >= params {
+                let zero = self.code.constant(ty.clone(), zero(&ty));
+                self.code.push(copy(&zero, &local));
+            }
+        }
+    }
+
+    fn local(&self, local_index: u32) -> anyhow::Result<(UasmVarName, UasmType)> {
+        let ty = self
+            .locals
+            .get(local_index as usize)
+            .ok_or_else(|| anyhow::anyhow!("Unknown local: {}", local_index))?;
+
+        Ok((
+            local_var(self.context.function_index, local_index as usize),
+            ty.clone(),
+        ))
+    }
+
+    /// Get the variable holding the operand at `depth` in the stack.
+    fn slot(&mut self, depth: usize, ty: &UasmType) -> UasmVarName {
+        let suffix = match ty {
+            UasmType::Int32 => "i32",
+            UasmType::Int64 => "i64",
+            UasmType::Single => "f32",
+            UasmType::Double => "f64",
+            _ => "ref",
+        };
+        let info = VarInfo::Stack {
+            scope: self.scope(),
+            depth,
+            ty: suffix,
+        };
+
+        self.code.var(info, ty.clone())
+    }
+
+    fn push_value(&mut self, ty: UasmType) -> UasmVarName {
+        let slot = self.slot(self.stack.len(), &ty);
+        self.stack.push(ty);
+
+        slot
+    }
+
+    fn pop_value(&mut self) -> anyhow::Result<(UasmVarName, UasmType)> {
+        Ok(self.pop_values(1)?.remove(0))
+    }
+
+    /// Pop `count` operands, returned in the order they were pushed.
+    fn pop_values(&mut self, count: usize) -> anyhow::Result<Vec<(UasmVarName, UasmType)>> {
+        let values = self.peek_values(count)?;
+        self.stack.truncate(self.stack.len() - count);
+
+        Ok(values)
+    }
+
+    fn peek_values(&mut self, count: usize) -> anyhow::Result<Vec<(UasmVarName, UasmType)>> {
+        let height = self.frames.last().map_or(0, |frame| frame.height);
+        if self.stack.len() < height + count {
+            anyhow::bail!("Operand stack underflow")
+        }
+
+        let start = self.stack.len() - count;
+        Ok((start..self.stack.len())
+            .map(|depth| {
+                let ty = self.stack[depth].clone();
+                (self.slot(depth, &ty), ty)
+            })
+            .collect())
+    }
+
+    /// Skip the rest of the current block, which can't be reached.
+    fn set_unreachable(&mut self) {
+        let height = self.frames.last().map_or(0, |frame| frame.height);
+        self.stack.truncate(height);
+        self.unreachable = true;
+    }
+
+    fn block_type(
+        &self,
+        block_type: wasmparser::BlockType,
+    ) -> anyhow::Result<(Vec<UasmType>, Vec<UasmType>)> {
+        use wasmparser::BlockType;
+
+        let types = |types: &[wasmparser::ValType]| {
+            types
+                .iter()
+                .map(|ty| UasmType::try_from(*ty))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+
+        match block_type {
+            BlockType::Empty => Ok((Vec::new(), Vec::new())),
+            BlockType::Type(ty) => Ok((Vec::new(), vec![UasmType::try_from(ty)?])),
+            BlockType::FuncType(type_index) => {
+                let ty = self.context.functions.func_type(type_index)?;
+                Ok((types(ty.params())?, types(ty.results())?))
+            }
+        }
+    }
+
+    fn open(
+        &mut self,
+        kind: FrameKind,
+        block_type: wasmparser::BlockType,
+        label: UasmCodeLabel,
+        else_label: Option<UasmCodeLabel>,
+    ) -> anyhow::Result<()> {
+        let (params, results) = self.block_type(block_type)?;
+        let height = self
+            .stack
+            .len()
+            .checked_sub(params.len())
+            .ok_or_else(|| anyhow::anyhow!("Operand stack underflow"))?;
+
+        self.frames.push(Frame {
+            kind,
+            height,
+            params,
+            results,
+            label: Some(label),
+            else_label,
+        });
+
+        Ok(())
+    }
+
+    /// Branch to the block `relative_depth` levels out, carrying the values it expects.
+    fn branch(&mut self, relative_depth: u32) -> anyhow::Result<()> {
+        let frame = self
+            .frames
+            .len()
+            .checked_sub(relative_depth as usize + 1)
+            .map(|index| &self.frames[index])
+            .ok_or_else(|| anyhow::anyhow!("Unknown block: {}", relative_depth))?;
+
+        let Some(label) = frame.label.clone() else {
+            return self.return_values();
+        };
+
+        let height = frame.height;
+        let types = frame.branch_types().to_vec();
+        let values = self.peek_values(types.len())?;
+
+        for (index, ((value, _), ty)) in values.iter().zip(types.iter()).enumerate() {
+            let slot = self.slot(height + index, ty);
+            if *value != slot {
+                self.code.push(copy(value, &slot));
+            }
+        }
+        self.code.push([jump(&label)]);
+
+        Ok(())
+    }
+
+    /// Whether a `br_if` to the block `relative_depth` levels out is a single jump,
+    /// once its condition is popped.
+    fn is_jump_after_condition(&self, relative_depth: u32) -> bool {
+        let Some(frame) = self
+            .frames
+            .len()
+            .checked_sub(relative_depth as usize + 1)
+            .map(|index| &self.frames[index])
+        else {
+            return false;
+        };
+
+        frame.label.is_some() && self.stack.len() == frame.height + frame.branch_types().len() + 1
+    }
+
+    /// Return the values on the top of the stack to the caller.
+    fn return_values(&mut self) -> anyhow::Result<()> {
+        let function_index = self.context.function_index;
+        let count = self
+            .frames
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Operator after the end of the function"))?
+            .results
+            .len();
+
+        for (result_index, (value, _)) in self.peek_values(count)?.into_iter().enumerate() {
+            self.code
+                .push(copy(&value, &result_var(function_index, result_index)));
+        }
+        self.code
+            .push([jump_indirect(&return_address_var(function_index))]);
+
+        Ok(())
+    }
+
+    /// Turn the `i32` on the top of the stack into a `SystemBoolean` telling whether it's not zero.
+    fn pop_condition(&mut self) -> anyhow::Result<UasmVarName> {
+        let (value, _) = self.pop_value()?;
+
+        self.test_zero(&value, "Inequality")
+    }
+
+    /// Compare the `i32` `value` with zero with the operator `op`.
+    fn test_zero(&mut self, value: &UasmVarName, op: &str) -> anyhow::Result<UasmVarName> {
+        let zero = self.code.constant(UasmType::Int32, UasmValue::Int(0));
+        let condition = self.code.temp(UasmType::Boolean);
+
+        self.code.call(
+            &externs::comparison(&UasmType::Int32, op),
+            None,
+            &[value.clone(), zero],
+            Some(&condition),
+        )?;
+
+        Ok(condition)
+    }
+
+    fn constant(&mut self, ty: UasmType, value: UasmValue) {
+        let constant = self.code.constant(ty.clone(), value);
+        let slot = self.push_value(ty);
+        self.code.push(copy(&constant, &slot));
+    }
+
+    /// Lower an operator taking two operands of `ty` and returning `ty` with the operator `op` of its class.
+    fn binary(&mut self, ty: UasmType, op: &str) -> anyhow::Result<()> {
+        let signature = externs::binary(&ty, op);
+
+        let args = self.pop_values(2)?;
+        let result = self.push_value(ty);
+        self.code.call(
+            &signature,
+            None,
+            &[args[0].0.clone(), args[1].0.clone()],
+            Some(&result),
+        )?;
+
+        Ok(())
+    }
+
+    /// Lower `rem_s`, which gives `0` for a divisor of `-1`
+    /// where the `%` of .NET throws for the minimum value.
+    fn remainder(&mut self, ty: UasmType) -> anyhow::Result<()> {
+        let minus_one = self.code.constant(ty.clone(), UasmValue::Int(-1));
+        let zero = self.code.constant(ty.clone(), UasmValue::Int(0));
+
+        let args = self.pop_values(2)?;
+        let [(lhs, _), (rhs, _)] = args.as_slice() else {
+            unreachable!("two values are popped")
+        };
+        let condition = self.code.temp(UasmType::Boolean);
+        self.code.call(
+            &externs::comparison(&ty, "Inequality"),
+            None,
+            &[rhs.clone(), minus_one],
+            Some(&condition),
+        )?;
+
+        let minus_one_label = self.code.new_label();
+        let end_label = self.code.new_label();
+        let result = self.push_value(ty.clone());
+        self.code.push(jump_if_false(&condition, &minus_one_label));
+        self.code.call(
+            &externs::binary(&ty, "Modulus"),
+            None,
+            &[lhs.clone(), rhs.clone()],
+            Some(&result),
+        )?;
+        self.code.push([jump(&end_label)]);
+
+        self.code.start_block(minus_one_label);
+        self.code.push(copy(&zero, &result));
+        self.code.start_block(end_label);
+
+        Ok(())
+    }
+
+    /// Copy the bits of `value` of `from` into `result` of `to`, which has the same size,
+    /// e.g. to read an `int` as a `uint` where `SystemConvert` throws for a negative value.
+    fn reinterpret(
+        &mut self,
+        value: &UasmVarName,
+        from: &UasmType,
+        to: &UasmType,
+        result: &UasmVarName,
+    ) -> anyhow::Result<()> {
+        let bytes = self.code.temp(UasmType::Array(UasmType::Byte.into()));
+        let zero = self.code.constant(UasmType::Int32, UasmValue::Int(0));
+
+        self.code.call(
+            &externs::get_bytes(from),
+            None,
+            slice::from_ref(value),
+            Some(&bytes),
+        )?;
+        self.code
+            .call(&externs::from_bytes(to), None, &[bytes, zero], Some(result))
+    }
+
+    /// Convert `value` of `from` into `result` of `to` for an extern (see [`Lowering::Convert`]).
+    ///
+    /// An `int` and a `uint` (or a `long` and a `ulong`) are reinterpreted,
+    /// as `SystemConvert` throws for the values out of the range of the other type.
+    fn convert(
+        &mut self,
+        value: &UasmVarName,
+        from: &UasmType,
+        to: &UasmType,
+        result: &UasmVarName,
+    ) -> anyhow::Result<()> {
+        match (from, to) {
+            (UasmType::Int32, UasmType::UInt32)
+            | (UasmType::UInt32, UasmType::Int32)
+            | (UasmType::Int64, UasmType::UInt64)
+            | (UasmType::UInt64, UasmType::Int64) => self.reinterpret(value, from, to, result),
+            _ => self.code.call(
+                &externs::convert(from, to),
+                None,
+                slice::from_ref(value),
+                Some(result),
+            ),
+        }
+    }
+
+    /// Lower `div_u` or `rem_u` with the operator `op` of the unsigned type of the same size.
+    fn binary_unsigned(&mut self, ty: UasmType, op: &str) -> anyhow::Result<()> {
+        let unsigned = unsigned(&ty);
+
+        let args = self.pop_values(2)?;
+        let mut operands = Vec::new();
+        for (value, _) in args {
+            let operand = self.code.temp(unsigned.clone());
+            self.reinterpret(&value, &ty, &unsigned, &operand)?;
+            operands.push(operand);
+        }
+
+        let value = self.code.temp(unsigned.clone());
+        self.code.call(
+            &externs::binary(&unsigned, op),
+            None,
+            &operands,
+            Some(&value),
+        )?;
+        let result = self.push_value(ty.clone());
+        self.reinterpret(&value, &unsigned, &ty, &result)
+    }
+
+    /// Lower a comparison of two operands of `ty`, which returns an `i32`.
+    fn compare(&mut self, ty: UasmType, op: &str) -> anyhow::Result<()> {
+        let args = self.pop_values(2)?;
+        self.compare_values(&ty, op, &args[0].0, &args[1].0)?;
+
+        Ok(())
+    }
+
+    fn compare_values(
+        &mut self,
+        ty: &UasmType,
+        op: &str,
+        lhs: &UasmVarName,
+        rhs: &UasmVarName,
+    ) -> anyhow::Result<()> {
+        let condition = self.code.temp(UasmType::Boolean);
+        self.code.call(
+            &externs::comparison(ty, op),
+            None,
+            &[lhs.clone(), rhs.clone()],
+            Some(&condition),
+        )?;
+
+        let result = self.push_value(UasmType::Int32);
+        self.code.call(
+            &externs::convert(&UasmType::Boolean, &UasmType::Int32),
+            None,
+            &[condition],
+            Some(&result),
+        )
+    }
+
+    /// Lower an unsigned comparison, flipping the sign bits so that the signed comparison gives the same result.
+    fn compare_unsigned(&mut self, ty: UasmType, op: &str) -> anyhow::Result<()> {
+        let min = match ty {
+            UasmType::Int32 => i32::MIN.into(),
+            _ => i64::MIN,
+        };
+        let min = self.code.constant(ty.clone(), UasmValue::Int(min));
+
+        let args = self.pop_values(2)?;
+        let mut flipped = Vec::new();
+        for (value, _) in args {
+            let temp = self.code.temp(ty.clone());
+            self.code.call(
+                &externs::binary(&ty, "LogicalXor"),
+                None,
+                &[value, min.clone()],
+                Some(&temp),
+            )?;
+            flipped.push(temp);
+        }
+
+        self.compare_values(&ty, op, &flipped[0], &flipped[1])?;
+
+        Ok(())
+    }
+
+    fn eqz(&mut self, ty: UasmType) -> anyhow::Result<()> {
+        let (value, _) = self.pop_value()?;
+        let zero = self.code.constant(ty.clone(), UasmValue::Int(0));
+        self.compare_values(&ty, "Equality", &value, &zero)?;
+
+        Ok(())
+    }
+
+    /// Shift `value` of `ty` by the `SystemInt32` `count` with the operator `op`.
+    fn shift_values(
+        &mut self,
+        ty: &UasmType,
+        op: &str,
+        value: &UasmVarName,
+        count: &UasmVarName,
+        result: &UasmVarName,
+    ) -> anyhow::Result<()> {
+        self.code.call(
+            &externs::shift(ty, op),
+            None,
+            &[value.clone(), count.clone()],
+            Some(result),
+        )
+    }
+
+    /// Pop the count of a shift of `ty`, which is a `SystemInt32` even for an `i64`.
+    fn pop_shift_count(&mut self, ty: &UasmType) -> anyhow::Result<UasmVarName> {
+        let (count, _) = self.pop_value()?;
+        if *ty == UasmType::Int32 {
+            return Ok(count);
+        }
+
+        // the count is taken modulo 64, as the shifts of .NET do
+        let mask = self.code.constant(UasmType::Int64, UasmValue::Int(63));
+        let masked = self.code.temp(UasmType::Int64);
+        self.code.call(
+            &externs::binary(&UasmType::Int64, "LogicalAnd"),
+            None,
+            &[count, mask],
+            Some(&masked),
+        )?;
+        let converted = self.code.temp(UasmType::Int32);
+        self.code.call(
+            &externs::convert(&UasmType::Int64, &UasmType::Int32),
+            None,
+            &[masked],
+            Some(&converted),
+        )?;
+
+        Ok(converted)
+    }
+
+    fn shift(&mut self, ty: UasmType, op: &str) -> anyhow::Result<()> {
+        let count = self.pop_shift_count(&ty)?;
+        let (value, _) = self.pop_value()?;
+        let result = self.push_value(ty.clone());
+        self.shift_values(&ty, op, &value, &count, &result)?;
+
+        Ok(())
+    }
+
+    /// Lower `shr_u` as an arithmetic shift clearing the bits copied from the sign bit.
+    ///
+    /// The mask is `!((-1 << (bits - 1 - count)) << 1)`, which keeps `bits - count` bits.
+    fn shift_right_unsigned(&mut self, ty: UasmType) -> anyhow::Result<()> {
+        let bits = if ty == UasmType::Int32 { 32 } else { 64 };
+
+        let count = self.pop_shift_count(&ty)?;
+        let (value, _) = self.pop_value()?;
+
+        let count_mask = self
+            .code
+            .constant(UasmType::Int32, UasmValue::Int(bits - 1));
+        let one = self.code.constant(UasmType::Int32, UasmValue::Int(1));
+        let all_ones = self.code.constant(ty.clone(), UasmValue::Int(-1));
+
+        let masked_count = self.code.temp(UasmType::Int32);
+        self.code.call(
+            &externs::binary(&UasmType::Int32, "LogicalAnd"),
+            None,
+            &[count.clone(), count_mask.clone()],
+            Some(&masked_count),
+        )?;
+        let kept = self.code.temp(UasmType::Int32);
+        self.code.call(
+            &externs::binary(&UasmType::Int32, "Subtraction"),
+            None,
+            &[count_mask, masked_count],
+            Some(&kept),
+        )?;
+
+        let mask = self.code.temp(ty.clone());
+        self.shift_values(&ty, "LeftShift", &all_ones, &kept, &mask)?;
+        self.shift_values(&ty, "LeftShift", &mask, &one, &mask)?;
+        self.code.call(
+            &externs::binary(&ty, "LogicalXor"),
+            None,
+            &[mask.clone(), all_ones],
+            Some(&mask),
+        )?;
+
+        let shifted = self.code.temp(ty.clone());
+        self.shift_values(&ty, "RightShift", &value, &count, &shifted)?;
+
+        let result = self.push_value(ty.clone());
+        self.code.call(
+            &externs::binary(&ty, "LogicalAnd"),
+            None,
+            &[shifted, mask],
+            Some(&result),
+        )?;
+
+        Ok(())
+    }
+
+    /// Keep the low `bits` bits of `ty`, extending their sign.
+    fn extend_sign(&mut self, ty: UasmType, bits: i64) -> anyhow::Result<()> {
+        let width = if ty == UasmType::Int32 { 32 } else { 64 };
+        let (value, _) = self.pop_value()?;
+        let count = self
+            .code
+            .constant(UasmType::Int32, UasmValue::Int(width - bits));
+
+        let shifted = self.code.temp(ty.clone());
+        self.shift_values(&ty, "LeftShift", &value, &count, &shifted)?;
+        let result = self.push_value(ty.clone());
+        self.shift_values(&ty, "RightShift", &shifted, &count, &result)?;
+
+        Ok(())
+    }
+
+    /// Lower an operator calling the extern `signature` with the operand on the top of the stack.
+    fn unary(&mut self, signature: &ExternSignature, ty: UasmType) -> anyhow::Result<()> {
+        let (value, _) = self.pop_value()?;
+        let result = self.push_value(ty);
+        self.code.call(signature, None, &[value], Some(&result))?;
+
+        Ok(())
+    }
+
+    /// Lower a float operator with the method `method` of `System.Math`, which takes `double`s.
+    fn math(&mut self, ty: UasmType, method: &str, arity: usize) -> anyhow::Result<()> {
+        let double = UasmType::Double;
+
+        let args = self.pop_values(arity)?;
+        let mut operands = Vec::new();
+        for (value, _) in args {
+            if ty == double {
+                operands.push(value);
+                continue;
+            }
+
+            let operand = self.code.temp(double.clone());
+            self.code.call(
+                &externs::convert(&ty, &double),
+                None,
+                &[value],
+                Some(&operand),
+            )?;
+            operands.push(operand);
+        }
+
+        let value = self.code.temp(double.clone());
+        self.code.call(
+            &externs::math(method, &double, arity),
+            None,
+            &operands,
+            Some(&value),
+        )?;
+
+        let result = self.push_value(ty.clone());
+        if ty == double {
+            self.code.push(copy(&value, &result));
+        } else {
+            // the result is exact in `double` but for `sqrt`, which `double` is precise enough to round twice
+            self.code.call(
+                &externs::convert(&double, &ty),
+                None,
+                &[value],
+                Some(&result),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Lower `min` or `max` of floats with `System.Math.Min` or `System.Math.Max`.
+    ///
+    /// They return a `NaN` operand as is, but not the zero of the expected sign
+    /// out of `-0.0` and `0.0`: two equal zeros are combined with additions instead,
+    /// `a + b` giving `-0.0` only if both are `-0.0` and `-(-a + -b)` only if one of them is.
+    fn min_max(&mut self, ty: UasmType, method: &str) -> anyhow::Result<()> {
+        let zero = self.code.constant(ty.clone(), zero(&ty));
+
+        let args = self.pop_values(2)?;
+        let [(lhs, _), (rhs, _)] = args.as_slice() else {
+            unreachable!("two values are popped")
+        };
+        let value = self.code.temp(ty.clone());
+        self.code.call(
+            &externs::math(method, &ty, 2),
+            None,
+            &[lhs.clone(), rhs.clone()],
+            Some(&value),
+        )?;
+
+        let end_label = self.code.new_label();
+        let condition = self.code.temp(UasmType::Boolean);
+        self.code.call(
+            &externs::comparison(&ty, "Equality"),
+            None,
+            &[lhs.clone(), rhs.clone()],
+            Some(&condition),
+        )?;
+        self.code.push(jump_if_false(&condition, &end_label));
+        self.code.call(
+            &externs::comparison(&ty, "Equality"),
+            None,
+            &[lhs.clone(), zero],
+            Some(&condition),
+        )?;
+        self.code.push(jump_if_false(&condition, &end_label));
+
+        if method == "Max" {
+            self.code.call(
+                &externs::binary(&ty, "Addition"),
+                None,
+                &[lhs.clone(), rhs.clone()],
+                Some(&value),
+            )?;
+        } else {
+            let negated_lhs = self.code.temp(ty.clone());
+            let negated_rhs = self.code.temp(ty.clone());
+            self.code.call(
+                &externs::negation(&ty),
+                None,
+                slice::from_ref(lhs),
+                Some(&negated_lhs),
+            )?;
+            self.code.call(
+                &externs::negation(&ty),
+                None,
+                slice::from_ref(rhs),
+                Some(&negated_rhs),
+            )?;
+            self.code.call(
+                &externs::binary(&ty, "Addition"),
+                None,
+                &[negated_lhs, negated_rhs],
+                Some(&value),
+            )?;
+            self.code.call(
+                &externs::negation(&ty),
+                None,
+                slice::from_ref(&value),
+                Some(&value),
+            )?;
+        }
+
+        self.code.start_block(end_label);
+        let result = self.push_value(ty);
+        self.code.push(copy(&value, &result));
+
+        Ok(())
+    }
+
+    /// Lower the conversion of a float into an integer, truncated towards zero.
+    ///
+    /// `SystemConvert` throws for a `NaN` or a value out of the range, as the operator traps.
+    fn truncate(&mut self, from: UasmType, to: UasmType, signed: bool) -> anyhow::Result<()> {
+        let double = UasmType::Double;
+
+        let (value, _) = self.pop_value()?;
+        let converted = if from == double {
+            value
+        } else {
+            let converted = self.code.temp(double.clone());
+            self.code.call(
+                &externs::convert(&from, &double),
+                None,
+                &[value],
+                Some(&converted),
+            )?;
+            converted
+        };
+
+        let truncated = self.code.temp(double.clone());
+        self.code.call(
+            &externs::math("Truncate", &double, 1),
+            None,
+            &[converted],
+            Some(&truncated),
+        )?;
+
+        let result = self.push_value(to.clone());
+        if signed {
+            return self.code.call(
+                &externs::convert(&double, &to),
+                None,
+                &[truncated],
+                Some(&result),
+            );
+        }
+
+        let unsigned = unsigned(&to);
+        let value = self.code.temp(unsigned.clone());
+        self.code.call(
+            &externs::convert(&double, &unsigned),
+            None,
+            &[truncated],
+            Some(&value),
+        )?;
+        self.reinterpret(&value, &unsigned, &to, &result)
+    }
+
+    fn wrap(&mut self) -> anyhow::Result<()> {
+        self.extend_sign(UasmType::Int64, 32)?;
+        self.unary(
+            &externs::convert(&UasmType::Int64, &UasmType::Int32),
+            UasmType::Int32,
+        )
+    }
+
+    /// Convert the `i32` on the top of the stack into an `i64` without extending its sign.
+    fn extend_unsigned(&mut self) -> anyhow::Result<()> {
+        self.unary(
+            &externs::convert(&UasmType::Int32, &UasmType::Int64),
+            UasmType::Int64,
+        )?;
+        self.constant(UasmType::Int64, UasmValue::Int(u32::MAX.into()));
+        self.binary(UasmType::Int64, "LogicalAnd")
+    }
+
+    fn memory(&self) -> anyhow::Result<&LinearMemory> {
+        self.context
+            .memory
+            .ok_or_else(|| anyhow::anyhow!("No memory to access"))
+    }
+
+    /// Get the address `index` bytes after the address popped from the stack.
+    fn address(&mut self, base: &UasmVarName, offset: u64) -> anyhow::Result<UasmVarName> {
+        let offset = i32::try_from(offset)
+            .map_err(|_| anyhow::anyhow!("Memory offset out of range: {}", offset))?;
+        if offset == 0 {
+            return Ok(base.clone());
+        }
+
+        let offset = self
+            .code
+            .constant(UasmType::Int32, UasmValue::Int(offset.into()));
+        let address = self.code.temp(UasmType::Int32);
+        self.code.call(
+            &externs::binary(&UasmType::Int32, "Addition"),
+            None,
+            &[base.clone(), offset],
+            Some(&address),
+        )?;
+
+        Ok(address)
+    }
+
+    /// Load `size` bytes in little endian into a value of `ty`, extending their sign if `signed`.
+    ///
+    /// An element of the memory holds a boxed `SystemByte`, or `null` for a byte never written.
+    fn load(
+        &mut self,
+        memarg: &wasmparser::MemArg,
+        size: u64,
+        signed: bool,
+        ty: UasmType,
+    ) -> anyhow::Result<()> {
+        self.memory()?;
+
+        let (base, _) = self.pop_value()?;
+        let value = self.code.temp(ty.clone());
+
+        for index in 0..size {
+            let address = self.address(&base, memarg.offset + index)?;
+            let object = self.code.temp(UasmType::Object);
+            self.code.call(
+                &externs::object_array_get_value(),
+                Some(&LinearMemory::var()),
+                &[address],
+                Some(&object),
+            )?;
+
+            if index == 0 {
+                self.code.call(
+                    &externs::convert(&UasmType::Object, &ty),
+                    None,
+                    &[object],
+                    Some(&value),
+                )?;
+                continue;
+            }
+
+            let byte = self.code.temp(ty.clone());
+            self.code.call(
+                &externs::convert(&UasmType::Object, &ty),
+                None,
+                &[object],
+                Some(&byte),
+            )?;
+            let count = self
+                .code
+                .constant(UasmType::Int32, UasmValue::Int((8 * index) as i64));
+            self.shift_values(&ty, "LeftShift", &byte, &count, &byte)?;
+            self.code.call(
+                &externs::binary(&ty, "LogicalOr"),
+                None,
+                &[value.clone(), byte],
+                Some(&value),
+            )?;
+        }
+
+        let slot = self.push_value(ty.clone());
+        self.code.push(copy(&value, &slot));
+
+        let width = if ty == UasmType::Int32 { 4 } else { 8 };
+        if signed && size < width {
+            self.extend_sign(ty, (8 * size) as i64)?;
+        }
+
+        Ok(())
+    }
+
+    /// Store the low `size` bytes of the value on the top of the stack in little endian.
+    fn store(&mut self, memarg: &wasmparser::MemArg, size: u64) -> anyhow::Result<()> {
+        self.memory()?;
+
+        let (value, ty) = self.pop_value()?;
+        let (base, _) = self.pop_value()?;
+        let byte_mask = self.code.constant(ty.clone(), UasmValue::Int(0xFF));
+
+        for index in 0..size {
+            let shifted = if index == 0 {
+                value.clone()
+            } else {
+                let count = self
+                    .code
+                    .constant(UasmType::Int32, UasmValue::Int((8 * index) as i64));
+                let shifted = self.code.temp(ty.clone());
+                self.shift_values(&ty, "RightShift", &value, &count, &shifted)?;
+                shifted
+            };
+
+            let masked = self.code.temp(ty.clone());
+            self.code.call(
+                &externs::binary(&ty, "LogicalAnd"),
+                None,
+                &[shifted, byte_mask.clone()],
+                Some(&masked),
+            )?;
+            let byte = self.code.temp(UasmType::Byte);
+            self.code.call(
+                &externs::convert(&ty, &UasmType::Byte),
+                None,
+                &[masked],
+                Some(&byte),
+            )?;
+
+            let address = self.address(&base, memarg.offset + index)?;
+            self.code.call(
+                &externs::object_array_set_value(),
+                Some(&LinearMemory::var()),
+                &[byte, address],
+                None,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Lower `memory.fill` as a loop storing the same byte.
+    fn fill(&mut self) -> anyhow::Result<()> {
+        self.memory()?;
+
+        let args = self.pop_values(3)?;
+        let [(dst, _), (value, _), (len, _)] = args.as_slice() else {
+            unreachable!("three values are popped")
+        };
+
+        let byte_mask = self.code.constant(UasmType::Int32, UasmValue::Int(0xFF));
+        let zero = self.code.constant(UasmType::Int32, UasmValue::Int(0));
+        let one = self.code.constant(UasmType::Int32, UasmValue::Int(1));
+
+        let masked = self.code.temp(UasmType::Int32);
+        self.code.call(
+            &externs::binary(&UasmType::Int32, "LogicalAnd"),
+            None,
+            &[value.clone(), byte_mask],
+            Some(&masked),
+        )?;
+        let byte = self.code.temp(UasmType::Byte);
+        self.code.call(
+            &externs::convert(&UasmType::Int32, &UasmType::Byte),
+            None,
+            &[masked],
+            Some(&byte),
+        )?;
+        let index = self.code.temp(UasmType::Int32);
+        self.code.push(copy(&zero, &index));
+
+        let loop_label = self.code.new_label();
+        let end_label = self.code.new_label();
+        self.code.start_block(loop_label.clone());
+
+        let condition = self.code.temp(UasmType::Boolean);
+        self.code.call(
+            &externs::comparison(&UasmType::Int32, "LessThan"),
+            None,
+            &[index.clone(), len.clone()],
+            Some(&condition),
+        )?;
+        self.code.push(jump_if_false(&condition, &end_label));
+        let address = self.code.temp(UasmType::Int32);
+        self.code.call(
+            &externs::binary(&UasmType::Int32, "Addition"),
+            None,
+            &[dst.clone(), index.clone()],
+            Some(&address),
+        )?;
+        self.code.call(
+            &externs::object_array_set_value(),
+            Some(&LinearMemory::var()),
+            &[byte, address],
+            None,
+        )?;
+        self.code.call(
+            &externs::binary(&UasmType::Int32, "Addition"),
+            None,
+            &[index.clone(), one],
+            Some(&index),
+        )?;
+        self.code.push([jump(&loop_label)]);
+
+        self.code.start_block(end_label);
+
+        Ok(())
+    }
+
+    fn call_function(&mut self, function_index: u32) -> anyhow::Result<()> {
+        let function = self.context.functions.get(function_index)?.clone();
+
+        if let Some((module, name)) = &function.import {
+            return self.call_import(module, name, &function.ty);
+        }
+
+        let args = self.pop_values(function.ty.params().len())?;
+        for (local_index, (arg, _)) in args.iter().enumerate() {
+            self.code
+                .push(copy(arg, &local_var(function_index, local_index)));
+        }
+
+        self.code.call_subroutine(
+            &function_label(function_index),
+            &return_address_var(function_index),
+        )?;
+
+        for (result_index, ty) in function.ty.results().iter().enumerate() {
+            let slot = self.push_value(UasmType::try_from(*ty)?);
+            self.code
+                .push(copy(&result_var(function_index, result_index), &slot));
+        }
+
+        Ok(())
+    }
+
+    fn call_import(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: &wasmparser::FuncType,
+    ) -> anyhow::Result<()> {
+        match module {
+            EXTERN_IMPORT_MODULE => self.call_extern(name, ty),
+            INTRINSIC_IMPORT_MODULE => {
+                let intrinsic = Intrinsic::new(name, ty)?;
+                let args: Vec<_> = self
+                    .pop_values(ty.params().len())?
+                    .into_iter()
+                    .map(|(arg, _)| arg)
+                    .collect();
+                intrinsic.lower(&mut self.code, &args)
+            }
+            module => anyhow::bail!("Unsupported import from {:?}: {}", module, name),
+        }
+    }
+
+    /// Call the extern named by an import from [`EXTERN_IMPORT_MODULE`] (see `docs/function.md`).
+    fn call_extern(&mut self, name: &str, ty: &wasmparser::FuncType) -> anyhow::Result<()> {
+        let signature = ExternSignature::parse(name)?;
+        let abi = ImportAbi::resolve(name, ty, self.context.extern_db)?;
+
+        let mut wasm_args = self.pop_values(ty.params().len())?.into_iter();
+
+        let instance = match abi.instance {
+            Some(lowering) => Some(self.lower_arg(
+                lowering,
+                &UasmType::from_udon_name(&signature.class),
+                &mut wasm_args,
+            )?),
+            None => None,
+        };
+
+        let mut args = Vec::new();
+        for (lowering, udon_ty) in abi.params.iter().zip(signature.params.iter()) {
+            args.push(self.lower_arg(*lowering, udon_ty, &mut wasm_args)?);
+        }
+
+        match (abi.ret, &signature.ret, ty.results()) {
+            (None, _, _) => {
+                self.code
+                    .push(signature.call(instance.as_ref(), &args, None)?);
+            }
+            (Some(Lowering::Direct), Some(_), [wasm_ty]) => {
+                let result = self.push_value(UasmType::try_from(*wasm_ty)?);
+                self.code
+                    .push(signature.call(instance.as_ref(), &args, Some(&result))?);
+            }
+            (Some(Lowering::Convert), Some(udon_ty), [wasm_ty]) => {
+                let value = self.code.temp(udon_ty.clone());
+                self.code
+                    .push(signature.call(instance.as_ref(), &args, Some(&value))?);
+
+                let wasm_ty = UasmType::try_from(*wasm_ty)?;
+                let result = self.push_value(wasm_ty.clone());
+                self.convert(&value, udon_ty, &wasm_ty, &result)?;
+            }
+            (Some(Lowering::Handle), Some(_), [_]) => {
+                let object = self.code.temp(UasmType::Object);
+                self.code
+                    .push(signature.call(instance.as_ref(), &args, Some(&object))?);
+
+                self.code
+                    .call_with(|address| HandleTable::default().alloc(&object, address))?;
+
+                let handle = self.push_value(UasmType::Int32);
+                self.code.push(copy(&HandleTable::handle_var(), &handle));
+            }
+            (Some(Lowering::Utf8), Some(udon_ty), [_]) => {
+                self.memory()?;
+                let string = self.code.temp(udon_ty.clone());
+                self.code
+                    .push(signature.call(instance.as_ref(), &args, Some(&string))?);
+
+                // the buffer follows the parameters
+                let (ptr, _) = wasm_args.next().ok_or_else(|| {
+                    anyhow::anyhow!("Missing buffer for the result of {}", signature)
+                })?;
+                let (capacity, _) = wasm_args.next().ok_or_else(|| {
+                    anyhow::anyhow!("Missing capacity for the result of {}", signature)
+                })?;
+                self.code
+                    .call_with(|address| Utf8Strings.encode(&string, &ptr, &capacity, address))?;
+
+                let len = self.push_value(UasmType::Int32);
+                self.code.push(copy(&Utf8Strings::encoded_len_var(), &len));
+            }
+            (Some(lowering), _, _) => anyhow::bail!(
+                "Unsupported lowering of the result of {}: {:?}",
+                signature,
+                lowering
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Get the variable passed as an argument of `udon_ty` from the wasm arguments.
+    fn lower_arg(
+        &mut self,
+        lowering: Lowering,
+        udon_ty: &UasmType,
+        wasm_args: &mut impl Iterator<Item = (UasmVarName, UasmType)>,
+    ) -> anyhow::Result<UasmVarName> {
+        let mut next = || {
+            wasm_args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing argument of type {}", udon_ty))
+        };
+
+        match lowering {
+            Lowering::Direct => Ok(next()?.0),
+            Lowering::Convert => {
+                let (value, wasm_ty) = next()?;
+                let converted = self.code.temp(udon_ty.clone());
+                self.convert(&value, &wasm_ty, udon_ty, &converted)?;
+
+                Ok(converted)
+            }
+            Lowering::Handle => {
+                let (handle, _) = next()?;
+                let object = self.code.temp(UasmType::Object);
+                self.code
+                    .push(HandleTable::default().get(&handle, &object)?);
+
+                Ok(object)
+            }
+            Lowering::Utf8 => {
+                self.memory()?;
+                let (ptr, _) = next()?;
+                let (len, _) = next()?;
+                self.code
+                    .call_with(|address| Utf8Strings.decode(&ptr, &len, address))?;
+
+                // the next string is decoded into the same variable
+                let string = self.code.temp(udon_ty.clone());
+                self.code.push(copy(&Utf8Strings::string_var(), &string));
+
+                Ok(string)
+            }
+        }
+    }
+
+    fn lower(&mut self, operator: &wasmparser::Operator<'_>) -> anyhow::Result<()> {
+        use wasmparser::Operator;
+
+        if self.unreachable {
+            match operator {
+                Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                    self.skipped += 1;
+                    return Ok(());
+                }
+                Operator::End if self.skipped > 0 => {
+                    self.skipped -= 1;
+                    return Ok(());
+                }
+                Operator::Else | Operator::End if self.skipped == 0 => {}
+                _ => return Ok(()),
+            }
+        }
+
+        let i32 = UasmType::Int32;
+        let i64 = UasmType::Int64;
+        let f32 = UasmType::Single;
+        let f64 = UasmType::Double;
+
+        match operator {
+            Operator::Nop => {}
+            Operator::Unreachable => {
+                // UdonVM stops at the halt address, like a trap
+                self.code
+                    .push([jump(&UasmCodeLabel::from_address(HALT_ADDRESS))]);
+                self.set_unreachable();
+            }
+            Operator::Block { blockty } => {
+                let label = self.code.new_label();
+                self.open(FrameKind::Block, *blockty, label, None)?;
+            }
+            Operator::Loop { blockty } => {
+                let label = self.code.new_label();
+                self.code.start_block(label.clone());
+                self.open(FrameKind::Loop, *blockty, label, None)?;
+            }
+            Operator::If { blockty } => {
+                let condition = self.pop_condition()?;
+                let else_label = self.code.new_label();
+                let end_label = self.code.new_label();
+                self.code.push(jump_if_false(&condition, &else_label));
+                self.open(FrameKind::If, *blockty, end_label, Some(else_label))?;
+            }
+            Operator::Else => {
+                let frame = self
+                    .frames
+                    .last_mut()
+                    .filter(|frame| frame.kind == FrameKind::If)
+                    .ok_or_else(|| anyhow::anyhow!("`else` outside of `if`"))?;
+                frame.kind = FrameKind::Else;
+                let else_label = frame.else_label.take().expect("an `if` has an else label");
+                let end_label = frame.label.clone().expect("an `if` has an end label");
+                let height = frame.height;
+                let params = frame.params.clone();
+
+                if !self.unreachable {
+                    self.code.push([jump(&end_label)]);
+                }
+                self.code.start_block(else_label);
+
+                self.stack.truncate(height);
+                self.stack.extend(params);
+                self.unreachable = false;
+            }
+            Operator::End => {
+                if self.frames.len() == 1 && !self.unreachable {
+                    self.return_values()?;
+                }
+
+                let frame = self
+                    .frames
+                    .pop()
+                    .ok_or_else(|| anyhow::anyhow!("Unbalanced `end`"))?;
+                if frame.kind == FrameKind::Function {
+                    return Ok(());
+                }
+
+                if let Some(else_label) = frame.else_label {
+                    self.code.start_block(else_label);
+                }
+                if frame.kind != FrameKind::Loop {
+                    self.code
+                        .start_block(frame.label.expect("a block has an end label"));
+                }
+
+                self.stack.truncate(frame.height);
+                self.stack.extend(frame.results);
+                self.unreachable = false;
+            }
+            Operator::Br { relative_depth } => {
+                self.branch(*relative_depth)?;
+                self.set_unreachable();
+            }
+            Operator::BrIf { relative_depth } => {
+                if self.is_jump_after_condition(*relative_depth) {
+                    // `JUMP_IF_FALSE` jumps when the value is zero
+                    let (value, _) = self.pop_value()?;
+                    let is_zero = self.test_zero(&value, "Equality")?;
+                    let index = self.frames.len() - 1 - *relative_depth as usize;
+                    let label = self.frames[index]
+                        .label
+                        .clone()
+                        .expect("a jump has a label");
+                    self.code.push(jump_if_false(&is_zero, &label));
+                    return Ok(());
+                }
+
+                let condition = self.pop_condition()?;
+                let skip = self.code.new_label();
+                self.code.push(jump_if_false(&condition, &skip));
+                self.branch(*relative_depth)?;
+                self.code.start_block(skip);
+            }
+            Operator::BrTable { targets } => {
+                let (index, _) = self.pop_value()?;
+
+                for (value, target) in targets.targets().enumerate() {
+                    let target = target.map_err(|err| {
+                        anyhow::anyhow!("Failed to parse branch table: {:?}", err)
+                    })?;
+                    let value = self
+                        .code
+                        .constant(UasmType::Int32, UasmValue::Int(value as i64));
+                    let condition = self.code.temp(UasmType::Boolean);
+                    self.code.call(
+                        &externs::comparison(&UasmType::Int32, "Equality"),
+                        None,
+                        &[index.clone(), value],
+                        Some(&condition),
+                    )?;
+
+                    let next = self.code.new_label();
+                    self.code.push(jump_if_false(&condition, &next));
+                    self.branch(target)?;
+                    self.code.start_block(next);
+                }
+                self.branch(targets.default())?;
+                self.set_unreachable();
+            }
+            Operator::Return => {
+                self.return_values()?;
+                self.set_unreachable();
+            }
+            Operator::Call { function_index } => self.call_function(*function_index)?,
+            Operator::Drop => {
+                self.pop_value()?;
+            }
+            Operator::Select | Operator::TypedSelect { .. } => {
+                let (condition, _) = self.pop_value()?;
+                let args = self.pop_values(2)?;
+                let result = self.push_value(args[0].1.clone());
+
+                // keep the first value unless the condition is zero
+                let is_zero = self.test_zero(&condition, "Equality")?;
+                let skip = self.code.new_label();
+                self.code.push(jump_if_false(&is_zero, &skip));
+                self.code.push(copy(&args[1].0, &result));
+                self.code.start_block(skip);
+            }
+            Operator::LocalGet { local_index } => {
+                let (local, ty) = self.local(*local_index)?;
+                let slot = self.push_value(ty);
+                self.code.push(copy(&local, &slot));
+            }
+            Operator::LocalSet { local_index } => {
+                let (local, _) = self.local(*local_index)?;
+                let (value, _) = self.pop_value()?;
+                self.code.push(copy(&value, &local));
+            }
+            Operator::LocalTee { local_index } => {
+                let (local, _) = self.local(*local_index)?;
+                let (value, _) = self.peek_values(1)?.remove(0);
+                self.code.push(copy(&value, &local));
+            }
+            Operator::GlobalGet { global_index } => {
+                let global = self.context.globals.get(*global_index)?;
+                let ty = global.ty.clone();
+                let mut instructions = self.context.globals.global_get(*global_index)?;
+
+                let slot = self.push_value(ty);
+                instructions.push(push(&slot));
+                instructions.push(UasmInstruction::new(UasmOpcode::Copy));
+                self.code.push(instructions);
+            }
+            Operator::GlobalSet { global_index } => {
+                let (value, _) = self.pop_value()?;
+                let mut instructions = vec![push(&value)];
+                instructions.extend(self.context.globals.global_set(*global_index)?);
+                self.code.push(instructions);
+            }
+
+            Operator::I32Load { memarg } => self.load(memarg, 4, false, i32)?,
+            Operator::I32Load8S { memarg } => self.load(memarg, 1, true, i32)?,
+            Operator::I32Load8U { memarg } => self.load(memarg, 1, false, i32)?,
+            Operator::I32Load16S { memarg } => self.load(memarg, 2, true, i32)?,
+            Operator::I32Load16U { memarg } => self.load(memarg, 2, false, i32)?,
+            Operator::I64Load { memarg } => self.load(memarg, 8, false, i64)?,
+            Operator::I64Load8S { memarg } => self.load(memarg, 1, true, i64)?,
+            Operator::I64Load8U { memarg } => self.load(memarg, 1, false, i64)?,
+            Operator::I64Load16S { memarg } => self.load(memarg, 2, true, i64)?,
+            Operator::I64Load16U { memarg } => self.load(memarg, 2, false, i64)?,
+            Operator::I64Load32S { memarg } => self.load(memarg, 4, true, i64)?,
+            Operator::I64Load32U { memarg } => self.load(memarg, 4, false, i64)?,
+            Operator::I32Store { memarg } | Operator::I64Store32 { memarg } => {
+                self.store(memarg, 4)?
+            }
+            Operator::I32Store8 { memarg } | Operator::I64Store8 { memarg } => {
+                self.store(memarg, 1)?
+            }
+            Operator::I32Store16 { memarg } | Operator::I64Store16 { memarg } => {
+                self.store(memarg, 2)?
+            }
+            Operator::I64Store { memarg } => self.store(memarg, 8)?,
+            Operator::MemorySize { .. } => {
+                self.memory()?;
+                let size = self.push_value(i32);
+                self.code.push(copy(&LinearMemory::pages_var(), &size));
+            }
+            Operator::MemoryGrow { .. } => {
+                self.memory()?;
+                let (delta, _) = self.pop_value()?;
+                self.code
+                    .push(copy(&delta, &LinearMemory::grow_delta_var()));
+                self.code.call_subroutine(
+                    &LinearMemory::grow_label(),
+                    &LinearMemory::grow_return_address_var(),
+                )?;
+                let result = self.push_value(i32);
+                self.code
+                    .push(copy(&LinearMemory::grow_result_var(), &result));
+            }
+            Operator::MemoryCopy { .. } => {
+                self.memory()?;
+                let args = self.pop_values(3)?;
+                let [(dst, _), (src, _), (len, _)] = args.as_slice() else {
+                    unreachable!("three values are popped")
+                };
+
+                // `System.Array.Copy` handles overlapping ranges like `memmove`
+                self.code.call(
+                    &externs::array_copy(),
+                    None,
+                    &[
+                        LinearMemory::var(),
+                        src.clone(),
+                        LinearMemory::var(),
+                        dst.clone(),
+                        len.clone(),
+                    ],
+                    None,
+                )?;
+            }
+            Operator::MemoryFill { .. } => self.fill()?,
+
+            Operator::I32Const { value } => self.constant(i32, UasmValue::Int((*value).into())),
+            Operator::I64Const { value } => self.constant(i64, UasmValue::Int(*value)),
+            Operator::F32Const { value } => {
+                self.constant(f32, UasmValue::Float(f32::from_bits(value.bits())))
+            }
+            Operator::F64Const { value } => {
+                self.constant(f64, UasmValue::Double(f64::from_bits(value.bits())))
+            }
+            Operator::RefNull { ty } => self.constant(UasmType::try_from(*ty)?, UasmValue::Null),
+            Operator::RefIsNull => {
+                let (value, _) = self.pop_value()?;
+                let null = self.code.constant(UasmType::Object, UasmValue::Null);
+                self.compare_values(&UasmType::Object, "Equality", &value, &null)?;
+            }
+
+            Operator::I32Eqz => self.eqz(i32)?,
+            Operator::I32Eq => self.compare(i32, "Equality")?,
+            Operator::I32Ne => self.compare(i32, "Inequality")?,
+            Operator::I32LtS => self.compare(i32, "LessThan")?,
+            Operator::I32GtS => self.compare(i32, "GreaterThan")?,
+            Operator::I32LeS => self.compare(i32, "LessThanOrEqual")?,
+            Operator::I32GeS => self.compare(i32, "GreaterThanOrEqual")?,
+            Operator::I32LtU => self.compare_unsigned(i32, "LessThan")?,
+            Operator::I32GtU => self.compare_unsigned(i32, "GreaterThan")?,
+            Operator::I32LeU => self.compare_unsigned(i32, "LessThanOrEqual")?,
+            Operator::I32GeU => self.compare_unsigned(i32, "GreaterThanOrEqual")?,
+            Operator::I64Eqz => self.eqz(i64)?,
+            Operator::I64Eq => self.compare(i64, "Equality")?,
+            Operator::I64Ne => self.compare(i64, "Inequality")?,
+            Operator::I64LtS => self.compare(i64, "LessThan")?,
+            Operator::I64GtS => self.compare(i64, "GreaterThan")?,
+            Operator::I64LeS => self.compare(i64, "LessThanOrEqual")?,
+            Operator::I64GeS => self.compare(i64, "GreaterThanOrEqual")?,
+            Operator::I64LtU => self.compare_unsigned(i64, "LessThan")?,
+            Operator::I64GtU => self.compare_unsigned(i64, "GreaterThan")?,
+            Operator::I64LeU => self.compare_unsigned(i64, "LessThanOrEqual")?,
+            Operator::I64GeU => self.compare_unsigned(i64, "GreaterThanOrEqual")?,
+            Operator::F32Eq => self.compare(f32, "Equality")?,
+            Operator::F32Ne => self.compare(f32, "Inequality")?,
+            Operator::F32Lt => self.compare(f32, "LessThan")?,
+            Operator::F32Gt => self.compare(f32, "GreaterThan")?,
+            Operator::F32Le => self.compare(f32, "LessThanOrEqual")?,
+            Operator::F32Ge => self.compare(f32, "GreaterThanOrEqual")?,
+            Operator::F64Eq => self.compare(f64, "Equality")?,
+            Operator::F64Ne => self.compare(f64, "Inequality")?,
+            Operator::F64Lt => self.compare(f64, "LessThan")?,
+            Operator::F64Gt => self.compare(f64, "GreaterThan")?,
+            Operator::F64Le => self.compare(f64, "LessThanOrEqual")?,
+            Operator::F64Ge => self.compare(f64, "GreaterThanOrEqual")?,
+
+            Operator::I32Add => self.binary(i32, "Addition")?,
+            Operator::I32Sub => self.binary(i32, "Subtraction")?,
+            Operator::I32Mul => self.binary(i32, "Multiplication")?,
+            Operator::I32DivS => self.binary(i32, "Division")?,
+            Operator::I32RemS => self.remainder(i32)?,
+            Operator::I32DivU => self.binary_unsigned(i32, "Division")?,
+            Operator::I32RemU => self.binary_unsigned(i32, "Modulus")?,
+            Operator::I32And => self.binary(i32, "LogicalAnd")?,
+            Operator::I32Or => self.binary(i32, "LogicalOr")?,
+            Operator::I32Xor => self.binary(i32, "LogicalXor")?,
+            Operator::I32Shl => self.shift(i32, "LeftShift")?,
+            Operator::I32ShrS => self.shift(i32, "RightShift")?,
+            Operator::I32ShrU => self.shift_right_unsigned(i32)?,
+            Operator::I64Add => self.binary(i64, "Addition")?,
+            Operator::I64Sub => self.binary(i64, "Subtraction")?,
+            Operator::I64Mul => self.binary(i64, "Multiplication")?,
+            Operator::I64DivS => self.binary(i64, "Division")?,
+            Operator::I64RemS => self.remainder(i64)?,
+            Operator::I64DivU => self.binary_unsigned(i64, "Division")?,
+            Operator::I64RemU => self.binary_unsigned(i64, "Modulus")?,
+            Operator::I64And => self.binary(i64, "LogicalAnd")?,
+            Operator::I64Or => self.binary(i64, "LogicalOr")?,
+            Operator::I64Xor => self.binary(i64, "LogicalXor")?,
+            Operator::I64Shl => self.shift(i64, "LeftShift")?,
+            Operator::I64ShrS => self.shift(i64, "RightShift")?,
+            Operator::I64ShrU => self.shift_right_unsigned(i64)?,
+            Operator::F32Add => self.binary(f32, "Addition")?,
+            Operator::F32Sub => self.binary(f32, "Subtraction")?,
+            Operator::F32Mul => self.binary(f32, "Multiplication")?,
+            Operator::F32Div => self.binary(f32, "Division")?,
+            Operator::F32Neg => self.unary(&externs::negation(&f32), f32)?,
+            Operator::F32Abs => self.math(f32, "Abs", 1)?,
+            Operator::F32Sqrt => self.math(f32, "Sqrt", 1)?,
+            Operator::F32Ceil => self.math(f32, "Ceiling", 1)?,
+            Operator::F32Floor => self.math(f32, "Floor", 1)?,
+            Operator::F32Trunc => self.math(f32, "Truncate", 1)?,
+            Operator::F32Nearest => self.math(f32, "Round", 1)?,
+            Operator::F32Min => self.min_max(f32, "Min")?,
+            Operator::F32Max => self.min_max(f32, "Max")?,
+            Operator::F64Add => self.binary(f64, "Addition")?,
+            Operator::F64Sub => self.binary(f64, "Subtraction")?,
+            Operator::F64Mul => self.binary(f64, "Multiplication")?,
+            Operator::F64Div => self.binary(f64, "Division")?,
+            Operator::F64Neg => self.unary(&externs::negation(&f64), f64)?,
+            Operator::F64Abs => self.math(f64, "Abs", 1)?,
+            Operator::F64Sqrt => self.math(f64, "Sqrt", 1)?,
+            Operator::F64Ceil => self.math(f64, "Ceiling", 1)?,
+            Operator::F64Floor => self.math(f64, "Floor", 1)?,
+            Operator::F64Trunc => self.math(f64, "Truncate", 1)?,
+            Operator::F64Nearest => self.math(f64, "Round", 1)?,
+            Operator::F64Min => self.min_max(f64, "Min")?,
+            Operator::F64Max => self.min_max(f64, "Max")?,
+
+            Operator::I32WrapI64 => self.wrap()?,
+            Operator::I64ExtendI32S => self.unary(&externs::convert(&i32, &i64), i64)?,
+            Operator::I64ExtendI32U => self.extend_unsigned()?,
+            Operator::I32Extend8S => self.extend_sign(i32, 8)?,
+            Operator::I32Extend16S => self.extend_sign(i32, 16)?,
+            Operator::I64Extend8S => self.extend_sign(i64, 8)?,
+            Operator::I64Extend16S => self.extend_sign(i64, 16)?,
+            Operator::I64Extend32S => self.extend_sign(i64, 32)?,
+            Operator::I32TruncF32S => self.truncate(f32, i32, true)?,
+            Operator::I32TruncF32U => self.truncate(f32, i32, false)?,
+            Operator::I32TruncF64S => self.truncate(f64, i32, true)?,
+            Operator::I32TruncF64U => self.truncate(f64, i32, false)?,
+            Operator::I64TruncF32S => self.truncate(f32, i64, true)?,
+            Operator::I64TruncF32U => self.truncate(f32, i64, false)?,
+            Operator::I64TruncF64S => self.truncate(f64, i64, true)?,
+            Operator::I64TruncF64U => self.truncate(f64, i64, false)?,
+            Operator::F32ConvertI32S => self.unary(&externs::convert(&i32, &f32), f32)?,
+            Operator::F32ConvertI64S => self.unary(&externs::convert(&i64, &f32), f32)?,
+            Operator::F64ConvertI32S => self.unary(&externs::convert(&i32, &f64), f64)?,
+            Operator::F64ConvertI64S => self.unary(&externs::convert(&i64, &f64), f64)?,
+            Operator::F32ConvertI32U => {
+                self.extend_unsigned()?;
+                self.unary(&externs::convert(&i64, &f32), f32)?
+            }
+            Operator::F64ConvertI32U => {
+                self.extend_unsigned()?;
+                self.unary(&externs::convert(&i64, &f64), f64)?
+            }
+            Operator::F32DemoteF64 => self.unary(&externs::convert(&f64, &f32), f32)?,
+            Operator::F64PromoteF32 => self.unary(&externs::convert(&f32, &f64), f64)?,
+
+            operator => anyhow::bail!("Unsupported operator: {:?}", operator),
+        }
+
+        Ok(())
+    }
+}
+
+/// Reject the recursive calls, as a function keeps its locals and its return address in fixed variables.
+pub fn check_recursion(parsed: &ParsedData<wasmparser::Payload<'_>>) -> anyhow::Result<()> {
+    use wasmparser::{Operator, Payload};
+
+    let functions = FunctionIndexSpace::new(parsed)?;
+
+    let mut bodies = Vec::new();
+    let mut current = Some(parsed);
+    while let Some(parsed) = current {
+        if let Payload::CodeSectionEntry(body) = parsed.get_data() {
+            bodies.push(body.clone());
+        }
+        current = parsed.get_next();
+    }
+    bodies.reverse();
+
+    let mut callees = Vec::new();
+    for body in bodies.iter() {
+        let err = |err| anyhow::anyhow!("Failed to parse function body: {:?}", err);
+
+        let mut called = Vec::new();
+        for operator in body.get_operators_reader().map_err(err)? {
+            if let Operator::Call { function_index } = operator.map_err(err)? {
+                called.push(function_index);
+            }
+        }
+        callees.push(called);
+    }
+
+    // the functions defined in the module come after the imported ones
+    let offset = functions.imported_count();
+    let callees = |function_index: u32| {
+        (function_index as usize)
+            .checked_sub(offset)
+            .and_then(|index| callees.get(index))
+            .map_or(&[][..], Vec::as_slice)
+    };
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        Visiting,
+        Done,
+    }
+
+    let mut states = vec![State::Unvisited; offset + bodies.len()];
+    for root in offset..offset + bodies.len() {
+        if states[root] != State::Unvisited {
+            continue;
+        }
+
+        // depth-first, with the index of the next callee of each function on the path
+        let mut path = vec![(root as u32, 0)];
+        states[root] = State::Visiting;
+        while let Some((function_index, next)) = path.last_mut() {
+            let Some(&callee) = callees(*function_index).get(*next) else {
+                states[*function_index as usize] = State::Done;
+                path.pop();
+                continue;
+            };
+            *next += 1;
+
+            match states.get(callee as usize) {
+                Some(State::Unvisited) => {
+                    states[callee as usize] = State::Visiting;
+                    path.push((callee, 0));
+                }
+                Some(State::Visiting) => {
+                    anyhow::bail!("Unsupported recursive call of function {}", callee)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::memory::PAGE_SIZE;
+    use crate::core::testing::{call, instantiate, run, ModuleBuilder, F32, F64, I32, I64};
+    use crate::core::wasm2uasm::ModuleContext;
+    use crate::udon::uasm::vm::Value;
+    use crate::wasm::parser::{WasmEntry, WasmParser};
+
+    /// Run the operator `opcode` on the parameters `[ty, ty]`, which gives a `result`.
+    fn binary(ty: u8, result: u8, opcode: u8, lhs: Value, rhs: Value) -> anyhow::Result<Value> {
+        run(
+            &[ty, ty],
+            result,
+            &[0x20, 0x00, 0x20, 0x01, opcode],
+            &[lhs, rhs],
+        )
+    }
+
+    fn unary(ty: u8, result: u8, opcode: u8, value: Value) -> anyhow::Result<Value> {
+        run(&[ty], result, &[0x20, 0x00, opcode], &[value])
+    }
+
+    fn i32_binary(opcode: u8, lhs: i32, rhs: i32) -> anyhow::Result<i32> {
+        match binary(I32, I32, opcode, Value::Int32(lhs), Value::Int32(rhs))? {
+            Value::Int32(value) => Ok(value),
+            value => panic!("not an i32: {:?}", value),
+        }
+    }
+
+    fn i64_binary(opcode: u8, lhs: i64, rhs: i64) -> anyhow::Result<i64> {
+        match binary(I64, I64, opcode, Value::Int64(lhs), Value::Int64(rhs))? {
+            Value::Int64(value) => Ok(value),
+            value => panic!("not an i64: {:?}", value),
+        }
+    }
+
+    /// a module with a single function of type `[] -> []` and the given body
+    fn module(body: &[u8]) -> Vec<u8> {
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        wasm.extend([0x01, 0x04, 0x01, 0x60, 0x00, 0x00]);
+        wasm.extend([0x03, 0x02, 0x01, 0x00]);
+        wasm.extend([0x0A, body.len() as u8 + 3, 0x01, body.len() as u8 + 1, 0x00]);
+        wasm.extend(body);
+
+        wasm
+    }
+
+    #[test]
+    fn reuses_temps_and_constants() {
+        let mut code = CodeBuilder::new("F0", UasmCodeLabel::new("__F__0".into()));
+
+        let first = code.temp(UasmType::Int32);
+        let second = code.temp(UasmType::Int32);
+        assert_ne!(first, second);
+
+        code.set_offset(0);
+        assert_eq!(code.temp(UasmType::Int32), first);
+
+        let one = code.constant(UasmType::Int32, UasmValue::Int(1));
+        assert_eq!(code.constant(UasmType::Int32, UasmValue::Int(1)), one);
+        assert_ne!(code.constant(UasmType::Int64, UasmValue::Int(1)), one);
+    }
+
+    #[test]
+    fn calls_a_subroutine() {
+        let mut code = CodeBuilder::new("START", UasmCodeLabel::new("_start".into()));
+        code.call_subroutine(
+            &UasmCodeLabel::new("__F__0".into()),
+            &UasmVarName::new("__F__0__RET".into()),
+        )
+        .unwrap();
+
+        let uasm = code.finish().unwrap();
+        let blocks: Vec<_> = uasm
+            .code_section
+            .as_ref()
+            .unwrap()
+            .get_code()
+            .blocks()
+            .map(|(label, _)| label.to_string())
+            .collect();
+        assert_eq!(blocks, ["_start", "__START_B0"]);
+
+        let return_site = &uasm.data_section.as_ref().unwrap().get_data()[0].variable;
+        assert_eq!(return_site.name.to_string(), "__START_A0");
+        assert_eq!(
+            return_site.value,
+            UasmValue::Address(UasmCodeLabel::new("__START_B0".into()))
+        );
+    }
+
+    #[test]
+    fn rejects_recursive_calls() {
+        // call 0
+        let wasm = module(&[0x10, 0x00, 0x0B]);
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+
+        let err = check_recursion(&parsed).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported recursive call of function 0");
+    }
+
+    #[test]
+    fn rejects_operators_after_the_end() {
+        let wasm = module(&[0x0B]);
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+        let context = ModuleContext::new(&parsed, None).unwrap();
+
+        // no locals, end, return
+        let body = wasmparser::FunctionBody::new(0, &[0x00, 0x0B, 0x0F]);
+        let err = lower_function(
+            FunctionContext {
+                function_index: 0,
+                functions: &context.functions,
+                globals: &context.globals,
+                memory: None,
+                extern_db: None,
+            },
+            &body,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Operator after the end of the function"));
+    }
+
+    #[test]
+    fn rejects_invalid_modules() {
+        // i32.const 1, end, which leaves a value in a function returning nothing
+        let wasm = module(&[0x41, 0x01, 0x0B]);
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+
+        let err = parser.parse_all().unwrap_err();
+        assert!(err.to_string().starts_with("Invalid wasm module: "));
+    }
+
+    #[test]
+    fn rejects_unsupported_operators() {
+        // i32.const 1, i32.const 2, i32.rotl, drop
+        let wasm = module(&[0x41, 0x01, 0x41, 0x02, 0x77, 0x1A, 0x0B]);
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+
+        let err = parsed
+            .interpret_all::<Uasm, _>(&ModuleContext::new(&parsed, None).unwrap())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Unsupported operator: I32Rotl (function 0 at offset"));
+    }
+
+    #[test]
+    fn computes_integer_arithmetic() {
+        // i32.add, i32.sub and i32.mul wrap around
+        assert_eq!(i32_binary(0x6A, i32::MAX, 1).unwrap(), i32::MIN);
+        assert_eq!(i32_binary(0x6B, i32::MIN, 1).unwrap(), i32::MAX);
+        assert_eq!(i32_binary(0x6C, 0x10000, 0x10001).unwrap(), 0x10000);
+        // i32.div_s and i32.rem_s truncate towards zero
+        assert_eq!(i32_binary(0x6D, -7, 2).unwrap(), -3);
+        assert_eq!(i32_binary(0x6F, -7, 2).unwrap(), -1);
+        // i32.rem_s of the minimum by -1 doesn't overflow
+        assert_eq!(i32_binary(0x6F, i32::MIN, -1).unwrap(), 0);
+        assert_eq!(i32_binary(0x6F, 7, -1).unwrap(), 0);
+        // i32.and, i32.or and i32.xor
+        assert_eq!(i32_binary(0x71, 0b1100, 0b1010).unwrap(), 0b1000);
+        assert_eq!(i32_binary(0x72, 0b1100, 0b1010).unwrap(), 0b1110);
+        assert_eq!(i32_binary(0x73, 0b1100, 0b1010).unwrap(), 0b0110);
+        // i32.shl, i32.shr_s and i32.shr_u take the count modulo 32
+        assert_eq!(i32_binary(0x74, 1, 33).unwrap(), 2);
+        assert_eq!(i32_binary(0x75, -8, 1).unwrap(), -4);
+        assert_eq!(i32_binary(0x76, -8, 1).unwrap(), 0x7FFF_FFFC);
+        assert_eq!(i32_binary(0x76, -8, 32).unwrap(), -8);
+
+        assert_eq!(i64_binary(0x7C, i64::MAX, 1).unwrap(), i64::MIN);
+        assert_eq!(i64_binary(0x7E, 1 << 32, 1 << 32).unwrap(), 0);
+        assert_eq!(i64_binary(0x7F, -7, 2).unwrap(), -3);
+        assert_eq!(i64_binary(0x81, -7, 2).unwrap(), -1);
+        assert_eq!(i64_binary(0x81, i64::MIN, -1).unwrap(), 0);
+        assert_eq!(i64_binary(0x86, 1, 65).unwrap(), 2);
+        assert_eq!(i64_binary(0x87, -8, 1).unwrap(), -4);
+        assert_eq!(i64_binary(0x88, -8, 1).unwrap(), 0x7FFF_FFFF_FFFF_FFFC);
+        assert_eq!(i64_binary(0x88, i64::MIN, 63).unwrap(), 1);
+    }
+
+    #[test]
+    fn traps_on_integer_division_errors() {
+        // i32.div_s by zero and of the minimum by -1
+        assert!(i32_binary(0x6D, 1, 0).is_err());
+        assert!(i32_binary(0x6D, i32::MIN, -1).is_err());
+        // i32.rem_s by zero
+        assert!(i32_binary(0x6F, 1, 0).is_err());
+        assert!(i64_binary(0x81, 1, 0).is_err());
+        assert!(i64_binary(0x7F, i64::MIN, -1).is_err());
+    }
+
+    #[test]
+    fn divides_unsigned_integers() {
+        // i32.div_u and i32.rem_u
+        assert_eq!(i32_binary(0x6E, -1, 2).unwrap(), i32::MAX);
+        assert_eq!(i32_binary(0x70, -1, 10).unwrap(), 5);
+        assert_eq!(i32_binary(0x6E, 7, -1).unwrap(), 0);
+        assert!(i32_binary(0x6E, 1, 0).is_err());
+        // i64.div_u and i64.rem_u
+        assert_eq!(i64_binary(0x80, -1, 2).unwrap(), i64::MAX);
+        assert_eq!(i64_binary(0x82, -1, 10).unwrap(), 5);
+        assert_eq!(i64_binary(0x80, i64::MIN, -1).unwrap(), 0);
+        assert!(i64_binary(0x82, 1, 0).is_err());
+    }
+
+    #[test]
+    fn compares_integers() {
+        let compare = |opcode, lhs, rhs| {
+            binary(I32, I32, opcode, Value::Int32(lhs), Value::Int32(rhs)).unwrap()
+        };
+
+        // i32.eq, i32.lt_s and i32.lt_u
+        assert_eq!(compare(0x46, 1, 1), Value::Int32(1));
+        assert_eq!(compare(0x48, -1, 1), Value::Int32(1));
+        assert_eq!(compare(0x49, -1, 1), Value::Int32(0));
+        // i32.ge_u
+        assert_eq!(compare(0x4F, -1, 1), Value::Int32(1));
+        // i32.eqz
+        assert_eq!(
+            unary(I32, I32, 0x45, Value::Int32(0)).unwrap(),
+            Value::Int32(1)
+        );
+
+        // i64.gt_u
+        assert_eq!(
+            binary(I64, I32, 0x56, Value::Int64(i64::MIN), Value::Int64(1)).unwrap(),
+            Value::Int32(1)
+        );
+    }
+
+    #[test]
+    fn converts_integers() {
+        // i32.wrap_i64, i64.extend_i32_s and i64.extend_i32_u
+        assert_eq!(
+            unary(I64, I32, 0xA7, Value::Int64(0x1_8000_0001)).unwrap(),
+            Value::Int32(i32::MIN + 1)
+        );
+        assert_eq!(
+            unary(I32, I64, 0xAC, Value::Int32(-1)).unwrap(),
+            Value::Int64(-1)
+        );
+        assert_eq!(
+            unary(I32, I64, 0xAD, Value::Int32(-1)).unwrap(),
+            Value::Int64(u32::MAX.into())
+        );
+        // i32.extend8_s
+        assert_eq!(
+            unary(I32, I32, 0xC0, Value::Int32(0x80)).unwrap(),
+            Value::Int32(-128)
+        );
+        // f64.convert_i32_u
+        assert_eq!(
+            unary(I32, F64, 0xB8, Value::Int32(-1)).unwrap(),
+            Value::Double(u32::MAX.into())
+        );
+    }
+
+    #[test]
+    fn computes_float_arithmetic() {
+        // f32.add, f64.div and f64.neg
+        assert_eq!(
+            binary(F32, F32, 0x92, Value::Single(0.5), Value::Single(0.25)).unwrap(),
+            Value::Single(0.75)
+        );
+        assert_eq!(
+            binary(F64, F64, 0xA3, Value::Double(1.0), Value::Double(0.0)).unwrap(),
+            Value::Double(f64::INFINITY)
+        );
+        assert_eq!(
+            unary(F64, F64, 0x9A, Value::Double(0.0)).unwrap(),
+            Value::Double(-0.0)
+        );
+        // f64.lt is false for NaN
+        assert_eq!(
+            binary(F64, I32, 0x63, Value::Double(f64::NAN), Value::Double(1.0)).unwrap(),
+            Value::Int32(0)
+        );
+    }
+
+    #[test]
+    fn runs_loops() {
+        // the sum of 1 to n:
+        // (local $sum i32)
+        // (loop $continue
+        //   (local.set $sum (i32.add (local.get $sum) (local.get $n)))
+        //   (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        //   (br_if $continue (local.get $n)))
+        // (local.get $sum)
+        let code = [
+            0x03, 0x40, //
+            0x20, 0x01, 0x20, 0x00, 0x6A, 0x21, 0x01, //
+            0x20, 0x00, 0x41, 0x01, 0x6B, 0x21, 0x00, //
+            0x20, 0x00, 0x0D, 0x00, //
+            0x0B, 0x20, 0x01,
+        ];
+        let wasm = ModuleBuilder::default()
+            .function(&[I32], &[I32], &[I32], &code)
+            .build();
+        let mut vm = instantiate(&wasm).unwrap();
+
+        assert_eq!(
+            call(&mut vm, 0, &[Value::Int32(100)], 1).unwrap(),
+            [Value::Int32(5050)]
+        );
+        // the locals are reset by the next call
+        assert_eq!(
+            call(&mut vm, 0, &[Value::Int32(3)], 1).unwrap(),
+            [Value::Int32(6)]
+        );
+    }
+
+    #[test]
+    fn runs_branches() {
+        // (if (result i32) (local.get 0) (then (i32.const 10)) (else (i32.const 20)))
+        let code = [0x20, 0x00, 0x04, I32, 0x41, 0x0A, 0x05, 0x41, 0x14, 0x0B];
+        assert_eq!(
+            run(&[I32], I32, &code, &[Value::Int32(7)]).unwrap(),
+            Value::Int32(10)
+        );
+        assert_eq!(
+            run(&[I32], I32, &code, &[Value::Int32(0)]).unwrap(),
+            Value::Int32(20)
+        );
+
+        // (block (block (block (br_table 0 1 2 (local.get 0))) (return (i32.const 10)))
+        //   (return (i32.const 11)))
+        // (i32.const 12)
+        let code = [
+            0x02, 0x40, 0x02, 0x40, 0x02, 0x40, //
+            0x20, 0x00, 0x0E, 0x02, 0x00, 0x01, 0x02, //
+            0x0B, 0x41, 0x0A, 0x0F, //
+            0x0B, 0x41, 0x0B, 0x0F, //
+            0x0B, 0x41, 0x0C,
+        ];
+        for (index, result) in [(0, 10), (1, 11), (2, 12), (-1, 12)] {
+            assert_eq!(
+                run(&[I32], I32, &code, &[Value::Int32(index)]).unwrap(),
+                Value::Int32(result)
+            );
+        }
+
+        // (select (i32.const 1) (i32.const 2) (local.get 0))
+        let code = [0x41, 0x01, 0x41, 0x02, 0x20, 0x00, 0x1B];
+        assert_eq!(
+            run(&[I32], I32, &code, &[Value::Int32(0)]).unwrap(),
+            Value::Int32(2)
+        );
+    }
+
+    #[test]
+    fn calls_functions() {
+        // function 0 returns (function 1 x) + (function 1 x), where function 1 doubles its parameter
+        let wasm = ModuleBuilder::default()
+            .function(
+                &[I64],
+                &[I64],
+                &[],
+                &[0x20, 0x00, 0x10, 0x01, 0x20, 0x00, 0x10, 0x01, 0x7C],
+            )
+            .function(&[I64], &[I64], &[], &[0x20, 0x00, 0x20, 0x00, 0x7C])
+            .build();
+        let mut vm = instantiate(&wasm).unwrap();
+
+        assert_eq!(
+            call(&mut vm, 0, &[Value::Int64(21)], 1).unwrap(),
+            [Value::Int64(84)]
+        );
+    }
+
+    #[test]
+    fn loads_and_stores() {
+        // (i32.store offset=4 (i32.const 0) (local.get 0))
+        // (i32.add (i32.load16_s offset=2 (i32.const 0)) (i32.load8_u offset=4 (i32.const 0)))
+        let code = [
+            0x41, 0x00, 0x20, 0x00, 0x36, 0x02, 0x04, //
+            0x41, 0x00, 0x2E, 0x01, 0x02, //
+            0x41, 0x00, 0x2D, 0x00, 0x04, 0x6A,
+        ];
+        let wasm = ModuleBuilder::default()
+            .function(&[I32], &[I32], &[], &code)
+            .memory(1, None)
+            .data(2, &[0xFE, 0xFF])
+            .build();
+        let mut vm = instantiate(&wasm).unwrap();
+
+        // -2 from the data segment and the low byte of the stored value
+        assert_eq!(
+            call(&mut vm, 0, &[Value::Int32(0x1234_5678)], 1).unwrap(),
+            [Value::Int32(0x78 - 2)]
+        );
+    }
+
+    #[test]
+    fn calls_externs() {
+        let wasm = ModuleBuilder::default()
+            .import(
+                EXTERN_IMPORT_MODULE,
+                "SystemMath.__Max__SystemInt32_SystemInt32__SystemInt32",
+                &[I32, I32],
+                &[I32],
+            )
+            .function(&[I32], &[I32], &[], &[0x20, 0x00, 0x41, 0x05, 0x10, 0x00])
+            .build();
+        let mut vm = instantiate(&wasm).unwrap();
+
+        assert_eq!(
+            call(&mut vm, 1, &[Value::Int32(3)], 1).unwrap(),
+            [Value::Int32(5)]
+        );
+        assert_eq!(
+            call(&mut vm, 1, &[Value::Int32(8)], 1).unwrap(),
+            [Value::Int32(8)]
+        );
+    }
+
+    #[test]
+    fn reinterprets_the_unsigned_integers_of_externs() {
+        let wasm = ModuleBuilder::default()
+            .import(
+                EXTERN_IMPORT_MODULE,
+                "SystemMath.__Max__SystemUInt32_SystemUInt32__SystemUInt32",
+                &[I32, I32],
+                &[I32],
+            )
+            .import(
+                EXTERN_IMPORT_MODULE,
+                "SystemMath.__Min__SystemUInt64_SystemUInt64__SystemUInt64",
+                &[I64, I64],
+                &[I64],
+            )
+            .function(
+                &[I32, I32],
+                &[I32],
+                &[],
+                &[0x20, 0x00, 0x20, 0x01, 0x10, 0x00],
+            )
+            .function(
+                &[I64, I64],
+                &[I64],
+                &[],
+                &[0x20, 0x00, 0x20, 0x01, 0x10, 0x01],
+            )
+            .build();
+        let mut vm = instantiate(&wasm).unwrap();
+
+        // -1 is the largest unsigned integer
+        assert_eq!(
+            call(&mut vm, 2, &[Value::Int32(-1), Value::Int32(5)], 1).unwrap(),
+            [Value::Int32(-1)]
+        );
+        assert_eq!(
+            call(&mut vm, 3, &[Value::Int64(-1), Value::Int64(5)], 1).unwrap(),
+            [Value::Int64(5)]
+        );
+        assert_eq!(
+            call(&mut vm, 3, &[Value::Int64(i64::MIN), Value::Int64(-1)], 1).unwrap(),
+            [Value::Int64(i64::MIN)]
+        );
+    }
+
+    #[test]
+    fn truncates_floats() {
+        // i32.trunc_f32_s and i32.trunc_f64_s
+        assert_eq!(
+            unary(F32, I32, 0xA8, Value::Single(-1.9)).unwrap(),
+            Value::Int32(-1)
+        );
+        assert_eq!(
+            unary(F64, I32, 0xAA, Value::Double(-2147483648.9)).unwrap(),
+            Value::Int32(i32::MIN)
+        );
+        assert!(unary(F64, I32, 0xAA, Value::Double(2147483648.0)).is_err());
+        assert!(unary(F32, I32, 0xA8, Value::Single(f32::NAN)).is_err());
+        // i32.trunc_f64_u
+        assert_eq!(
+            unary(F64, I32, 0xAB, Value::Double(4294967295.9)).unwrap(),
+            Value::Int32(-1)
+        );
+        assert_eq!(
+            unary(F64, I32, 0xAB, Value::Double(-0.9)).unwrap(),
+            Value::Int32(0)
+        );
+        assert!(unary(F64, I32, 0xAB, Value::Double(-1.0)).is_err());
+        // i64.trunc_f32_u and i64.trunc_f64_s
+        assert_eq!(
+            unary(F32, I64, 0xAF, Value::Single(1.8446743e19)).unwrap(),
+            Value::Int64(1.8446743e19f32 as u64 as i64)
+        );
+        assert_eq!(
+            unary(F64, I64, 0xB0, Value::Double(-9.2e18)).unwrap(),
+            Value::Int64(-9_200_000_000_000_000_000)
+        );
+        assert!(unary(F64, I64, 0xB1, Value::Double(f64::INFINITY)).is_err());
+    }
+
+    #[test]
+    fn computes_float_functions() {
+        let float = |value| match value {
+            Value::Single(value) => value.into(),
+            Value::Double(value) => value,
+            value => panic!("not a float: {:?}", value),
+        };
+
+        // f32.abs, f32.sqrt and f64.sqrt
+        assert_eq!(
+            float(unary(F32, F32, 0x8B, Value::Single(-1.5)).unwrap()),
+            1.5
+        );
+        assert_eq!(
+            unary(F32, F32, 0x91, Value::Single(2.0)).unwrap(),
+            Value::Single(2.0f32.sqrt())
+        );
+        assert_eq!(
+            unary(F64, F64, 0x9F, Value::Double(2.0)).unwrap(),
+            Value::Double(2.0f64.sqrt())
+        );
+        // f64.ceil, f64.floor, f64.trunc and f64.nearest, which rounds ties to even
+        for (opcode, value, result) in [
+            (0x9B, -1.5, -1.0),
+            (0x9C, -1.5, -2.0),
+            (0x9D, -1.5, -1.0),
+            (0x9E, 2.5, 2.0),
+            (0x9E, 3.5, 4.0),
+        ] {
+            assert_eq!(
+                unary(F64, F64, opcode, Value::Double(value)).unwrap(),
+                Value::Double(result)
+            );
+        }
+        // f32.nearest
+        assert_eq!(
+            unary(F32, F32, 0x90, Value::Single(-0.5)).unwrap(),
+            Value::Single(-0.0)
+        );
+
+        let min_max = |ty, opcode, lhs: f64, rhs: f64| {
+            let (lhs, rhs) = match ty {
+                F32 => (Value::Single(lhs as f32), Value::Single(rhs as f32)),
+                _ => (Value::Double(lhs), Value::Double(rhs)),
+            };
+            float(binary(ty, ty, opcode, lhs, rhs).unwrap())
+        };
+        for (ty, min, max) in [(F32, 0x96, 0x97), (F64, 0xA4, 0xA5)] {
+            assert_eq!(min_max(ty, min, 2.0, 3.0), 2.0);
+            assert_eq!(min_max(ty, max, 2.0, 3.0), 3.0);
+            assert_eq!(min_max(ty, min, -4.0, -4.0), -4.0);
+            assert!(min_max(ty, min, 1.0, f64::NAN).is_nan());
+            assert!(min_max(ty, max, f64::NAN, 1.0).is_nan());
+            // the sign of the zeros
+            assert!(min_max(ty, min, 0.0, -0.0).is_sign_negative());
+            assert!(min_max(ty, min, -0.0, 0.0).is_sign_negative());
+            assert!(min_max(ty, min, 0.0, 0.0).is_sign_positive());
+            assert!(min_max(ty, max, -0.0, 0.0).is_sign_positive());
+            assert!(min_max(ty, max, 0.0, -0.0).is_sign_positive());
+            assert!(min_max(ty, max, -0.0, -0.0).is_sign_negative());
+        }
+    }
+
+    #[test]
+    fn grows_the_memory() {
+        let wasm = ModuleBuilder::default()
+            // memory.size
+            .function(&[], &[I32], &[], &[0x3F, 0x00])
+            // memory.grow
+            .function(&[I32], &[I32], &[], &[0x20, 0x00, 0x40, 0x00])
+            // i32.store8
+            .function(
+                &[I32, I32],
+                &[],
+                &[],
+                &[0x20, 0x00, 0x20, 0x01, 0x3A, 0x00, 0x00],
+            )
+            // i32.load8_u
+            .function(&[I32], &[I32], &[], &[0x20, 0x00, 0x2D, 0x00, 0x00])
+            .memory(1, Some(4))
+            .build();
+        let mut vm = instantiate(&wasm).unwrap();
+        let mut invoke = |function_index, args: &[i32]| {
+            let args = args
+                .iter()
+                .map(|arg| Value::Int32(*arg))
+                .collect::<Vec<_>>();
+            let results = usize::from(function_index != 2);
+            call(&mut vm, function_index, &args, results).map(|results| results.first().cloned())
+        };
+        let page = PAGE_SIZE as i32;
+
+        assert_eq!(invoke(0, &[]).unwrap(), Some(Value::Int32(1)));
+        invoke(2, &[10, 42]).unwrap();
+        assert!(invoke(2, &[page, 1]).is_err());
+
+        assert_eq!(invoke(1, &[2]).unwrap(), Some(Value::Int32(1)));
+        assert_eq!(invoke(0, &[]).unwrap(), Some(Value::Int32(3)));
+        // the memory is kept, and the new pages are usable
+        assert_eq!(invoke(3, &[10]).unwrap(), Some(Value::Int32(42)));
+        assert_eq!(invoke(3, &[page]).unwrap(), Some(Value::Int32(0)));
+        invoke(2, &[3 * page - 1, 7]).unwrap();
+        assert_eq!(invoke(3, &[3 * page - 1]).unwrap(), Some(Value::Int32(7)));
+
+        // past the maximum, or by a delta too large to be signed
+        assert_eq!(invoke(1, &[2]).unwrap(), Some(Value::Int32(-1)));
+        assert_eq!(invoke(1, &[-1]).unwrap(), Some(Value::Int32(-1)));
+        assert_eq!(invoke(1, &[1]).unwrap(), Some(Value::Int32(3)));
+        assert_eq!(invoke(1, &[0]).unwrap(), Some(Value::Int32(4)));
+        assert_eq!(invoke(0, &[]).unwrap(), Some(Value::Int32(4)));
+    }
+}
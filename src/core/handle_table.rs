@@ -0,0 +1,460 @@
+use ::alloc::{format, vec, vec::Vec};
+use ::core::slice;
+
+use crate::core::externs;
+use crate::core::runtime::{
+    code, copy, halt_address_var, initialized_data_section, jump, jump_if_false, jump_indirect,
+    null_var, one_var, runtime_label, runtime_var, zero_var,
+};
+use crate::udon::uasm::data::{
+    UasmCode, UasmCodeLabel, UasmDataSection, UasmInstruction, UasmType, UasmValue, UasmVarName,
+};
+
+/// The number of handles a table can hold by default, including the null handle.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+#[doc = include_str!("../../docs/handle_table.md")]
+#[derive(Debug, Clone)]
+pub struct HandleTable {
+    capacity: usize,
+}
+
+impl Default for HandleTable {
+    fn default() -> Self {
+        HandleTable::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl HandleTable {
+    pub fn new(capacity: usize) -> HandleTable {
+        HandleTable { capacity }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// the `SystemObjectArray` holding the objects
+    pub fn table_var() -> UasmVarName {
+        runtime_var("handle_table")
+    }
+
+    /// the `SystemInt32Array` linking each free slot to the next one
+    pub fn next_free_var() -> UasmVarName {
+        runtime_var("handle_next_free")
+    }
+
+    /// the first free slot
+    pub fn head_var() -> UasmVarName {
+        runtime_var("handle_head")
+    }
+
+    /// the capacity of the table, set to [`HandleTable::capacity`]
+    pub fn capacity_var() -> UasmVarName {
+        runtime_var("handle_capacity")
+    }
+
+    /// the variable holding the address to return to after initializing the table
+    pub fn return_address_var() -> UasmVarName {
+        runtime_var("handle_table_init_return")
+    }
+
+    /// the label of the code initializing the table
+    pub fn init_label() -> UasmCodeLabel {
+        runtime_label("handle_table_init")
+    }
+
+    /// the label of the code stopping the program when the table is full
+    fn full_label() -> UasmCodeLabel {
+        runtime_label("handle_table_full")
+    }
+
+    fn full_message_var() -> UasmVarName {
+        runtime_var("handle_table_full_message")
+    }
+
+    /// the object to allocate a handle for
+    pub fn object_var() -> UasmVarName {
+        runtime_var("handle_object")
+    }
+
+    /// the allocated handle (or the handle to free)
+    pub fn handle_var() -> UasmVarName {
+        runtime_var("handle")
+    }
+
+    /// the variable holding the address to return to after allocating or freeing a handle
+    pub fn handle_return_address_var() -> UasmVarName {
+        runtime_var("handle_return")
+    }
+
+    pub fn alloc_label() -> UasmCodeLabel {
+        runtime_label("handle_alloc")
+    }
+
+    pub fn free_label() -> UasmCodeLabel {
+        runtime_label("handle_free")
+    }
+
+    fn subroutine_condition_var() -> UasmVarName {
+        runtime_var("handle_condition")
+    }
+
+    fn freed_object_var() -> UasmVarName {
+        runtime_var("handle_freed_object")
+    }
+
+    fn index_var() -> UasmVarName {
+        runtime_var("handle_init_index")
+    }
+
+    fn next_var() -> UasmVarName {
+        runtime_var("handle_init_next")
+    }
+
+    fn condition_var() -> UasmVarName {
+        runtime_var("handle_init_condition")
+    }
+
+    /// The variables used by the table, apart from the constants of the runtime.
+    pub fn data_section(&self) -> UasmDataSection {
+        initialized_data_section(&[
+            (Self::table_var(), "SystemObjectArray", UasmValue::Null),
+            (Self::next_free_var(), "SystemInt32Array", UasmValue::Null),
+            // set again by the initialization
+            (Self::head_var(), "SystemInt32", UasmValue::Int(1)),
+            (
+                Self::capacity_var(),
+                "SystemInt32",
+                UasmValue::Int(self.capacity as i64),
+            ),
+            (Self::return_address_var(), "SystemUInt32", UasmValue::Null),
+            (Self::index_var(), "SystemInt32", UasmValue::Null),
+            (Self::next_var(), "SystemInt32", UasmValue::Null),
+            (Self::condition_var(), "SystemBoolean", UasmValue::Null),
+            (Self::object_var(), "SystemObject", UasmValue::Null),
+            (Self::handle_var(), "SystemInt32", UasmValue::Null),
+            (
+                Self::handle_return_address_var(),
+                "SystemUInt32",
+                UasmValue::Null,
+            ),
+            (
+                Self::subroutine_condition_var(),
+                "SystemBoolean",
+                UasmValue::Null,
+            ),
+            (Self::freed_object_var(), "SystemObject", UasmValue::Null),
+            (
+                Self::full_message_var(),
+                "SystemString",
+                UasmValue::String(format!("Handle table full: {} handles", self.capacity)),
+            ),
+        ])
+    }
+
+    /// The code allocating the arrays and linking every slot but the null one into the free list,
+    /// followed by the subroutines allocating and freeing a handle.
+    ///
+    /// The initialization jumps back to [`HandleTable::return_address_var`] when done,
+    /// the subroutines to [`HandleTable::handle_return_address_var`].
+    pub fn init_code(&self) -> anyhow::Result<UasmCode> {
+        let table = Self::table_var();
+        let next_free = Self::next_free_var();
+        let head = Self::head_var();
+        let capacity = Self::capacity_var();
+        let one = one_var();
+        let index = Self::index_var();
+        let next = Self::next_var();
+        let condition = Self::condition_var();
+
+        let loop_label = runtime_label("handle_table_init_loop");
+        let end_label = runtime_label("handle_table_init_end");
+
+        let mut init = Vec::new();
+        init.extend(externs::array_ctor(UasmType::Object).call(
+            None,
+            slice::from_ref(&capacity),
+            Some(&table),
+        )?);
+        init.extend(externs::array_ctor(UasmType::Int32).call(
+            None,
+            slice::from_ref(&capacity),
+            Some(&next_free),
+        )?);
+        init.extend(copy(&one, &head));
+        init.extend(copy(&one, &index));
+        // falls through into the loop
+
+        let mut body = Vec::new();
+        body.extend(externs::comparison(&UasmType::Int32, "LessThan").call(
+            None,
+            &[index.clone(), capacity],
+            Some(&condition),
+        )?);
+        body.extend(jump_if_false(&condition, &end_label));
+        body.extend(externs::binary(&UasmType::Int32, "Addition").call(
+            None,
+            &[index.clone(), one],
+            Some(&next),
+        )?);
+        body.extend(externs::array_set(UasmType::Int32).call(
+            Some(&next_free),
+            &[index.clone(), next.clone()],
+            None,
+        )?);
+        body.extend(copy(&next, &index));
+        body.push(jump(&loop_label));
+
+        let end = vec![jump_indirect(&Self::return_address_var())];
+
+        let mut full = externs::log_error().call(None, &[Self::full_message_var()], None)?;
+        full.push(jump_indirect(&halt_address_var()));
+
+        let mut blocks = vec![
+            (Self::init_label(), init),
+            (loop_label, body),
+            (end_label, end),
+            (Self::full_label(), full),
+        ];
+        blocks.extend(Self::alloc_blocks()?);
+        blocks.extend(Self::free_blocks()?);
+
+        code(blocks)
+    }
+
+    fn alloc_blocks() -> anyhow::Result<Vec<(UasmCodeLabel, Vec<UasmInstruction>)>> {
+        let object = Self::object_var();
+        let handle = Self::handle_var();
+        let head = Self::head_var();
+        let condition = Self::subroutine_condition_var();
+        let return_address = Self::handle_return_address_var();
+
+        let object_label = runtime_label("handle_alloc_object");
+
+        // null gets the null handle and no slot
+        let mut null = Vec::new();
+        null.extend(externs::comparison(&UasmType::Object, "Equality").call(
+            None,
+            &[object.clone(), null_var()],
+            Some(&condition),
+        )?);
+        null.extend(jump_if_false(&condition, &object_label));
+        null.extend(copy(&zero_var(), &handle));
+        null.push(jump_indirect(&return_address));
+
+        let mut alloc = Vec::new();
+        alloc.extend(copy(&head, &handle));
+        alloc.extend(externs::comparison(&UasmType::Int32, "LessThan").call(
+            None,
+            &[handle.clone(), Self::capacity_var()],
+            Some(&condition),
+        )?);
+        alloc.extend(jump_if_false(&condition, &Self::full_label()));
+        alloc.extend(externs::array_get(UasmType::Int32).call(
+            Some(&Self::next_free_var()),
+            slice::from_ref(&handle),
+            Some(&head),
+        )?);
+        alloc.extend(externs::object_array_set_value().call(
+            Some(&Self::table_var()),
+            &[object, handle],
+            None,
+        )?);
+        alloc.push(jump_indirect(&return_address));
+
+        Ok(vec![(Self::alloc_label(), null), (object_label, alloc)])
+    }
+
+    fn free_blocks() -> anyhow::Result<Vec<(UasmCodeLabel, Vec<UasmInstruction>)>> {
+        let handle = Self::handle_var();
+        let head = Self::head_var();
+        let object = Self::freed_object_var();
+        let condition = Self::subroutine_condition_var();
+        let return_address = Self::handle_return_address_var();
+
+        let free_label = runtime_label("handle_free_slot");
+        let end_label = runtime_label("handle_free_end");
+
+        // the null handle, a handle out of the table and a free slot are left alone
+        let mut check = Vec::new();
+        check.extend(externs::comparison(&UasmType::Int32, "GreaterThan").call(
+            None,
+            &[handle.clone(), zero_var()],
+            Some(&condition),
+        )?);
+        check.extend(jump_if_false(&condition, &end_label));
+        check.extend(externs::comparison(&UasmType::Int32, "LessThan").call(
+            None,
+            &[handle.clone(), Self::capacity_var()],
+            Some(&condition),
+        )?);
+        check.extend(jump_if_false(&condition, &end_label));
+        check.extend(externs::object_array_get_value().call(
+            Some(&Self::table_var()),
+            slice::from_ref(&handle),
+            Some(&object),
+        )?);
+        check.extend(externs::comparison(&UasmType::Object, "Inequality").call(
+            None,
+            &[object, null_var()],
+            Some(&condition),
+        )?);
+        check.extend(jump_if_false(&condition, &end_label));
+
+        let mut free = Vec::new();
+        free.extend(externs::array_set(UasmType::Int32).call(
+            Some(&Self::next_free_var()),
+            &[handle.clone(), head.clone()],
+            None,
+        )?);
+        free.extend(externs::object_array_set_value().call(
+            Some(&Self::table_var()),
+            &[null_var(), handle.clone()],
+            None,
+        )?);
+        free.extend(copy(&handle, &head));
+        // falls through into the end
+
+        let end = vec![jump_indirect(&return_address)];
+
+        Ok(vec![
+            (Self::free_label(), check),
+            (free_label, free),
+            (end_label, end),
+        ])
+    }
+
+    /// Call the subroutine storing `object` in a free slot, coming back to the address in `return_address`.
+    ///
+    /// The handle is in [`HandleTable::handle_var`] afterwards, the null handle if `object` is null.
+    /// The program stops with an error when the table is full, as the last free slot links to the capacity.
+    pub fn alloc(
+        &self,
+        object: &UasmVarName,
+        return_address: &UasmVarName,
+    ) -> anyhow::Result<Vec<UasmInstruction>> {
+        let mut instructions = Vec::new();
+        instructions.extend(copy(object, &Self::object_var()));
+        instructions.extend(copy(return_address, &Self::handle_return_address_var()));
+        instructions.push(jump(&Self::alloc_label()));
+
+        Ok(instructions)
+    }
+
+    /// Put the object of `handle` into `object`.
+    pub fn get(
+        &self,
+        handle: &UasmVarName,
+        object: &UasmVarName,
+    ) -> anyhow::Result<Vec<UasmInstruction>> {
+        externs::object_array_get_value().call(
+            Some(&Self::table_var()),
+            slice::from_ref(handle),
+            Some(object),
+        )
+    }
+
+    /// Call the subroutine releasing the slot of `handle`, coming back to the address in `return_address`.
+    ///
+    /// Nothing happens for the null handle, a handle out of the table or one already freed.
+    pub fn free(
+        &self,
+        handle: &UasmVarName,
+        return_address: &UasmVarName,
+    ) -> anyhow::Result<Vec<UasmInstruction>> {
+        let mut instructions = Vec::new();
+        instructions.extend(copy(handle, &Self::handle_var()));
+        instructions.extend(copy(return_address, &Self::handle_return_address_var()));
+        instructions.push(jump(&Self::free_label()));
+
+        Ok(instructions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::intrinsic::INTRINSIC_IMPORT_MODULE;
+    use crate::core::testing::{call, instantiate, ModuleBuilder, I32};
+    use crate::udon::uasm::vm::{Value, Vm};
+    use crate::udon::EXTERN_IMPORT_MODULE;
+
+    /// A module allocating arrays (function 3), getting their elements (function 4) and freeing handles (function 5).
+    fn module() -> Vm {
+        let wasm = ModuleBuilder::default()
+            .import(
+                EXTERN_IMPORT_MODULE,
+                "SystemObjectArray.__ctor__SystemInt32__SystemObjectArray",
+                &[I32],
+                &[I32],
+            )
+            .import(
+                EXTERN_IMPORT_MODULE,
+                "SystemObjectArray.__GetValue__SystemInt32__SystemObject",
+                &[I32, I32],
+                &[I32],
+            )
+            .import(INTRINSIC_IMPORT_MODULE, "free_handle", &[I32], &[])
+            .function(&[I32], &[I32], &[], &[0x20, 0x00, 0x10, 0x00])
+            .function(
+                &[I32, I32],
+                &[I32],
+                &[],
+                &[0x20, 0x00, 0x20, 0x01, 0x10, 0x01],
+            )
+            .function(&[I32], &[], &[], &[0x20, 0x00, 0x10, 0x02])
+            .build();
+
+        instantiate(&wasm).unwrap()
+    }
+
+    fn alloc(vm: &mut Vm) -> Value {
+        call(vm, 3, &[Value::Int32(1)], 1).unwrap().remove(0)
+    }
+
+    fn free(vm: &mut Vm, handle: i32) {
+        call(vm, 5, &[Value::Int32(handle)], 0).unwrap();
+    }
+
+    #[test]
+    fn allocates_and_frees_handles() {
+        let mut vm = module();
+
+        assert_eq!(alloc(&mut vm), Value::Int32(1));
+        assert_eq!(alloc(&mut vm), Value::Int32(2));
+        free(&mut vm, 1);
+        assert_eq!(alloc(&mut vm), Value::Int32(1));
+        assert_eq!(alloc(&mut vm), Value::Int32(3));
+    }
+
+    #[test]
+    fn gives_the_null_handle_to_null() {
+        let mut vm = module();
+        let array = alloc(&mut vm);
+
+        assert_eq!(
+            call(&mut vm, 4, &[array, Value::Int32(0)], 1).unwrap(),
+            [Value::Int32(0)]
+        );
+        assert_eq!(alloc(&mut vm), Value::Int32(2));
+    }
+
+    #[test]
+    fn ignores_invalid_frees() {
+        let mut vm = module();
+        assert_eq!(alloc(&mut vm), Value::Int32(1));
+        assert_eq!(alloc(&mut vm), Value::Int32(2));
+
+        free(&mut vm, 0);
+        free(&mut vm, -1);
+        free(&mut vm, 1024);
+        free(&mut vm, 3);
+        assert_eq!(alloc(&mut vm), Value::Int32(3));
+
+        free(&mut vm, 2);
+        free(&mut vm, 2);
+        assert_eq!(alloc(&mut vm), Value::Int32(2));
+        assert_eq!(alloc(&mut vm), Value::Int32(4));
+        assert!(vm.logs.is_empty());
+    }
+}
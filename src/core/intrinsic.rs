@@ -0,0 +1,85 @@
+//! Functions the guest imports from [`INTRINSIC_IMPORT_MODULE`], implemented by the translator.
+//!
+//! See `docs/function.md`.
+
+use ::alloc::vec;
+
+use crate::core::function::CodeBuilder;
+use crate::core::handle_table::HandleTable;
+use crate::core::runtime::this_var;
+use crate::udon::uasm::data::UasmVarName;
+use crate::udon::uasm::ExternSignature;
+
+/// The wasm import module of the intrinsics.
+pub const INTRINSIC_IMPORT_MODULE: &str = "wasdon";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intrinsic {
+    /// `request_serialization: [] -> []`
+    RequestSerialization,
+    /// `free_handle: [i32] -> []`, releasing a handle of the handle table
+    FreeHandle,
+}
+
+impl Intrinsic {
+    /// Get the intrinsic imported as `name` with the type `ty`.
+    pub fn new(name: &str, ty: &wasmparser::FuncType) -> anyhow::Result<Intrinsic> {
+        let intrinsic = match name {
+            "request_serialization" => Intrinsic::RequestSerialization,
+            "free_handle" => Intrinsic::FreeHandle,
+            _ => anyhow::bail!("Unknown intrinsic: {}", name),
+        };
+
+        let (params, results) = intrinsic.ty();
+        if ty.params() != params || ty.results() != results {
+            anyhow::bail!(
+                "Wrong type for intrinsic {}: {:?} -> {:?}",
+                name,
+                ty.params(),
+                ty.results()
+            )
+        }
+
+        Ok(intrinsic)
+    }
+
+    /// the types of the parameters and the results
+    fn ty(
+        &self,
+    ) -> (
+        &'static [wasmparser::ValType],
+        &'static [wasmparser::ValType],
+    ) {
+        use wasmparser::ValType;
+
+        match self {
+            Intrinsic::RequestSerialization => (&[], &[]),
+            Intrinsic::FreeHandle => (&[ValType::I32], &[]),
+        }
+    }
+
+    /// Whether the intrinsic uses the handle table, which is then initialized at startup.
+    pub fn uses_handle_table(&self) -> bool {
+        matches!(self, Intrinsic::FreeHandle)
+    }
+
+    /// Lower a call to the intrinsic with the variables holding its arguments.
+    pub fn lower(&self, code: &mut CodeBuilder, args: &[UasmVarName]) -> anyhow::Result<()> {
+        match self {
+            Intrinsic::RequestSerialization => code.call(
+                &ExternSignature::new(
+                    "VRCUdonCommonInterfacesIUdonEventReceiver",
+                    "RequestSerialization",
+                    vec![],
+                    None,
+                ),
+                Some(&this_var()),
+                &[],
+                None,
+            ),
+            Intrinsic::FreeHandle => {
+                code.call_with(|address| HandleTable::default().free(&args[0], address))
+            }
+        }
+    }
+}
@@ -1,9 +1,198 @@
-use ::alloc::format;
-use ::alloc::string::String;
+use ::alloc::{format, string::String};
+use ::core::fmt::Write;
 
-/// Mangle `str` with the mangling rules.
-pub fn mangle_str(str: Option<String>, rule: ManglingRule) -> String {
-    unimplemented!("Will be deprecated soon")
+/// the prefix of a mangled name
+const MANGLED_PREFIX: &str = "__M";
+
+/// How much of a name is kept when mangling it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManglingRule {
+    /// The name is a whole Udon identifier, such as an exported variable.
+    ///
+    /// A valid identifier is kept, unless it starts with `__` like the generated names.
+    Identifier,
+    /// The name is a part of a generated name, such as a debug name.
+    ///
+    /// A name made of ASCII letters, digits and `_` is kept, unless it starts with `_`,
+    /// which would make a mangled name with the `_` written before it.
+    Fragment,
+}
+
+#[doc = include_str!("../../docs/mangle.md")]
+pub fn mangle_str(str: &str, rule: ManglingRule) -> String {
+    let kept = match rule {
+        ManglingRule::Identifier => is_identifier(str) && !str.starts_with("__"),
+        ManglingRule::Fragment => {
+            str.starts_with(|c: char| c.is_ascii_alphanumeric())
+                && str.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+    };
+    if kept && !str.contains(MANGLED_PREFIX) {
+        return String::from(str);
+    }
+
+    let mut escaped = String::new();
+    for c in str.chars() {
+        match c {
+            '_' => escaped.push_str("__"),
+            c if c.is_ascii_alphanumeric() => escaped.push(c),
+            c => write!(escaped, "_{:x}_", c as u32).expect("writing into a String doesn't fail"),
+        }
+    }
+
+    format!("{}{}_{}", MANGLED_PREFIX, escaped.len(), escaped)
+}
+
+/// Get back the name mangled as `str` with [`mangle_str`].
+///
+/// A name which isn't mangled is returned as it is.
+pub fn demangle_str(str: &str) -> anyhow::Result<String> {
+    if !str.starts_with(MANGLED_PREFIX) {
+        return Ok(String::from(str));
+    }
+
+    match demangle_prefix(str) {
+        Some((name, len)) if len == str.len() => Ok(name),
+        _ => anyhow::bail!("Invalid mangled name: {}", str),
+    }
+}
+
+/// Replace the mangled names in `text`, including the ones inside generated names,
+/// with the names they stand for, e.g. to print an error message.
+pub fn demangle_names(text: &str) -> String {
+    let mut demangled = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(MANGLED_PREFIX) {
+        demangled.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match demangle_prefix(rest) {
+            Some((name, len)) => {
+                demangled.push_str(&name);
+                rest = &rest[len..];
+            }
+            None => {
+                demangled.push_str(MANGLED_PREFIX);
+                rest = &rest[MANGLED_PREFIX.len()..];
+            }
+        }
+    }
+    demangled.push_str(rest);
+
+    demangled
+}
+
+/// Whether `name` is a valid Udon identifier.
+pub fn is_identifier(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
-pub enum ManglingRule {}
+/// Demangle the mangled name at the start of `str`, returning the name and the length it takes in `str`.
+fn demangle_prefix(str: &str) -> Option<(String, usize)> {
+    let rest = str.strip_prefix(MANGLED_PREFIX)?;
+
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    let len = rest[..digits].parse::<usize>().ok()?;
+    let escaped = rest[digits..].strip_prefix('_')?.get(..len)?;
+
+    let mut name = String::new();
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '_' => match chars.next()? {
+                '_' => name.push('_'),
+                c => {
+                    let mut code = String::from(c);
+                    loop {
+                        match chars.next()? {
+                            '_' => break,
+                            c => code.push(c),
+                        }
+                    }
+                    name.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+            },
+            c if c.is_ascii_alphanumeric() => name.push(c),
+            _ => return None,
+        }
+    }
+
+    Some((name, MANGLED_PREFIX.len() + digits + 1 + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(name: &str, rule: ManglingRule) -> String {
+        let mangled = mangle_str(name, rule);
+        if rule == ManglingRule::Identifier {
+            assert!(is_identifier(&mangled), "{mangled}");
+        }
+        assert_eq!(demangle_str(&mangled).unwrap(), name);
+
+        mangled
+    }
+
+    #[test]
+    fn keeps_readable_names() {
+        assert_eq!(round_trip("score", ManglingRule::Identifier), "score");
+        assert_eq!(round_trip("_score", ManglingRule::Identifier), "_score");
+        assert_eq!(round_trip("main_loop", ManglingRule::Fragment), "main_loop");
+        assert_eq!(round_trip("0x", ManglingRule::Fragment), "0x");
+    }
+
+    #[test]
+    fn mangles_other_names() {
+        assert_eq!(
+            round_trip("core::fmt::write", ManglingRule::Fragment),
+            "__M28_core_3a__3a_fmt_3a__3a_write"
+        );
+        assert_eq!(
+            round_trip("high score", ManglingRule::Identifier),
+            "__M13_high_20_score"
+        );
+        assert_eq!(round_trip("", ManglingRule::Fragment), "__M0_");
+        assert_eq!(round_trip("0x", ManglingRule::Identifier), "__M2_0x");
+        assert_eq!(
+            round_trip("__G__0", ManglingRule::Identifier),
+            "__M10_____G____0"
+        );
+        round_trip("a__M5_hello", ManglingRule::Identifier);
+        round_trip("<T as Trait>::f\u{1F600}", ManglingRule::Fragment);
+    }
+
+    #[test]
+    fn mangles_fragments_starting_with_underscore() {
+        assert_eq!(
+            round_trip("_M5_hello", ManglingRule::Fragment),
+            "__M11___M5__hello"
+        );
+        assert_eq!(round_trip("_x", ManglingRule::Fragment), "__M3___x");
+    }
+
+    #[test]
+    fn demangles_names_in_text() {
+        let name = |name| format!("__G__0_{}", mangle_str(name, ManglingRule::Fragment));
+
+        assert_eq!(demangle_names(&name("_M5_hello")), "__G__0__M5_hello");
+        assert_eq!(demangle_names(&name("hello")), "__G__0_hello");
+        assert_eq!(
+            demangle_names(&format!("Unknown variable: {}__RET", name("a::b"))),
+            "Unknown variable: __G__0_a::b__RET"
+        );
+        assert_eq!(demangle_names("__M9_x and __M"), "__M9_x and __M");
+    }
+
+    #[test]
+    fn rejects_invalid_mangled_names() {
+        assert!(demangle_str("__M3_ab").is_err());
+        assert!(demangle_str("__M2_a_").is_err());
+        assert!(demangle_str("__M4__zz_").is_err());
+        assert_eq!(demangle_str("score").unwrap(), "score");
+    }
+}
@@ -0,0 +1,361 @@
+use ::alloc::{string::String, vec, vec::Vec};
+use ::core::slice;
+
+use crate::core::const_eval::{self, ConstValue};
+use crate::core::externs;
+use crate::core::function::CodeBuilder;
+use crate::core::runtime::{copy, jump_if_false, jump_indirect, runtime_label, runtime_var};
+use crate::core::wasm2uasm::{
+    generate_variable_name, init_label, init_return_var, GlobalIndexSpace, VarInfo,
+};
+use crate::core::ParsedData;
+use crate::udon::uasm::data::{UasmCodeLabel, UasmType, UasmValue, UasmVarName};
+use crate::udon::uasm::{ExternSignature, Uasm};
+
+/// the size of a wasm page in bytes
+pub const PAGE_SIZE: u64 = 0x1_0000;
+
+/// the number of pages the memory can hold at most, as an `object[]` is indexed with an `int`
+pub const MAX_PAGES: u64 = i32::MAX as u64 / PAGE_SIZE;
+
+#[doc = include_str!("../../docs/linear_memory.md")]
+#[derive(Debug, Clone)]
+pub struct LinearMemory {
+    /// the initial size in pages
+    pages: u64,
+    /// the size in pages the memory can grow up to
+    maximum: u64,
+}
+
+impl LinearMemory {
+    pub fn new(ty: &wasmparser::MemoryType) -> anyhow::Result<LinearMemory> {
+        if ty.memory64 || ty.shared {
+            anyhow::bail!("Unsupported memory type: {:?}", ty)
+        }
+
+        if ty.initial > MAX_PAGES {
+            anyhow::bail!("Memory too large: {} pages", ty.initial)
+        }
+
+        Ok(LinearMemory {
+            pages: ty.initial,
+            maximum: ty.maximum.unwrap_or(MAX_PAGES).min(MAX_PAGES),
+        })
+    }
+
+    /// Find the memory declared by `parsed` or the payloads parsed before it, if any.
+    pub fn find(
+        parsed: &ParsedData<wasmparser::Payload<'_>>,
+    ) -> anyhow::Result<Option<LinearMemory>> {
+        use wasmparser::{Payload, TypeRef};
+
+        let mut memories = Vec::new();
+
+        let mut current = Some(parsed);
+        while let Some(parsed) = current {
+            match parsed.get_data() {
+                Payload::ImportSection(section) => {
+                    for import in section.clone() {
+                        let import = import.map_err(|err| {
+                            anyhow::anyhow!("Failed to parse import section: {:?}", err)
+                        })?;
+                        if let TypeRef::Memory(_) = import.ty {
+                            anyhow::bail!(
+                                "Unsupported memory import from {:?}: {}",
+                                import.module,
+                                import.name
+                            )
+                        }
+                    }
+                }
+                Payload::MemorySection(section) => {
+                    for memory in section.clone() {
+                        memories.push(memory.map_err(|err| {
+                            anyhow::anyhow!("Failed to parse memory section: {:?}", err)
+                        })?);
+                    }
+                }
+                _ => {}
+            }
+            current = parsed.get_next();
+        }
+
+        match memories.as_slice() {
+            [] => Ok(None),
+            [memory] => Ok(Some(LinearMemory::new(memory)?)),
+            _ => anyhow::bail!("Unsupported multiple memories: {}", memories.len()),
+        }
+    }
+
+    pub fn pages(&self) -> u64 {
+        self.pages
+    }
+
+    pub fn maximum(&self) -> u64 {
+        self.maximum
+    }
+
+    /// the `SystemObjectArray` holding the linear memory
+    pub fn var() -> UasmVarName {
+        runtime_var("memory")
+    }
+
+    /// the current size of the memory in pages
+    pub fn pages_var() -> UasmVarName {
+        runtime_var("memory_pages")
+    }
+
+    /// the number of pages to grow the memory by
+    pub fn grow_delta_var() -> UasmVarName {
+        runtime_var("memory_grow_delta")
+    }
+
+    /// the previous size in pages after growing the memory, or `-1` if it can't grow
+    pub fn grow_result_var() -> UasmVarName {
+        runtime_var("memory_grow_result")
+    }
+
+    /// the variable holding the address to return to after growing the memory
+    pub fn grow_return_address_var() -> UasmVarName {
+        runtime_var("memory_grow_return")
+    }
+
+    pub fn grow_label() -> UasmCodeLabel {
+        runtime_label("memory_grow")
+    }
+
+    /// The variables holding the memory, the subroutine allocating it at startup
+    /// and the one growing it (see [`LinearMemory::grow_label`]).
+    pub fn uasm(&self) -> anyhow::Result<Uasm> {
+        let mut code = CodeBuilder::new("M", init_label("memory"));
+
+        code.declare(
+            &Self::var(),
+            UasmType::Array(UasmType::Object.into()),
+            UasmValue::Null,
+        );
+        code.declare(
+            &init_return_var("memory"),
+            UasmType::UInt32,
+            UasmValue::Null,
+        );
+
+        let size = code.constant(
+            UasmType::Int32,
+            UasmValue::Int((self.pages * PAGE_SIZE) as i64),
+        );
+        code.call(
+            &externs::array_ctor(UasmType::Object),
+            None,
+            slice::from_ref(&size),
+            Some(&Self::var()),
+        )?;
+        code.push([jump_indirect(&init_return_var("memory"))]);
+
+        self.grow(&mut code)?;
+
+        code.finish()
+    }
+
+    /// Push the subroutine growing the memory by [`LinearMemory::grow_delta_var`] pages,
+    /// which allocates a larger array and copies the memory into it.
+    ///
+    /// It jumps back to [`LinearMemory::grow_return_address_var`] when done.
+    fn grow(&self, code: &mut CodeBuilder) -> anyhow::Result<()> {
+        let pages = Self::pages_var();
+        let delta = Self::grow_delta_var();
+        let result = Self::grow_result_var();
+        let return_address = Self::grow_return_address_var();
+
+        code.declare(&pages, UasmType::Int32, UasmValue::Int(self.pages as i64));
+        code.declare(&delta, UasmType::Int32, UasmValue::Null);
+        code.declare(&result, UasmType::Int32, UasmValue::Null);
+        code.declare(&return_address, UasmType::UInt32, UasmValue::Null);
+
+        let zero = code.constant(UasmType::Int32, UasmValue::Int(0));
+        let minus_one = code.constant(UasmType::Int32, UasmValue::Int(-1));
+        let maximum = code.constant(UasmType::Int32, UasmValue::Int(self.maximum as i64));
+        let page_size = code.constant(UasmType::Int32, UasmValue::Int(PAGE_SIZE as i64));
+        let condition = code.temp(UasmType::Boolean);
+        let room = code.temp(UasmType::Int32);
+        let new_pages = code.temp(UasmType::Int32);
+        let size = code.temp(UasmType::Int32);
+        let len = code.temp(UasmType::Int32);
+        let memory = code.temp(UasmType::Array(UasmType::Object.into()));
+
+        let fail_label = code.new_label();
+        code.start_block(Self::grow_label());
+
+        // the delta is unsigned, so a negative one is too large
+        code.call(
+            &externs::comparison(&UasmType::Int32, "GreaterThanOrEqual"),
+            None,
+            &[delta.clone(), zero.clone()],
+            Some(&condition),
+        )?;
+        code.push(jump_if_false(&condition, &fail_label));
+        code.call(
+            &externs::binary(&UasmType::Int32, "Subtraction"),
+            None,
+            &[maximum, pages.clone()],
+            Some(&room),
+        )?;
+        code.call(
+            &externs::comparison(&UasmType::Int32, "LessThanOrEqual"),
+            None,
+            &[delta.clone(), room],
+            Some(&condition),
+        )?;
+        code.push(jump_if_false(&condition, &fail_label));
+
+        code.call(
+            &externs::binary(&UasmType::Int32, "Addition"),
+            None,
+            &[pages.clone(), delta],
+            Some(&new_pages),
+        )?;
+        code.call(
+            &externs::binary(&UasmType::Int32, "Multiplication"),
+            None,
+            &[new_pages.clone(), page_size.clone()],
+            Some(&size),
+        )?;
+        code.call(
+            &externs::array_ctor(UasmType::Object),
+            None,
+            slice::from_ref(&size),
+            Some(&memory),
+        )?;
+        code.call(
+            &externs::binary(&UasmType::Int32, "Multiplication"),
+            None,
+            &[pages.clone(), page_size],
+            Some(&len),
+        )?;
+        code.call(
+            &externs::array_copy(),
+            None,
+            &[Self::var(), zero.clone(), memory.clone(), zero, len],
+            None,
+        )?;
+        code.push(copy(&memory, &Self::var()));
+        code.push(copy(&pages, &result));
+        code.push(copy(&new_pages, &pages));
+        code.push([jump_indirect(&return_address)]);
+
+        code.start_block(fail_label);
+        code.push(copy(&minus_one, &result));
+        code.push([jump_indirect(&return_address)]);
+
+        Ok(())
+    }
+
+    /// The subroutine copying the active data segments into the memory at startup.
+    ///
+    /// A segment is held as a base64 `SystemString`, as Udon Assembly has no literal for a byte array.
+    pub fn data_uasm(
+        &self,
+        data_section: &wasmparser::SectionLimited<'_, wasmparser::Data<'_>>,
+        globals: &GlobalIndexSpace,
+    ) -> anyhow::Result<Uasm> {
+        use wasmparser::DataKind;
+
+        let mut code = CodeBuilder::new("D", init_label("data"));
+        code.declare(&init_return_var("data"), UasmType::UInt32, UasmValue::Null);
+
+        for (segment_index, data) in data_section.clone().into_iter().enumerate() {
+            let data =
+                data.map_err(|err| anyhow::anyhow!("Failed to parse data section: {:?}", err))?;
+
+            let DataKind::Active {
+                memory_index: 0,
+                offset_expr,
+            } = data.kind
+            else {
+                anyhow::bail!("Unsupported data segment: {}", segment_index)
+            };
+
+            let Some(ConstValue::I32(offset)) = const_eval::eval(&offset_expr, globals)? else {
+                anyhow::bail!("Unsupported offset of data segment: {}", segment_index)
+            };
+
+            if data.data.is_empty() {
+                continue;
+            }
+
+            let segment =
+                UasmVarName::new(generate_variable_name(VarInfo::Data { segment_index }).into());
+            code.declare(
+                &segment,
+                UasmType::String,
+                UasmValue::String(base64(data.data)),
+            );
+
+            let bytes = code.temp(UasmType::Array(UasmType::Byte.into()));
+            let zero = code.constant(UasmType::Int32, UasmValue::Int(0));
+            let offset = code.constant(UasmType::Int32, UasmValue::Int(offset.into()));
+            let len = code.constant(UasmType::Int32, UasmValue::Int(data.data.len() as i64));
+
+            code.call(
+                &ExternSignature::new(
+                    "SystemConvert",
+                    "FromBase64String",
+                    vec![UasmType::String],
+                    Some(UasmType::Array(UasmType::Byte.into())),
+                ),
+                None,
+                &[segment],
+                Some(&bytes),
+            )?;
+            // copying a `byte[]` into an `object[]` boxes each byte
+            code.call(
+                &externs::array_copy(),
+                None,
+                &[bytes, zero, Self::var(), offset, len],
+                None,
+            )?;
+        }
+
+        code.push([jump_indirect(&init_return_var("data"))]);
+
+        code.finish()
+    }
+}
+
+/// Encode `bytes` in base64 with padding, as `System.Convert.FromBase64String` reads it.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (index, byte)| {
+            word | (*byte as u32) << (16 - 8 * index)
+        });
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(word >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xFF, 0xFE, 0x00]), "//4A");
+    }
+}
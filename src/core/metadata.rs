@@ -0,0 +1,201 @@
+use ::alloc::{string::String, vec::Vec};
+
+use crate::udon::uasm::data::{UasmDataAttributeSync, UasmType};
+
+/// The name of the custom section holding the metadata.
+pub const METADATA_SECTION: &str = "wasdon";
+
+#[doc = include_str!("../../docs/metadata.md")]
+#[derive(Debug, Default, Clone)]
+pub struct Metadata {
+    pub globals: Vec<GlobalMetadata>,
+    pub events: Vec<EventMetadata>,
+}
+
+/// How a global is bound to an Udon variable.
+#[derive(Debug, Clone)]
+pub struct GlobalMetadata {
+    /// the index in the global index space
+    pub index: u32,
+    /// the name to export the variable as
+    pub name: Option<String>,
+    pub sync: Option<UasmDataAttributeSync>,
+    /// the Udon type of an `externref` global
+    pub ty: Option<UasmType>,
+}
+
+/// An Udon event calling a function.
+#[derive(Debug, Clone)]
+pub struct EventMetadata {
+    pub name: String,
+    /// the index in the function index space
+    pub function: u32,
+}
+
+impl Metadata {
+    /// Parse the content of the custom section.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Metadata> {
+        use serde_json::Value;
+
+        let root: Value = serde_json::from_slice(data).map_err(|err| {
+            anyhow::anyhow!("Failed to parse {} section: {}", METADATA_SECTION, err)
+        })?;
+
+        let Value::Object(root) = root else {
+            anyhow::bail!("Expected an object in {} section", METADATA_SECTION)
+        };
+
+        let entries = |key: &str| -> anyhow::Result<Vec<Value>> {
+            match root.get(key) {
+                None => Ok(Vec::new()),
+                Some(Value::Array(entries)) => Ok(entries.clone()),
+                Some(_) => anyhow::bail!("Expected an array in `{}`", key),
+            }
+        };
+
+        let index = |entry: &Value, key: &str| -> anyhow::Result<u32> {
+            entry
+                .get(key)
+                .and_then(Value::as_u64)
+                .and_then(|index| u32::try_from(index).ok())
+                .ok_or_else(|| anyhow::anyhow!("Missing `{}` in entry: {}", key, entry))
+        };
+
+        let string = |entry: &Value, key: &str| -> anyhow::Result<Option<String>> {
+            match entry.get(key) {
+                None => Ok(None),
+                Some(Value::String(value)) => Ok(Some(value.clone())),
+                Some(_) => anyhow::bail!("Expected a string in `{}` of entry: {}", key, entry),
+            }
+        };
+
+        let globals = entries("globals")?
+            .iter()
+            .map(|entry| {
+                Ok(GlobalMetadata {
+                    index: index(entry, "index")?,
+                    name: string(entry, "name")?,
+                    sync: string(entry, "sync")?
+                        .map(|mode| UasmDataAttributeSync::try_from(mode.as_str()))
+                        .transpose()?,
+                    ty: string(entry, "type")?.map(|ty| UasmType::from_udon_name(&ty)),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let events = entries("events")?
+            .iter()
+            .map(|entry| {
+                Ok(EventMetadata {
+                    name: string(entry, "name")?
+                        .ok_or_else(|| anyhow::anyhow!("Missing `name` in entry: {}", entry))?,
+                    function: index(entry, "function")?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Metadata { globals, events })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::{string::ToString, vec};
+
+    use super::*;
+    use crate::core::wasm2uasm::ModuleContext;
+    use crate::udon::uasm::Uasm;
+    use crate::wasm::parser::{WasmEntry, WasmParser};
+
+    #[test]
+    fn parses_the_metadata() {
+        let metadata = Metadata::parse(
+            br#"{
+                "globals": [
+                    { "index": 0, "name": "position", "sync": "linear" },
+                    { "index": 1, "type": "UnityEngineTransform" }
+                ],
+                "events": [{ "name": "_onDeserialization", "function": 3 }]
+            }"#,
+        )
+        .unwrap();
+
+        let [position, transform] = metadata.globals.as_slice() else {
+            panic!("Expected 2 globals: {:?}", metadata.globals)
+        };
+        assert_eq!(position.index, 0);
+        assert_eq!(position.name.as_deref(), Some("position"));
+        assert_eq!(position.sync, Some(UasmDataAttributeSync::Linear));
+        assert_eq!(position.ty, None);
+        assert_eq!(transform.name, None);
+        assert_eq!(transform.sync, None);
+        assert_eq!(
+            transform.ty,
+            Some(UasmType::Other("UnityEngineTransform".into()))
+        );
+
+        let [event] = metadata.events.as_slice() else {
+            panic!("Expected 1 event: {:?}", metadata.events)
+        };
+        assert_eq!(event.name, "_onDeserialization");
+        assert_eq!(event.function, 3);
+
+        // both keys are optional
+        let metadata = Metadata::parse(b"{}").unwrap();
+        assert!(metadata.globals.is_empty() && metadata.events.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_metadata() {
+        let error = |json: &str| Metadata::parse(json.as_bytes()).unwrap_err().to_string();
+
+        assert_eq!(error("[]"), "Expected an object in wasdon section");
+        assert_eq!(
+            error(r#"{ "globals": {} }"#),
+            "Expected an array in `globals`"
+        );
+        assert_eq!(
+            error(r#"{ "globals": [{ "name": "score" }] }"#),
+            r#"Missing `index` in entry: {"name":"score"}"#
+        );
+        assert_eq!(
+            error(r#"{ "events": [{ "function": 0 }] }"#),
+            r#"Missing `name` in entry: {"function":0}"#
+        );
+        assert!(Metadata::parse(br#"{ "globals": [{ "index": 0, "sync": "fast" }] }"#).is_err());
+    }
+
+    #[test]
+    fn resolves_the_metadata_before_the_globals() {
+        let json = br#"{ "globals": [{ "index": 0, "name": "score", "sync": "none" }] }"#;
+
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        // the custom section comes first
+        wasm.extend([0x00, (1 + METADATA_SECTION.len() + json.len()) as u8]);
+        wasm.push(METADATA_SECTION.len() as u8);
+        wasm.extend(METADATA_SECTION.as_bytes());
+        wasm.extend(json);
+        // (global (mut i32) (i32.const 0))
+        wasm.extend([0x06, 0x06, 0x01, 0x7F, 0x01, 0x41, 0x00, 0x0B]);
+
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+
+        let context = ModuleContext::new(&parsed, None).unwrap();
+        let units = parsed.interpret_all::<Uasm, _>(&context).unwrap();
+        let metadata = units
+            .iter()
+            .find(|unit| {
+                unit.data_section
+                    .as_ref()
+                    .is_some_and(|data_section| !data_section.get_aliases().is_empty())
+            })
+            .unwrap();
+
+        let alias = &metadata.data_section.as_ref().unwrap().get_aliases()[0];
+        assert_eq!(alias.internal.to_string(), "__G__0");
+        assert_eq!(alias.exported.to_string(), "score");
+        assert!(alias.attribute.exported);
+        assert_eq!(alias.attribute.sync, Some(UasmDataAttributeSync::None));
+    }
+}
@@ -1,4 +1,19 @@
+pub mod const_eval;
+pub mod extern_abi;
+pub(crate) mod externs;
+pub mod function;
+pub mod handle_table;
+pub mod intrinsic;
 pub mod mangle;
+pub mod memory;
+pub mod metadata;
+pub mod names;
+pub mod runtime;
+pub mod source_map;
+pub mod startup;
+pub mod string;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod wasm2uasm;
 
 use ::core::ops::Deref;
@@ -29,6 +44,24 @@ impl<T> ParsedData<T> {
     pub fn get_next(&self) -> Option<&ParsedData<T>> {
         Some(self.next.as_ref()?)
     }
+
+    /// Interpret this data and the data parsed before it, from the last one to the first one.
+    ///
+    /// The data stays linked, so that it can be walked again afterwards.
+    pub fn interpret_all<U, C>(&self, context: &C) -> anyhow::Result<Units<U>>
+    where
+        Self: InterpretableAs<U, C>,
+    {
+        let mut units = Units::new();
+
+        let mut current = Some(self);
+        while let Some(parsed) = current {
+            units.push(parsed.interpret(context)?);
+            current = parsed.get_next();
+        }
+
+        Ok(units)
+    }
 }
 
 impl<T> Iterator for ParsedData<T> {
@@ -41,23 +74,9 @@ impl<T> Iterator for ParsedData<T> {
     }
 }
 
-pub trait InterpretableAs<T>
-where
-    Self: Iterator<Item = Self> + Sized,
-{
-    fn interpret(&self) -> anyhow::Result<T>;
-    fn interpret_all(&mut self) -> anyhow::Result<Units<T>> {
-        let mut units = Units::new();
-
-        for item in self.into_iter() {
-            match item.interpret() {
-                Ok(unit) => units.push(unit),
-                Err(err) => return Err(err),
-            }
-        }
-
-        Ok(units)
-    }
+/// Data interpreted as `T`, knowing `C` of the whole input (e.g. the loaded externs).
+pub trait InterpretableAs<T, C> {
+    fn interpret(&self, context: &C) -> anyhow::Result<T>;
 }
 
 #[derive(Debug)]
@@ -73,6 +92,12 @@ impl<T> Units<T> {
     }
 }
 
+impl<T> Default for Units<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Deref for Units<T> {
     type Target = Vec<T>;
 
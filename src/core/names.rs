@@ -0,0 +1,317 @@
+use ::alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use hashbrown::{HashMap, HashSet};
+
+use crate::core::mangle::{mangle_str, ManglingRule};
+use crate::core::wasm2uasm::{function_name, generate_variable_name, VarInfo};
+use crate::core::ParsedData;
+use crate::udon::uasm::data::{UasmCodeLabel, UasmVarName};
+use crate::udon::uasm::Uasm;
+
+/// The names of a module read from the `name` custom section.
+///
+/// In debug mode, they are folded into the generated names of the variables and the labels
+/// (see `docs/variable.md`), so that the Udon Assembly can be read.
+#[derive(Debug, Default, Clone)]
+pub struct ModuleNames {
+    functions: HashMap<u32, String>,
+    /// the names of the locals by function index and local index
+    locals: HashMap<(u32, u32), String>,
+    globals: HashMap<u32, String>,
+}
+
+impl ModuleNames {
+    /// Read the name section among `parsed` and the payloads parsed before it, if any.
+    pub fn new(parsed: &ParsedData<wasmparser::Payload<'_>>) -> anyhow::Result<ModuleNames> {
+        use wasmparser::{Name, NameSectionReader, Payload};
+
+        let mut names = ModuleNames::default();
+
+        let mut current = Some(parsed);
+        while let Some(parsed) = current {
+            current = parsed.get_next();
+
+            let Payload::CustomSection(section) = parsed.get_data() else {
+                continue;
+            };
+            if section.name() != "name" {
+                continue;
+            }
+
+            let err = |err| anyhow::anyhow!("Failed to parse name section: {:?}", err);
+
+            for name in NameSectionReader::new(section.data(), section.data_offset()) {
+                match name.map_err(err)? {
+                    Name::Function(map) => {
+                        for naming in map {
+                            let naming = naming.map_err(err)?;
+                            names.functions.insert(naming.index, naming.name.into());
+                        }
+                    }
+                    Name::Local(map) => {
+                        for indirect in map {
+                            let indirect = indirect.map_err(err)?;
+                            for naming in indirect.names {
+                                let naming = naming.map_err(err)?;
+                                names
+                                    .locals
+                                    .insert((indirect.index, naming.index), naming.name.into());
+                            }
+                        }
+                    }
+                    Name::Global(map) => {
+                        for naming in map {
+                            let naming = naming.map_err(err)?;
+                            names.globals.insert(naming.index, naming.name.into());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
+    pub fn function(&self, function_index: u32) -> Option<&str> {
+        self.functions.get(&function_index).map(String::as_str)
+    }
+
+    pub fn local(&self, function_index: u32, local_index: u32) -> Option<&str> {
+        self.locals
+            .get(&(function_index, local_index))
+            .map(String::as_str)
+    }
+
+    pub fn global(&self, global_index: u32) -> Option<&str> {
+        self.globals.get(&global_index).map(String::as_str)
+    }
+
+    /// Rename the variables and the labels of `uasm` after the names.
+    ///
+    /// A name colliding with another one, or with a name already declared in `uasm`, is skipped.
+    pub fn apply(&self, uasm: &mut Uasm) {
+        let declared_variables = uasm
+            .data_section
+            .iter()
+            .flat_map(|data_section| data_section.get_data())
+            .map(|data| data.variable.name.to_string())
+            .collect::<HashSet<_>>();
+
+        let mut variables = Vec::new();
+        let mut labels = Vec::new();
+
+        for (&global_index, name) in self.globals.iter() {
+            let info = |name| VarInfo::Global {
+                global_index: global_index as usize,
+                name,
+            };
+            variables.push(renamed(info(None), info(Some(name.clone()))));
+        }
+
+        for (&function_index, name) in self.functions.iter() {
+            let function = |name| VarInfo::Function {
+                function_index,
+                name,
+            };
+            labels.push(renamed(function(None), function(Some(name.clone()))));
+
+            let ret = |name| VarInfo::Return {
+                function_index,
+                name,
+            };
+            variables.push(renamed(ret(None), ret(Some(name.clone()))));
+
+            // the results are declared along with the return address
+            let result = |result_index, name| VarInfo::Result {
+                function_index,
+                result_index,
+                name,
+            };
+            variables.extend(
+                (0..)
+                    .map(|result_index| {
+                        renamed(
+                            result(result_index, None),
+                            result(result_index, Some(name.clone())),
+                        )
+                    })
+                    .take_while(|(from, _)| declared_variables.contains(from)),
+            );
+        }
+
+        for (&(function_index, local_index), name) in self.locals.iter() {
+            // the index is kept, as several functions can have the same name
+            let fn_name = match self.function(function_index) {
+                Some(name) => format!(
+                    "{}_{}",
+                    function_name(function_index),
+                    mangle_str(name, ManglingRule::Fragment)
+                ),
+                None => function_name(function_index),
+            };
+
+            variables.push(renamed(
+                VarInfo::Local {
+                    local_index: local_index as usize,
+                    fn_name: function_name(function_index),
+                    name: None,
+                },
+                VarInfo::Local {
+                    local_index: local_index as usize,
+                    fn_name,
+                    name: Some(name.clone()),
+                },
+            ));
+        }
+
+        let declared_labels = uasm
+            .code_section
+            .iter()
+            .flat_map(|code_section| code_section.get_code().blocks())
+            .map(|(label, _)| label.to_string())
+            .collect();
+
+        let variables = without_collisions(variables, declared_variables)
+            .map(|(from, to)| (UasmVarName::new(from.into()), UasmVarName::new(to.into())))
+            .collect();
+        let labels = without_collisions(labels, declared_labels)
+            .map(|(from, to)| {
+                (
+                    UasmCodeLabel::new(from.into()),
+                    UasmCodeLabel::new(to.into()),
+                )
+            })
+            .collect();
+
+        uasm.rename(&variables, &labels);
+    }
+}
+
+fn renamed(from: VarInfo, to: VarInfo) -> (String, String) {
+    (generate_variable_name(from), generate_variable_name(to))
+}
+
+/// Drop the renamings to a name which is already taken, by another renaming or by a `declared` name.
+fn without_collisions(
+    renamings: Vec<(String, String)>,
+    declared: HashSet<String>,
+) -> impl Iterator<Item = (String, String)> {
+    let mut count = HashMap::<String, usize>::new();
+    for (from, to) in renamings.iter() {
+        *count.entry(from.clone()).or_default() += 1;
+        *count.entry(to.clone()).or_default() += 1;
+    }
+
+    renamings.into_iter().filter(move |(_, to)| {
+        let collides = count[to] > 1 || declared.contains(to);
+        if collides {
+            log::warn!("Name collision, keeping the generated name: {}", to);
+        }
+        !collides
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> ModuleNames {
+        let mut names = ModuleNames::default();
+        names.globals.insert(0, "a::b".into());
+        names.globals.insert(1, "b".into());
+        names.functions.insert(3, "core::fmt::write".into());
+        names.locals.insert((3, 0), "x".into());
+
+        names
+    }
+
+    #[test]
+    fn renames_after_the_names() {
+        let mut uasm: Uasm = "\
+.data_start
+  __G__0: %SystemInt32, 0
+  __F3_L0: %SystemInt32, 0
+  __F__3__RET: %SystemUInt32, 0
+.data_end
+
+.code_start
+  __F__3:
+    PUSH, __G__0
+    PUSH, __F3_L0
+    JUMP_INDIRECT, __F__3__RET
+.code_end
+"
+        .parse()
+        .unwrap();
+
+        names().apply(&mut uasm);
+
+        let variables: Vec<_> = uasm
+            .data_section
+            .as_ref()
+            .unwrap()
+            .get_data()
+            .iter()
+            .map(|data| data.variable.name.to_string())
+            .collect();
+        assert_eq!(
+            variables,
+            [
+                "__G__0___M10_a_3a__3a_b",
+                "__F3___M28_core_3a__3a_fmt_3a__3a_write_L0_x",
+                "__F__3___M28_core_3a__3a_fmt_3a__3a_write__RET",
+            ]
+        );
+        assert!(variables
+            .iter()
+            .all(|name| crate::core::mangle::is_identifier(name)));
+
+        let code = uasm.code_section.as_ref().unwrap().get_code();
+        let (label, block) = code.blocks().next().unwrap();
+        assert_eq!(
+            label.to_string(),
+            "__F__3___M28_core_3a__3a_fmt_3a__3a_write"
+        );
+        let instructions: Vec<_> = block
+            .get_instructions()
+            .iter()
+            .map(|instruction| instruction.opcode.to_string())
+            .collect();
+        assert_eq!(
+            instructions,
+            [
+                format!("PUSH, {}", variables[0]),
+                format!("PUSH, {}", variables[1]),
+                format!("JUMP_INDIRECT, {}", variables[2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_names_already_declared() {
+        let mut uasm: Uasm = "\
+.data_start
+  __G__1: %SystemInt32, 0
+  __G__1_b: %SystemInt32, 0
+.data_end
+"
+        .parse()
+        .unwrap();
+
+        names().apply(&mut uasm);
+
+        let variables: Vec<_> = uasm
+            .data_section
+            .as_ref()
+            .unwrap()
+            .get_data()
+            .iter()
+            .map(|data| data.variable.name.to_string())
+            .collect();
+        assert_eq!(variables, ["__G__1", "__G__1_b"]);
+    }
+}
@@ -0,0 +1,146 @@
+//! Building blocks of the runtime managed by the translator.
+
+use ::alloc::{vec, vec::Vec};
+
+use crate::core::handle_table::HandleTable;
+use crate::core::string::Utf8Strings;
+use crate::core::wasm2uasm::{generate_variable_name, VarInfo};
+use crate::udon::uasm::data::{
+    UasmCode, UasmCodeBlock, UasmCodeLabel, UasmCodeSection, UasmData, UasmDataAttribute,
+    UasmDataSection, UasmInstruction, UasmOpcode, UasmType, UasmValue, UasmVarName, UasmVariable,
+};
+use crate::udon::uasm::Uasm;
+
+/// the constant `0`
+pub fn zero_var() -> UasmVarName {
+    runtime_var("zero")
+}
+
+/// the constant `1`
+pub fn one_var() -> UasmVarName {
+    runtime_var("one")
+}
+
+/// the constant `null`
+pub fn null_var() -> UasmVarName {
+    runtime_var("null")
+}
+
+/// the behaviour running the program
+pub fn this_var() -> UasmVarName {
+    runtime_var("this")
+}
+
+/// the address where UdonVM stops
+pub const HALT_ADDRESS: u32 = 0xFFFF_FFFC;
+
+/// the constant [`HALT_ADDRESS`]
+pub fn halt_address_var() -> UasmVarName {
+    runtime_var("halt")
+}
+
+/// The constants shared by the runtime.
+pub fn constants_data_section() -> UasmDataSection {
+    initialized_data_section(&[
+        (zero_var(), "SystemInt32", UasmValue::Int(0)),
+        (one_var(), "SystemInt32", UasmValue::Int(1)),
+        (null_var(), "SystemObject", UasmValue::Null),
+        (this_var(), "VRCUdonUdonBehaviour", UasmValue::This),
+        (
+            halt_address_var(),
+            "SystemUInt32",
+            UasmValue::Int(HALT_ADDRESS.into()),
+        ),
+    ])
+}
+
+/// The units of the runtime, linked into a program only when it uses them
+/// (see [`crate::udon::uasm::linker::Linker::add_helper`]).
+pub fn helpers() -> anyhow::Result<Vec<Uasm>> {
+    let handle_table = HandleTable::default();
+    let strings = Utf8Strings;
+
+    Ok(vec![
+        Uasm::new(Some(constants_data_section()), None),
+        Uasm::new(
+            Some(handle_table.data_section()),
+            Some(UasmCodeSection::new(handle_table.init_code()?)),
+        ),
+        Uasm::new(
+            Some(strings.data_section()),
+            Some(UasmCodeSection::new(strings.code()?)),
+        ),
+    ])
+}
+
+pub(crate) fn runtime_var(name: &'static str) -> UasmVarName {
+    UasmVarName::new(generate_variable_name(VarInfo::Runtime { name }).into())
+}
+
+pub(crate) fn runtime_label(name: &'static str) -> UasmCodeLabel {
+    UasmCodeLabel::new(generate_variable_name(VarInfo::Runtime { name }).into())
+}
+
+/// Declare the variables initialized with `null`.
+pub(crate) fn data_section(variables: &[(UasmVarName, &str)]) -> UasmDataSection {
+    let variables = variables
+        .iter()
+        .map(|(name, ty)| (name.clone(), *ty, UasmValue::Null))
+        .collect::<Vec<_>>();
+
+    initialized_data_section(&variables)
+}
+
+pub(crate) fn initialized_data_section(
+    variables: &[(UasmVarName, &str, UasmValue)],
+) -> UasmDataSection {
+    let mut data_section = UasmDataSection::new();
+
+    for (name, ty, value) in variables.iter() {
+        data_section.push_data(&UasmData {
+            attribute: UasmDataAttribute::default(),
+            variable: UasmVariable::new(name.clone(), UasmType::from_udon_name(ty))
+                .with_value(value.clone()),
+        });
+    }
+
+    data_section
+}
+
+/// Lay out the blocks in order, so that a block can fall through into the next one.
+pub(crate) fn code(blocks: Vec<(UasmCodeLabel, Vec<UasmInstruction>)>) -> anyhow::Result<UasmCode> {
+    UasmCode::from_blocks(blocks.into_iter().map(|(label, instructions)| {
+        let mut block = UasmCodeBlock::new();
+        instructions
+            .iter()
+            .for_each(|instruction| block.push_instruction(instruction));
+
+        (label, block)
+    }))
+}
+
+pub(crate) fn push(var_name: &UasmVarName) -> UasmInstruction {
+    UasmInstruction::new(UasmOpcode::Push(var_name.clone()))
+}
+
+pub(crate) fn copy(src: &UasmVarName, dst: &UasmVarName) -> [UasmInstruction; 3] {
+    [push(src), push(dst), UasmInstruction::new(UasmOpcode::Copy)]
+}
+
+pub(crate) fn jump(label: &UasmCodeLabel) -> UasmInstruction {
+    UasmInstruction::new(UasmOpcode::Jump(label.clone()))
+}
+
+pub(crate) fn jump_if_false(
+    condition: &UasmVarName,
+    label: &UasmCodeLabel,
+) -> [UasmInstruction; 2] {
+    [
+        push(condition),
+        UasmInstruction::new(UasmOpcode::JumpIfFalse(label.clone())),
+    ]
+}
+
+pub(crate) fn jump_indirect(var_name: &UasmVarName) -> UasmInstruction {
+    UasmInstruction::new(UasmOpcode::JumpIndirect(var_name.clone()))
+}
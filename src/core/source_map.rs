@@ -0,0 +1,334 @@
+//! Map the instructions of the generated Udon Assembly back to the wasm binary and its sources.
+//!
+//! UdonVM reports exceptions with the address of the failing instruction.
+//! The source map gives, for each instruction lowered from a wasm operator,
+//! its address, its line in the Udon Assembly, the offset of the operator in the wasm binary
+//! and, when the binary carries DWARF, the source line the operator comes from.
+
+use ::alloc::{string::String, vec::Vec};
+use ::core::fmt;
+use hashbrown::HashMap;
+
+use crate::core::ParsedData;
+use crate::udon::uasm::Uasm;
+
+/// A line of a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// The line table read from the `.debug_line` custom section.
+#[derive(Debug, Default)]
+pub struct DebugLines {
+    /// the offset of the code section, which DWARF addresses are relative to
+    code_offset: usize,
+    /// the rows sorted by address
+    rows: Vec<(u64, SourceLocation)>,
+}
+
+impl DebugLines {
+    /// Read the DWARF sections among `parsed` and the payloads parsed before it.
+    ///
+    /// The table is empty if the binary carries no DWARF.
+    pub fn new(parsed: &ParsedData<wasmparser::Payload<'_>>) -> anyhow::Result<DebugLines> {
+        use gimli::{EndianSlice, LittleEndian};
+        use wasmparser::Payload;
+
+        let mut sections = HashMap::new();
+        let mut code_offset = 0;
+
+        let mut current = Some(parsed);
+        while let Some(parsed) = current {
+            match parsed.get_data() {
+                Payload::CustomSection(section) if section.name().starts_with(".debug_") => {
+                    sections.insert(section.name(), section.data());
+                }
+                Payload::CodeSectionStart { range, .. } => code_offset = range.start,
+                _ => {}
+            }
+            current = parsed.get_next();
+        }
+
+        if !sections.contains_key(".debug_line") {
+            return Ok(DebugLines::default());
+        }
+
+        let err = |err: gimli::Error| anyhow::anyhow!("Failed to parse DWARF: {:?}", err);
+
+        let dwarf = gimli::Dwarf::load(|id: gimli::SectionId| {
+            let data = sections.get(id.name()).copied().unwrap_or_default();
+            Ok::<_, gimli::Error>(EndianSlice::new(data, LittleEndian))
+        })
+        .map_err(err)?;
+
+        let mut rows = Vec::new();
+
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next().map_err(err)? {
+            let unit = dwarf.unit(header).map_err(err)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+
+            let mut program_rows = program.rows();
+            while let Some((header, row)) = program_rows.next_row().map_err(err)? {
+                let (Some(line), Some(file)) = (row.line(), row.file(header)) else {
+                    continue;
+                };
+
+                let mut path = String::new();
+                if let Some(directory) = file.directory(header) {
+                    let directory = dwarf.attr_string(&unit, directory).map_err(err)?;
+                    path.push_str(&directory.to_string_lossy());
+                    path.push('/');
+                }
+                let name = dwarf.attr_string(&unit, file.path_name()).map_err(err)?;
+                path.push_str(&name.to_string_lossy());
+
+                rows.push((
+                    row.address(),
+                    SourceLocation {
+                        file: path,
+                        line: line.get(),
+                    },
+                ));
+            }
+        }
+
+        rows.sort_by_key(|(address, _)| *address);
+
+        Ok(DebugLines { code_offset, rows })
+    }
+
+    /// Find the source line of the operator at `wasm_offset` in the binary.
+    pub fn lookup(&self, wasm_offset: usize) -> Option<&SourceLocation> {
+        let address = wasm_offset.checked_sub(self.code_offset)? as u64;
+
+        let index = self.rows.partition_point(|(row, _)| *row <= address);
+        let (_, location) = self.rows.get(index.checked_sub(1)?)?;
+
+        Some(location)
+    }
+}
+
+/// An instruction lowered from a wasm operator.
+#[derive(Debug, Clone)]
+pub struct SourceMapEntry {
+    /// the address of the instruction in the program
+    pub address: u32,
+    /// the line of the instruction in the Udon Assembly, starting from 1
+    pub uasm_line: usize,
+    pub wasm_offset: usize,
+    pub source: Option<SourceLocation>,
+}
+
+/// The source map of a program, written as a sidecar file of tab-separated values.
+#[derive(Debug, Default)]
+pub struct SourceMap(Vec<SourceMapEntry>);
+
+impl SourceMap {
+    /// Map the instructions of `uasm`, printed on `uasm_lines` as given by
+    /// [`UasmPrinter::print_with_lines`](crate::udon::uasm::printer::UasmPrinter::print_with_lines).
+    pub fn new(uasm: &Uasm, uasm_lines: &[usize], debug_lines: &DebugLines) -> SourceMap {
+        let Some(code_section) = &uasm.code_section else {
+            return SourceMap::default();
+        };
+
+        let mut lines = uasm_lines.iter().copied();
+
+        let mut entries = Vec::new();
+
+        for (address, _, block) in code_section.get_code().blocks_with_addresses() {
+            for (address, instruction) in block.instructions_with_addresses(address) {
+                let uasm_line = lines.next();
+
+                if let (Some(wasm_offset), Some(uasm_line)) = (instruction.offset, uasm_line) {
+                    entries.push(SourceMapEntry {
+                        address,
+                        uasm_line,
+                        wasm_offset,
+                        source: debug_lines.lookup(wasm_offset).cloned(),
+                    });
+                }
+            }
+        }
+
+        SourceMap(entries)
+    }
+
+    pub fn entries(&self) -> &Vec<SourceMapEntry> {
+        &self.0
+    }
+
+    /// Find the entry of the instruction at `address`.
+    pub fn lookup(&self, address: u32) -> Option<&SourceMapEntry> {
+        // the entries are sorted by address, as the instructions are laid out in order
+        let index = self
+            .0
+            .binary_search_by_key(&address, |entry| entry.address)
+            .ok()?;
+
+        self.0.get(index)
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# address\tuasm line\twasm offset\tsource")?;
+
+        for entry in self.0.iter() {
+            write!(
+                f,
+                "0x{:08X}\t{}\t0x{:X}\t",
+                entry.address, entry.uasm_line, entry.wasm_offset
+            )?;
+            match &entry.source {
+                Some(source) => writeln!(f, "{}", source)?,
+                None => writeln!(f, "-")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::{string::ToString, vec, vec::Vec};
+
+    use super::*;
+    use crate::udon::uasm::data::{
+        UasmCode, UasmCodeBlock, UasmCodeLabel, UasmCodeSection, UasmInstruction, UasmOpcode,
+        UasmVarName,
+    };
+    use crate::wasm::parser::{WasmEntry, WasmParser};
+
+    fn custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut section = vec![name.len() as u8];
+        section.extend(name.as_bytes());
+        section.extend(data);
+
+        let mut bytes = vec![0x00, section.len() as u8];
+        bytes.extend(section);
+        bytes
+    }
+
+    /// a module with a function of type `[] -> []` and a DWARF line table mapping
+    /// the code addresses `0x10` to `src/lib.rs:10` and `0x18` to `src/lib.rs:12`
+    fn module_with_dwarf() -> Vec<u8> {
+        let abbrev = [0x01, 0x11, 0x00, 0x10, 0x17, 0x00, 0x00, 0x00];
+        // a compile unit whose line program is at offset 0
+        let info = [12, 0, 0, 0, 4, 0, 0, 0, 0, 0, 4, 0x01, 0, 0, 0, 0];
+
+        let mut header = vec![1, 1, 1, 0xFB, 14, 13];
+        header.extend([0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        header.extend(b"src\0\0");
+        header.extend(b"lib.rs\0");
+        header.extend([1, 0, 0, 0]);
+        let program = [
+            0x00, 0x05, 0x02, 0x10, 0, 0, 0, // set the address to 0x10
+            0x03, 0x09, // advance the line to 10
+            0x01, // copy
+            0x02, 0x08, // advance the address to 0x18
+            0x03, 0x02, // advance the line to 12
+            0x01, // copy
+            0x02, 0x04, // advance the address to 0x1C
+            0x00, 0x01, 0x01, // end the sequence
+        ];
+        let mut line = Vec::new();
+        line.extend(((2 + 4 + header.len() + program.len()) as u32).to_le_bytes());
+        line.extend(4u16.to_le_bytes());
+        line.extend((header.len() as u32).to_le_bytes());
+        line.extend(header);
+        line.extend(program);
+
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        wasm.extend(custom_section(".debug_abbrev", &abbrev));
+        wasm.extend(custom_section(".debug_info", &info));
+        wasm.extend(custom_section(".debug_line", &line));
+        wasm.extend([0x01, 0x04, 0x01, 0x60, 0x00, 0x00]);
+        wasm.extend([0x03, 0x02, 0x01, 0x00]);
+        wasm.extend([0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B]);
+
+        wasm
+    }
+
+    #[test]
+    fn reads_the_lines_relative_to_the_code_section() {
+        let wasm = module_with_dwarf();
+        // the code section starts after its id and size
+        let code_offset = wasm.len() - 4;
+
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+        let debug_lines = DebugLines::new(&parsed).unwrap();
+
+        let line = |wasm_offset: usize| {
+            debug_lines
+                .lookup(wasm_offset)
+                .map(|location| location.to_string())
+        };
+        assert_eq!(line(code_offset + 0x0F), None);
+        assert_eq!(line(code_offset + 0x10).as_deref(), Some("src/lib.rs:10"));
+        assert_eq!(line(code_offset + 0x17).as_deref(), Some("src/lib.rs:10"));
+        assert_eq!(line(code_offset + 0x18).as_deref(), Some("src/lib.rs:12"));
+        assert_eq!(line(0x10), None);
+    }
+
+    #[test]
+    fn maps_the_instructions_lowered_from_operators() {
+        let instruction = |opcode: UasmOpcode, offset: Option<usize>| UasmInstruction {
+            offset,
+            ..UasmInstruction::new(opcode)
+        };
+        let push = || UasmOpcode::Push(UasmVarName::new("a".into()));
+
+        let mut block = UasmCodeBlock::new();
+        for instruction in [
+            instruction(push(), None),
+            instruction(push(), Some(0x21)),
+            instruction(UasmOpcode::Copy, Some(0x21)),
+            instruction(UasmOpcode::Nop, Some(0x25)),
+        ] {
+            block.push_instruction(&instruction);
+        }
+        let code = UasmCode::from_blocks([(UasmCodeLabel::new("_start".into()), block)]).unwrap();
+        let uasm = Uasm::new(None, Some(UasmCodeSection::new(code)));
+
+        let debug_lines = DebugLines {
+            code_offset: 0x20,
+            rows: vec![(
+                0x4,
+                SourceLocation {
+                    file: "src/lib.rs".into(),
+                    line: 3,
+                },
+            )],
+        };
+        let source_map = SourceMap::new(&uasm, &[3, 4, 5, 6], &debug_lines);
+
+        let entries: Vec<_> = source_map
+            .entries()
+            .iter()
+            .map(|entry| (entry.address, entry.uasm_line, entry.wasm_offset))
+            .collect();
+        assert_eq!(entries, [(8, 4, 0x21), (16, 5, 0x21), (20, 6, 0x25)]);
+
+        assert_eq!(source_map.lookup(16).unwrap().uasm_line, 5);
+        assert!(source_map.lookup(0).is_none());
+        assert!(source_map.lookup(12).is_none());
+        assert_eq!(
+            source_map.lookup(20).unwrap().source.as_ref().unwrap().line,
+            3
+        );
+        assert!(source_map.lookup(8).unwrap().source.is_none());
+    }
+}
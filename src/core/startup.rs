@@ -0,0 +1,158 @@
+use ::alloc::vec::Vec;
+
+use crate::core::extern_abi::ImportAbi;
+use crate::core::function::CodeBuilder;
+use crate::core::handle_table::HandleTable;
+use crate::core::intrinsic::{Intrinsic, INTRINSIC_IMPORT_MODULE};
+use crate::core::metadata::{Metadata, METADATA_SECTION};
+use crate::core::runtime::{halt_address_var, jump, jump_indirect, runtime_label};
+use crate::core::wasm2uasm::{
+    function_label, init_label, init_return_var, return_address_var, FunctionIndexSpace,
+    ModuleContext,
+};
+use crate::core::ParsedData;
+use crate::udon::extern_db::ExternDatabase;
+use crate::udon::uasm::data::{UasmCodeLabel, UasmVarName};
+use crate::udon::uasm::Uasm;
+use crate::udon::EXTERN_IMPORT_MODULE;
+
+/// The code running when VRChat sends the `_start` event, before any other code of the module.
+///
+/// It runs the subroutines initializing the module in order (the handle table, the globals, the memory, then the data segments),
+/// calls the start function of the module, and finally the function exported as `_start`, if any.
+#[derive(Debug, Default)]
+pub struct Startup {
+    /// the subroutines to run, with the variables holding their return addresses
+    subroutines: Vec<(UasmCodeLabel, UasmVarName)>,
+    start_function: Option<u32>,
+    /// whether a function is exported as the `_start` event
+    start_event: bool,
+}
+
+impl Startup {
+    /// the Udon event sent when the behaviour starts
+    pub const EVENT: &'static str = "_start";
+
+    /// the label of the event exported as `_start`, which is called at the end of the startup
+    pub fn event_label() -> UasmCodeLabel {
+        runtime_label("start_event")
+    }
+
+    /// Find what to run at startup in the whole module, which ends with `parsed`.
+    pub fn new(
+        parsed: &ParsedData<wasmparser::Payload<'_>>,
+        context: &ModuleContext<'_>,
+    ) -> anyhow::Result<Startup> {
+        use wasmparser::{ExternalKind, Payload};
+
+        let mut startup = Startup::default();
+        let mut data = false;
+
+        let mut current = Some(parsed);
+        while let Some(parsed) = current {
+            match parsed.get_data() {
+                Payload::DataSection(_) => data = true,
+                Payload::StartSection { func, .. } => startup.start_function = Some(*func),
+                Payload::ExportSection(section) => {
+                    for export in section.clone() {
+                        let export = export.map_err(|err| {
+                            anyhow::anyhow!("Failed to parse export section: {:?}", err)
+                        })?;
+                        if export.kind == ExternalKind::Func && export.name == Self::EVENT {
+                            startup.start_event = true;
+                        }
+                    }
+                }
+                Payload::CustomSection(section) if section.name() == METADATA_SECTION => {
+                    let metadata = Metadata::parse(section.data())?;
+                    if metadata
+                        .events
+                        .iter()
+                        .any(|event| event.name == Self::EVENT)
+                    {
+                        startup.start_event = true;
+                    }
+                }
+                _ => {}
+            }
+            current = parsed.get_next();
+        }
+
+        if uses_handle_table(&context.functions, context.extern_db)? {
+            startup
+                .subroutines
+                .push((HandleTable::init_label(), HandleTable::return_address_var()));
+        }
+        if context.globals.is_initialized_at_startup() {
+            startup
+                .subroutines
+                .push((init_label("globals"), init_return_var("globals")));
+        }
+        if context.memory.is_some() {
+            startup
+                .subroutines
+                .push((init_label("memory"), init_return_var("memory")));
+        }
+        if data {
+            startup
+                .subroutines
+                .push((init_label("data"), init_return_var("data")));
+        }
+
+        Ok(startup)
+    }
+
+    /// Whether there's anything to run at startup.
+    pub fn is_needed(&self) -> bool {
+        !self.subroutines.is_empty() || self.start_function.is_some() || self.start_event
+    }
+
+    /// The exported `_start` event running the startup.
+    pub fn uasm(&self) -> anyhow::Result<Uasm> {
+        if !self.is_needed() {
+            return Ok(Uasm::default());
+        }
+
+        let mut code =
+            CodeBuilder::new("START", UasmCodeLabel::new(Self::EVENT.into())).with_export(true);
+
+        for (label, return_address) in self.subroutines.iter() {
+            code.call_subroutine(label, return_address)?;
+        }
+
+        if let Some(function_index) = self.start_function {
+            code.call_subroutine(
+                &function_label(function_index),
+                &return_address_var(function_index),
+            )?;
+        }
+
+        if self.start_event {
+            code.push([jump(&Self::event_label())]);
+        } else {
+            code.push([jump_indirect(&halt_address_var())]);
+        }
+
+        code.finish()
+    }
+}
+
+/// Whether an import passes a value as a handle or releases one.
+fn uses_handle_table(
+    functions: &FunctionIndexSpace,
+    extern_db: Option<&ExternDatabase>,
+) -> anyhow::Result<bool> {
+    for (module, name, ty) in functions.imports() {
+        let uses_handle_table = match module {
+            EXTERN_IMPORT_MODULE => ImportAbi::resolve(name, ty, extern_db)?.uses_handle_table(),
+            INTRINSIC_IMPORT_MODULE => Intrinsic::new(name, ty)?.uses_handle_table(),
+            _ => false,
+        };
+
+        if uses_handle_table {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,421 @@
+use ::alloc::{vec, vec::Vec};
+use ::core::slice;
+
+use crate::core::externs;
+use crate::core::memory::LinearMemory;
+use crate::core::runtime::{
+    code, copy, data_section, jump, jump_if_false, jump_indirect, one_var, runtime_label,
+    runtime_var, zero_var,
+};
+use crate::udon::uasm::data::{
+    UasmCode, UasmCodeLabel, UasmDataSection, UasmInstruction, UasmType, UasmVarName,
+};
+use crate::udon::uasm::ExternSignature;
+
+#[doc = include_str!("../../docs/string.md")]
+#[derive(Debug, Default, Clone)]
+pub struct Utf8Strings;
+
+impl Utf8Strings {
+    /// the address of the UTF-8 bytes (or of the buffer when encoding)
+    pub fn ptr_var() -> UasmVarName {
+        runtime_var("utf8_ptr")
+    }
+
+    /// the length of the UTF-8 bytes (or the capacity of the buffer when encoding)
+    pub fn len_var() -> UasmVarName {
+        runtime_var("utf8_len")
+    }
+
+    /// the decoded string (or the string to encode)
+    pub fn string_var() -> UasmVarName {
+        runtime_var("utf8_string")
+    }
+
+    /// the whole length in bytes of the encoded string
+    pub fn encoded_len_var() -> UasmVarName {
+        runtime_var("utf8_encoded_len")
+    }
+
+    /// the variable holding the address to return to after decoding or encoding
+    pub fn return_address_var() -> UasmVarName {
+        runtime_var("utf8_return")
+    }
+
+    pub fn decode_label() -> UasmCodeLabel {
+        runtime_label("utf8_decode")
+    }
+
+    pub fn encode_label() -> UasmCodeLabel {
+        runtime_label("utf8_encode")
+    }
+
+    fn bytes_var() -> UasmVarName {
+        runtime_var("utf8_bytes")
+    }
+
+    fn byte_var() -> UasmVarName {
+        runtime_var("utf8_byte")
+    }
+
+    fn byte_object_var() -> UasmVarName {
+        runtime_var("utf8_byte_object")
+    }
+
+    fn index_var() -> UasmVarName {
+        runtime_var("utf8_index")
+    }
+
+    fn count_var() -> UasmVarName {
+        runtime_var("utf8_count")
+    }
+
+    fn address_var() -> UasmVarName {
+        runtime_var("utf8_address")
+    }
+
+    fn condition_var() -> UasmVarName {
+        runtime_var("utf8_condition")
+    }
+
+    fn encoding_var() -> UasmVarName {
+        runtime_var("utf8_encoding")
+    }
+
+    /// The variables used by the subroutines, apart from the constants of the runtime.
+    pub fn data_section(&self) -> UasmDataSection {
+        data_section(&[
+            (Self::ptr_var(), "SystemInt32"),
+            (Self::len_var(), "SystemInt32"),
+            (Self::string_var(), "SystemString"),
+            (Self::encoded_len_var(), "SystemInt32"),
+            (Self::return_address_var(), "SystemUInt32"),
+            (Self::bytes_var(), "SystemByteArray"),
+            (Self::byte_var(), "SystemByte"),
+            (Self::byte_object_var(), "SystemObject"),
+            (Self::index_var(), "SystemInt32"),
+            (Self::count_var(), "SystemInt32"),
+            (Self::address_var(), "SystemInt32"),
+            (Self::condition_var(), "SystemBoolean"),
+            (Self::encoding_var(), "SystemTextEncoding"),
+        ])
+    }
+
+    /// The subroutines decoding and encoding strings.
+    ///
+    /// Both jump back to [`Utf8Strings::return_address_var`] when done.
+    pub fn code(&self) -> anyhow::Result<UasmCode> {
+        let mut blocks = self.decode_blocks()?;
+        blocks.extend(self.encode_blocks()?);
+
+        code(blocks)
+    }
+
+    fn decode_blocks(&self) -> anyhow::Result<Vec<(UasmCodeLabel, Vec<UasmInstruction>)>> {
+        let ptr = Self::ptr_var();
+        let len = Self::len_var();
+        let bytes = Self::bytes_var();
+        let byte = Self::byte_var();
+        let byte_object = Self::byte_object_var();
+        let index = Self::index_var();
+        let address = Self::address_var();
+        let condition = Self::condition_var();
+        let encoding = Self::encoding_var();
+
+        let loop_label = runtime_label("utf8_decode_loop");
+        let end_label = runtime_label("utf8_decode_end");
+
+        let mut init = Vec::new();
+        init.extend(externs::array_ctor(UasmType::Byte).call(
+            None,
+            slice::from_ref(&len),
+            Some(&bytes),
+        )?);
+        init.extend(copy(&zero_var(), &index));
+        // falls through into the loop
+
+        let mut body = Vec::new();
+        body.extend(externs::comparison(&UasmType::Int32, "LessThan").call(
+            None,
+            &[index.clone(), len],
+            Some(&condition),
+        )?);
+        body.extend(jump_if_false(&condition, &end_label));
+        body.extend(externs::binary(&UasmType::Int32, "Addition").call(
+            None,
+            &[ptr, index.clone()],
+            Some(&address),
+        )?);
+        body.extend(externs::object_array_get_value().call(
+            Some(&LinearMemory::var()),
+            &[address],
+            Some(&byte_object),
+        )?);
+        body.extend(externs::convert(&UasmType::Object, &UasmType::Byte).call(
+            None,
+            &[byte_object],
+            Some(&byte),
+        )?);
+        body.extend(externs::array_set(UasmType::Byte).call(
+            Some(&bytes),
+            &[index.clone(), byte],
+            None,
+        )?);
+        body.extend(externs::binary(&UasmType::Int32, "Addition").call(
+            None,
+            &[index.clone(), one_var()],
+            Some(&index),
+        )?);
+        body.push(jump(&loop_label));
+
+        let mut end = Vec::new();
+        end.extend(utf8_encoding().call(None, &[], Some(&encoding))?);
+        end.extend(encoding_get_string().call(
+            Some(&encoding),
+            &[bytes],
+            Some(&Self::string_var()),
+        )?);
+        end.push(jump_indirect(&Self::return_address_var()));
+
+        Ok(vec![
+            (Self::decode_label(), init),
+            (loop_label, body),
+            (end_label, end),
+        ])
+    }
+
+    fn encode_blocks(&self) -> anyhow::Result<Vec<(UasmCodeLabel, Vec<UasmInstruction>)>> {
+        let ptr = Self::ptr_var();
+        let len = Self::len_var();
+        let encoded_len = Self::encoded_len_var();
+        let bytes = Self::bytes_var();
+        let byte = Self::byte_var();
+        let index = Self::index_var();
+        let count = Self::count_var();
+        let address = Self::address_var();
+        let condition = Self::condition_var();
+        let encoding = Self::encoding_var();
+
+        let loop_label = runtime_label("utf8_encode_loop");
+        let end_label = runtime_label("utf8_encode_end");
+
+        let mut init = Vec::new();
+        init.extend(utf8_encoding().call(None, &[], Some(&encoding))?);
+        init.extend(encoding_get_bytes().call(
+            Some(&encoding),
+            &[Self::string_var()],
+            Some(&bytes),
+        )?);
+        init.extend(byte_array_length().call(Some(&bytes), &[], Some(&encoded_len))?);
+        init.extend(externs::math("Min", &UasmType::Int32, 2).call(
+            None,
+            &[encoded_len, len],
+            Some(&count),
+        )?);
+        init.extend(copy(&zero_var(), &index));
+        // falls through into the loop
+
+        let mut body = Vec::new();
+        body.extend(externs::comparison(&UasmType::Int32, "LessThan").call(
+            None,
+            &[index.clone(), count],
+            Some(&condition),
+        )?);
+        body.extend(jump_if_false(&condition, &end_label));
+        body.extend(externs::array_get(UasmType::Byte).call(
+            Some(&bytes),
+            slice::from_ref(&index),
+            Some(&byte),
+        )?);
+        body.extend(externs::binary(&UasmType::Int32, "Addition").call(
+            None,
+            &[ptr, index.clone()],
+            Some(&address),
+        )?);
+        body.extend(externs::object_array_set_value().call(
+            Some(&LinearMemory::var()),
+            &[byte, address],
+            None,
+        )?);
+        body.extend(externs::binary(&UasmType::Int32, "Addition").call(
+            None,
+            &[index.clone(), one_var()],
+            Some(&index),
+        )?);
+        body.push(jump(&loop_label));
+
+        let end = vec![jump_indirect(&Self::return_address_var())];
+
+        Ok(vec![
+            (Self::encode_label(), init),
+            (loop_label, body),
+            (end_label, end),
+        ])
+    }
+
+    /// Call the decoding subroutine on `(ptr, len)`, coming back to the address in `return_address`.
+    ///
+    /// The string is in [`Utf8Strings::string_var`] afterwards.
+    pub fn decode(
+        &self,
+        ptr: &UasmVarName,
+        len: &UasmVarName,
+        return_address: &UasmVarName,
+    ) -> anyhow::Result<Vec<UasmInstruction>> {
+        let mut instructions = Vec::new();
+        instructions.extend(copy(ptr, &Self::ptr_var()));
+        instructions.extend(copy(len, &Self::len_var()));
+        instructions.extend(copy(return_address, &Self::return_address_var()));
+        instructions.push(jump(&Self::decode_label()));
+
+        Ok(instructions)
+    }
+
+    /// Call the encoding subroutine writing `string` into the buffer `(ptr, capacity)`,
+    /// coming back to the address in `return_address`.
+    ///
+    /// The whole length of the encoded string is in [`Utf8Strings::encoded_len_var`] afterwards.
+    pub fn encode(
+        &self,
+        string: &UasmVarName,
+        ptr: &UasmVarName,
+        capacity: &UasmVarName,
+        return_address: &UasmVarName,
+    ) -> anyhow::Result<Vec<UasmInstruction>> {
+        let mut instructions = Vec::new();
+        instructions.extend(copy(string, &Self::string_var()));
+        instructions.extend(copy(ptr, &Self::ptr_var()));
+        instructions.extend(copy(capacity, &Self::len_var()));
+        instructions.extend(copy(return_address, &Self::return_address_var()));
+        instructions.push(jump(&Self::encode_label()));
+
+        Ok(instructions)
+    }
+}
+
+fn encoding() -> UasmType {
+    UasmType::Other("SystemTextEncoding".into())
+}
+
+/// `System.Text.Encoding.UTF8`
+fn utf8_encoding() -> ExternSignature {
+    ExternSignature::new(encoding().udon_name(), "get_UTF8", vec![], Some(encoding()))
+}
+
+/// `System.Text.Encoding.GetString(byte[])`
+fn encoding_get_string() -> ExternSignature {
+    ExternSignature::new(
+        encoding().udon_name(),
+        "GetString",
+        vec![UasmType::Array(UasmType::Byte.into())],
+        Some(UasmType::String),
+    )
+}
+
+/// `System.Text.Encoding.GetBytes(string)`
+fn encoding_get_bytes() -> ExternSignature {
+    ExternSignature::new(
+        encoding().udon_name(),
+        "GetBytes",
+        vec![UasmType::String],
+        Some(UasmType::Array(UasmType::Byte.into())),
+    )
+}
+
+/// `System.Byte[].Length`
+fn byte_array_length() -> ExternSignature {
+    ExternSignature::new(
+        UasmType::Array(UasmType::Byte.into()).udon_name(),
+        "get_Length",
+        vec![],
+        Some(UasmType::Int32),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::vec::Vec;
+
+    use crate::core::testing::{call, instantiate, ModuleBuilder, I32};
+    use crate::udon::uasm::vm::{Value, Vm};
+    use crate::udon::EXTERN_IMPORT_MODULE;
+
+    /// A module logging strings (function 2), concatenating them into a buffer (function 3)
+    /// and reading a byte of the memory (function 4), with `hé` at 0 and `→` at 16.
+    fn module() -> Vm {
+        let wasm = ModuleBuilder::default()
+            .import(
+                EXTERN_IMPORT_MODULE,
+                "UnityEngineDebug.__Log__SystemString__SystemVoid",
+                &[I32, I32],
+                &[],
+            )
+            .import(
+                EXTERN_IMPORT_MODULE,
+                "SystemString.__Concat__SystemString_SystemString__SystemString",
+                &[I32; 6],
+                &[I32],
+            )
+            .function(&[I32, I32], &[], &[], &[0x20, 0x00, 0x20, 0x01, 0x10, 0x00])
+            .function(
+                &[I32; 6],
+                &[I32],
+                &[],
+                &[
+                    0x20, 0x00, 0x20, 0x01, 0x20, 0x02, 0x20, 0x03, 0x20, 0x04, 0x20, 0x05, 0x10,
+                    0x01,
+                ],
+            )
+            .function(&[I32], &[I32], &[], &[0x20, 0x00, 0x2D, 0x00, 0x00])
+            .memory(1, None)
+            .data(0, "hé".as_bytes())
+            .data(16, "→".as_bytes())
+            .build();
+
+        instantiate(&wasm).unwrap()
+    }
+
+    fn concat(vm: &mut Vm, capacity: i32) -> Value {
+        let args = [0, 3, 16, 3, 32, capacity].map(Value::Int32);
+        call(vm, 3, &args, 1).unwrap().remove(0)
+    }
+
+    fn bytes(vm: &mut Vm, ptr: i32, len: i32) -> Vec<u8> {
+        (ptr..ptr + len)
+            .map(
+                |address| match call(vm, 4, &[Value::Int32(address)], 1).unwrap()[..] {
+                    [Value::Int32(byte)] => byte as u8,
+                    ref results => panic!("not a byte: {:?}", results),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn decodes_strings() {
+        let mut vm = module();
+        call(&mut vm, 2, &[Value::Int32(0), Value::Int32(3)], 0).unwrap();
+        call(&mut vm, 2, &[Value::Int32(16), Value::Int32(3)], 0).unwrap();
+        call(&mut vm, 2, &[Value::Int32(0), Value::Int32(0)], 0).unwrap();
+
+        assert_eq!(vm.logs, ["hé", "→", ""]);
+    }
+
+    #[test]
+    fn encodes_strings() {
+        let mut vm = module();
+
+        assert_eq!(concat(&mut vm, 16), Value::Int32(6));
+        assert_eq!(bytes(&mut vm, 32, 7), "hé→\0".as_bytes());
+    }
+
+    #[test]
+    fn truncates_strings_to_the_capacity() {
+        let mut vm = module();
+
+        // the whole length is returned, and only the bytes fitting in the buffer are written
+        assert_eq!(concat(&mut vm, 4), Value::Int32(6));
+        assert_eq!(bytes(&mut vm, 32, 6), [b'h', 0xC3, 0xA9, 0xE2, 0, 0]);
+        assert_eq!(concat(&mut vm, 0), Value::Int32(6));
+    }
+}
@@ -0,0 +1,238 @@
+//! Building small modules, translating them and running them in the test VM.
+
+use ::alloc::{string::String, vec, vec::Vec};
+
+use crate::core::runtime::{self, HALT_ADDRESS};
+use crate::core::startup::Startup;
+use crate::core::wasm2uasm::{
+    function_label, local_var, result_var, return_address_var, ModuleContext,
+};
+use crate::udon::uasm::data::UasmCodeLabel;
+use crate::udon::uasm::linker::Linker;
+use crate::udon::uasm::vm::{Value, Vm};
+use crate::udon::uasm::Uasm;
+use crate::wasm::parser::{WasmEntry, WasmParser};
+
+pub(crate) const I32: u8 = 0x7F;
+pub(crate) const I64: u8 = 0x7E;
+pub(crate) const F32: u8 = 0x7D;
+pub(crate) const F64: u8 = 0x7C;
+
+fn leb128(mut value: usize, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn section(id: u8, count: usize, entries: Vec<u8>, out: &mut Vec<u8>) {
+    if count == 0 {
+        return;
+    }
+
+    let mut contents = Vec::new();
+    leb128(count, &mut contents);
+    contents.extend(entries);
+
+    out.push(id);
+    leb128(contents.len(), out);
+    out.extend(contents);
+}
+
+fn name(name: &str, out: &mut Vec<u8>) {
+    leb128(name.len(), out);
+    out.extend(name.as_bytes());
+}
+
+/// A module built section by section, each function having a type of its own.
+#[derive(Debug, Default)]
+pub(crate) struct ModuleBuilder {
+    types: Vec<(Vec<u8>, Vec<u8>)>,
+    imports: Vec<(String, String, usize)>,
+    /// the type and the body of each defined function
+    functions: Vec<(usize, Vec<u8>)>,
+    /// the encoded limits of the memory
+    memory: Option<Vec<u8>>,
+    data: Vec<(i32, Vec<u8>)>,
+}
+
+impl ModuleBuilder {
+    fn ty(&mut self, params: &[u8], results: &[u8]) -> usize {
+        self.types.push((params.to_vec(), results.to_vec()));
+        self.types.len() - 1
+    }
+
+    /// Import the function `module.name`, which comes before the defined functions in the index space.
+    pub(crate) fn import(
+        mut self,
+        module: &str,
+        name: &str,
+        params: &[u8],
+        results: &[u8],
+    ) -> Self {
+        let ty = self.ty(params, results);
+        self.imports.push((module.into(), name.into(), ty));
+        self
+    }
+
+    /// Define a function with `locals` (as encoded) and the instructions `code`, without the final `end`.
+    pub(crate) fn function(
+        mut self,
+        params: &[u8],
+        results: &[u8],
+        locals: &[u8],
+        code: &[u8],
+    ) -> Self {
+        let ty = self.ty(params, results);
+        let mut body = Vec::new();
+        leb128(locals.len(), &mut body);
+        for local in locals {
+            body.extend([0x01, *local]);
+        }
+        body.extend(code);
+        body.push(0x0B);
+        self.functions.push((ty, body));
+        self
+    }
+
+    /// Declare a memory of `pages` pages, which can grow up to `maximum` pages if given.
+    pub(crate) fn memory(mut self, pages: u32, maximum: Option<u32>) -> Self {
+        let mut limits = Vec::new();
+        match maximum {
+            Some(maximum) => {
+                limits.push(0x01);
+                leb128(pages as usize, &mut limits);
+                leb128(maximum as usize, &mut limits);
+            }
+            None => {
+                limits.push(0x00);
+                leb128(pages as usize, &mut limits);
+            }
+        }
+        self.memory = Some(limits);
+        self
+    }
+
+    /// Add an active data segment copied at `offset`, which must be below 64.
+    pub(crate) fn data(mut self, offset: i32, bytes: &[u8]) -> Self {
+        self.data.push((offset, bytes.to_vec()));
+        self
+    }
+
+    pub(crate) fn build(self) -> Vec<u8> {
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+
+        let mut types = Vec::new();
+        for (params, results) in self.types.iter() {
+            types.push(0x60);
+            for list in [params, results] {
+                leb128(list.len(), &mut types);
+                types.extend(list);
+            }
+        }
+        section(0x01, self.types.len(), types, &mut wasm);
+
+        let mut imports = Vec::new();
+        for (module, field, ty) in self.imports.iter() {
+            name(module, &mut imports);
+            name(field, &mut imports);
+            imports.push(0x00);
+            leb128(*ty, &mut imports);
+        }
+        section(0x02, self.imports.len(), imports, &mut wasm);
+
+        let mut functions = Vec::new();
+        for (ty, _) in self.functions.iter() {
+            leb128(*ty, &mut functions);
+        }
+        section(0x03, self.functions.len(), functions, &mut wasm);
+
+        if let Some(limits) = &self.memory {
+            section(0x05, 1, limits.clone(), &mut wasm);
+        }
+
+        let mut code = Vec::new();
+        for (_, body) in self.functions.iter() {
+            leb128(body.len(), &mut code);
+            code.extend(body);
+        }
+        section(0x0A, self.functions.len(), code, &mut wasm);
+
+        let mut data = Vec::new();
+        for (offset, bytes) in self.data.iter() {
+            data.extend([0x00, 0x41, *offset as u8, 0x0B]);
+            leb128(bytes.len(), &mut data);
+            data.extend(bytes);
+        }
+        section(0x0B, self.data.len(), data, &mut wasm);
+
+        wasm
+    }
+}
+
+/// Translate and link `wasm` as the translator does, without an extern database.
+pub(crate) fn translate(wasm: &[u8]) -> anyhow::Result<Uasm> {
+    let mut parser = WasmParser::from(WasmEntry::new(wasm, 0));
+    let parsed = parser.parse_all()?;
+    let context = ModuleContext::new(&parsed, None)?;
+
+    let mut linker = Linker::new();
+    linker.add_units(parsed.interpret_all(&context)?);
+    runtime::helpers()?
+        .into_iter()
+        .for_each(|helper| linker.add_helper(helper));
+
+    let mut uasm = linker.link()?;
+    uasm.resolve_aliases()?;
+    uasm.resolve_addresses()?;
+
+    Ok(uasm)
+}
+
+/// Translate `wasm` into the VM and run the startup, if the module needs one.
+pub(crate) fn instantiate(wasm: &[u8]) -> anyhow::Result<Vm> {
+    let mut vm = Vm::new(&translate(wasm)?)?;
+    let start = UasmCodeLabel::new(Startup::EVENT.into());
+    if vm.label_address(&start).is_ok() {
+        vm.run(&start)?;
+    }
+
+    Ok(vm)
+}
+
+/// Call the function `function_index` with `args`, returning its `results` results.
+pub(crate) fn call(
+    vm: &mut Vm,
+    function_index: u32,
+    args: &[Value],
+    results: usize,
+) -> anyhow::Result<Vec<Value>> {
+    for (local_index, arg) in args.iter().enumerate() {
+        vm.set(&local_var(function_index, local_index), arg.clone())?;
+    }
+    vm.set(
+        &return_address_var(function_index),
+        Value::UInt32(HALT_ADDRESS),
+    )?;
+
+    vm.run(&function_label(function_index))?;
+
+    (0..results)
+        .map(|result_index| Ok(vm.get(&result_var(function_index, result_index))?.clone()))
+        .collect()
+}
+
+/// Run the function `(params) -> (result)` whose instructions are `code`, and get its result.
+pub(crate) fn run(params: &[u8], result: u8, code: &[u8], args: &[Value]) -> anyhow::Result<Value> {
+    let wasm = ModuleBuilder::default()
+        .function(params, &[result], &[], code)
+        .build();
+    let mut vm = instantiate(&wasm)?;
+
+    Ok(call(&mut vm, 0, args, 1)?.remove(0))
+}
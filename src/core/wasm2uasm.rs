@@ -1,66 +1,713 @@
+use crate::core::const_eval::{self, ConstValue};
+use crate::core::externs;
+use crate::core::function::{check_recursion, lower_function, CodeBuilder, FunctionContext};
+use crate::core::mangle::{is_identifier, mangle_str, ManglingRule};
+use crate::core::memory::LinearMemory;
+use crate::core::metadata::{Metadata, METADATA_SECTION};
+use crate::core::runtime::{copy, halt_address_var, jump, jump_indirect};
+use crate::core::startup::Startup;
 use crate::core::InterpretableAs;
 use crate::core::ParsedData;
-use crate::udon::uasm::data::UasmInstruction;
+use crate::udon::extern_db::ExternDatabase;
 use crate::udon::uasm::data::{
-    UasmCode, UasmCodeLabel, UasmData, UasmDataAttribute, UasmDataSection, UasmType, UasmVarName,
+    UasmAlias, UasmCode, UasmCodeBlock, UasmCodeLabel, UasmCodeSection, UasmData,
+    UasmDataAttribute, UasmDataAttributeSync, UasmDataSection, UasmType, UasmValue, UasmVarName,
     UasmVariable,
 };
+use crate::udon::uasm::data::{UasmInstruction, UasmOpcode};
 use crate::udon::uasm::Uasm;
+use crate::udon::EXTERN_IMPORT_MODULE;
 use ::alloc::format;
+use ::alloc::vec;
+use ::alloc::vec::Vec;
+use hashbrown::HashMap;
 
-use ::alloc::string::String;
-
-impl InterpretableAs<UasmInstruction> for ParsedData<wasmparser::Operator<'_>> {
-    fn interpret(&self) -> anyhow::Result<UasmInstruction> {
-        use wasmparser::Operator;
-
-        match self.get_data() {
-            // TODO: implement all instructions
-            // Operator::Nop => Ok(UasmInstruction::new(UasmOpcode::Nop)),
-            _ => {
-                todo!()
-            }
-        }
-    }
-}
-
-impl TryInto<Uasm> for ParsedData<wasmparser::Payload<'_>> {
-    type Error = anyhow::Error;
-
-    fn try_into(self) -> Result<Uasm, Self::Error> {
-        unimplemented!()
-    }
-}
+use ::alloc::string::{String, ToString};
 
+/// The `name` of each variant is the name from the name section, only given in debug mode.
 #[doc = include_str!("../../docs/variable.md")]
 pub enum VarInfo {
-    Local { local_index: usize, fn_name: String },
-    Global { global_index: usize },
+    Local {
+        local_index: usize,
+        fn_name: String,
+        name: Option<String>,
+    },
+    Global {
+        global_index: usize,
+        name: Option<String>,
+    },
+    Function {
+        function_index: u32,
+        name: Option<String>,
+    },
+    Return {
+        function_index: u32,
+        name: Option<String>,
+    },
+    Runtime {
+        name: &'static str,
+    },
+    /// a slot of the operand stack of the code lowered in `scope` (e.g. `F0`)
+    Stack {
+        scope: String,
+        depth: usize,
+        ty: &'static str,
+    },
+    /// a temporary of the code lowered in `scope`
+    Temp {
+        scope: String,
+        index: usize,
+    },
+    /// a constant of the code lowered in `scope`
+    Const {
+        scope: String,
+        index: usize,
+    },
+    /// a label inside the code lowered in `scope`
+    Label {
+        scope: String,
+        index: usize,
+    },
+    /// the address of the code following a call in `scope`
+    ReturnSite {
+        scope: String,
+        index: usize,
+    },
+    Result {
+        function_index: u32,
+        result_index: usize,
+        name: Option<String>,
+    },
+    /// the subroutine initializing `name` at startup
+    Init {
+        name: &'static str,
+    },
+    /// the address the subroutine initializing `name` returns to
+    InitReturn {
+        name: &'static str,
+    },
+    Data {
+        segment_index: usize,
+    },
 }
 
 #[doc = include_str!("../../docs/variable.md")]
 pub fn generate_variable_name(info: VarInfo) -> String {
+    let debug_name = |name: Option<String>| match name {
+        Some(name) => format!("_{}", mangle_str(&name, ManglingRule::Fragment)),
+        None => String::new(),
+    };
+
     let var = match info {
         VarInfo::Local {
             local_index,
             fn_name,
+            name,
+        } => {
+            format!("{fn_name}_L{local_index}{}", debug_name(name))
+        }
+        VarInfo::Global { global_index, name } => {
+            format!("G__{global_index}{}", debug_name(name))
+        }
+        VarInfo::Function {
+            function_index,
+            name,
+        } => {
+            format!("F__{function_index}{}", debug_name(name))
+        }
+        VarInfo::Return {
+            function_index,
+            name,
         } => {
-            format!("{fn_name}_L{local_index}")
+            format!("F__{function_index}{}__RET", debug_name(name))
+        }
+        VarInfo::Runtime { name } => {
+            format!("RT__{name}")
         }
-        VarInfo::Global { global_index } => {
-            format!("G__{global_index}")
+        VarInfo::Stack { scope, depth, ty } => format!("{scope}_S{depth}_{ty}"),
+        VarInfo::Temp { scope, index } => format!("{scope}_T{index}"),
+        VarInfo::Const { scope, index } => format!("{scope}_K{index}"),
+        VarInfo::Label { scope, index } => format!("{scope}_B{index}"),
+        VarInfo::ReturnSite { scope, index } => format!("{scope}_A{index}"),
+        VarInfo::Result {
+            function_index,
+            result_index,
+            name,
+        } => {
+            format!("F__{function_index}{}__R{result_index}", debug_name(name))
         }
+        VarInfo::Init { name } => format!("INIT__{name}"),
+        VarInfo::InitReturn { name } => format!("INIT__{name}__RET"),
+        VarInfo::Data { segment_index } => format!("D__{segment_index}"),
     };
 
     format!("__{var}")
 }
 
+/// Get the name of the function `function_index` used in the names of its locals.
+pub fn function_name(function_index: u32) -> String {
+    format!("F{function_index}")
+}
+
+/// Get the label of the function `function_index` (see `docs/function.md`).
+pub fn function_label(function_index: u32) -> UasmCodeLabel {
+    UasmCodeLabel::new(
+        generate_variable_name(VarInfo::Function {
+            function_index,
+            name: None,
+        })
+        .into(),
+    )
+}
+
+/// Get the variable holding the address the function `function_index` returns to.
+pub fn return_address_var(function_index: u32) -> UasmVarName {
+    UasmVarName::new(
+        generate_variable_name(VarInfo::Return {
+            function_index,
+            name: None,
+        })
+        .into(),
+    )
+}
+
+/// Get the variable receiving the result `result_index` of the function `function_index`.
+pub fn result_var(function_index: u32, result_index: usize) -> UasmVarName {
+    UasmVarName::new(
+        generate_variable_name(VarInfo::Result {
+            function_index,
+            result_index,
+            name: None,
+        })
+        .into(),
+    )
+}
+
+/// Get the variable holding the local `local_index` of the function `function_index`.
+pub fn local_var(function_index: u32, local_index: usize) -> UasmVarName {
+    UasmVarName::new(
+        generate_variable_name(VarInfo::Local {
+            local_index,
+            fn_name: function_name(function_index),
+            name: None,
+        })
+        .into(),
+    )
+}
+
+/// Get the label of the subroutine initializing `name` at startup.
+pub fn init_label(name: &'static str) -> UasmCodeLabel {
+    UasmCodeLabel::new(generate_variable_name(VarInfo::Init { name }).into())
+}
+
+/// Get the variable holding the address the subroutine initializing `name` returns to.
+pub fn init_return_var(name: &'static str) -> UasmVarName {
+    UasmVarName::new(generate_variable_name(VarInfo::InitReturn { name }).into())
+}
+
+/// A function in the function index space of a module.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub ty: wasmparser::FuncType,
+    /// the module and the name of an imported function
+    pub import: Option<(String, String)>,
+}
+
+/// The functions of a module in the order of the function index space, where imported functions come first.
+#[derive(Debug, Default)]
+pub struct FunctionIndexSpace {
+    types: Vec<wasmparser::FuncType>,
+    functions: Vec<Function>,
+}
+
+impl FunctionIndexSpace {
+    /// Collect the functions declared by `parsed` and the payloads parsed before it.
+    pub fn new(parsed: &ParsedData<wasmparser::Payload<'_>>) -> anyhow::Result<FunctionIndexSpace> {
+        use wasmparser::{Payload, Type, TypeRef};
+
+        let mut type_sections = Vec::new();
+        let mut import_sections = Vec::new();
+        let mut function_sections = Vec::new();
+
+        let mut current = Some(parsed);
+        while let Some(parsed) = current {
+            match parsed.get_data() {
+                Payload::TypeSection(section) => type_sections.push(section.clone()),
+                Payload::ImportSection(section) => import_sections.push(section.clone()),
+                Payload::FunctionSection(section) => function_sections.push(section.clone()),
+                _ => {}
+            }
+            current = parsed.get_next();
+        }
+
+        let mut types = Vec::new();
+        for section in type_sections.into_iter().rev() {
+            for ty in section {
+                let Type::Func(func_type) =
+                    ty.map_err(|err| anyhow::anyhow!("Failed to parse type section: {:?}", err))?;
+                types.push(func_type);
+            }
+        }
+
+        let get_type = |type_index: u32| {
+            types
+                .get(type_index as usize)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Unknown type: {}", type_index))
+        };
+
+        let mut functions = Vec::new();
+
+        for section in import_sections.into_iter().rev() {
+            for import in section {
+                let import = import
+                    .map_err(|err| anyhow::anyhow!("Failed to parse import section: {:?}", err))?;
+
+                if let TypeRef::Func(type_index) = import.ty {
+                    functions.push(Function {
+                        ty: get_type(type_index)?,
+                        import: Some((import.module.to_string(), import.name.to_string())),
+                    });
+                }
+            }
+        }
+
+        for section in function_sections.into_iter().rev() {
+            for type_index in section {
+                let type_index = type_index.map_err(|err| {
+                    anyhow::anyhow!("Failed to parse function section: {:?}", err)
+                })?;
+
+                functions.push(Function {
+                    ty: get_type(type_index)?,
+                    import: None,
+                });
+            }
+        }
+
+        Ok(FunctionIndexSpace { types, functions })
+    }
+
+    pub fn get(&self, function_index: u32) -> anyhow::Result<&Function> {
+        self.functions
+            .get(function_index as usize)
+            .ok_or_else(|| anyhow::anyhow!("Unknown function: {}", function_index))
+    }
+
+    /// The imported functions, with the module and the name of their import.
+    pub fn imports(&self) -> impl Iterator<Item = (&str, &str, &wasmparser::FuncType)> {
+        self.functions.iter().filter_map(|function| {
+            let (module, name) = function.import.as_ref()?;
+            Some((module.as_str(), name.as_str(), &function.ty))
+        })
+    }
+
+    /// Get the type `type_index`, e.g. the type of a block.
+    pub fn func_type(&self, type_index: u32) -> anyhow::Result<&wasmparser::FuncType> {
+        self.types
+            .get(type_index as usize)
+            .ok_or_else(|| anyhow::anyhow!("Unknown type: {}", type_index))
+    }
+
+    pub fn imported_count(&self) -> usize {
+        self.functions
+            .iter()
+            .filter(|function| function.import.is_some())
+            .count()
+    }
+
+    /// Lower the Udon event `name` calling the function `function_index`, which returns to the halt address.
+    ///
+    /// The `_start` event is called by the code running at startup instead (see [`Startup`]).
+    pub fn event(
+        &self,
+        name: &str,
+        function_index: u32,
+    ) -> anyhow::Result<(UasmCodeLabel, UasmCodeBlock)> {
+        let function = self.get(function_index)?;
+
+        if function.import.is_some() {
+            anyhow::bail!("Cannot export an imported function as an event: {}", name)
+        }
+
+        if !function.ty.params().is_empty() || !function.ty.results().is_empty() {
+            anyhow::bail!("An event takes no parameters and returns nothing: {}", name)
+        }
+
+        if !is_identifier(name) {
+            anyhow::bail!("Invalid name for an Udon event: {:?}", name)
+        }
+
+        let (label, exported) = match name {
+            Startup::EVENT => (Startup::event_label(), false),
+            name => (UasmCodeLabel::new(name.into()), true),
+        };
+
+        let mut block = UasmCodeBlock::new()
+            .with_export(exported)
+            .with_comment(format!("calls function {}", function_index));
+        copy(&halt_address_var(), &return_address_var(function_index))
+            .iter()
+            .for_each(|instruction| block.push_instruction(instruction));
+        block.push_instruction(&jump(&function_label(function_index)));
+
+        Ok((label, block))
+    }
+}
+
+/// A global in the global index space of a module.
+#[derive(Debug, Clone)]
+pub struct GlobalVar {
+    pub name: UasmVarName,
+    pub ty: UasmType,
+    pub mutable: bool,
+    pub imported: bool,
+    /// the initial value, if known at translate time
+    pub value: Option<ConstValue>,
+}
+
+/// The globals of a module in the order of the global index space, where imported globals come first.
+///
+/// An imported global is bound to an exported Udon variable named after the import,
+/// so that it can be set in the Unity inspector.
+#[derive(Debug, Default)]
+pub struct GlobalIndexSpace(Vec<GlobalVar>);
+
+impl GlobalIndexSpace {
+    /// Collect the globals declared by `parsed` and the payloads parsed before it.
+    pub fn new(parsed: &ParsedData<wasmparser::Payload<'_>>) -> anyhow::Result<GlobalIndexSpace> {
+        use wasmparser::{Payload, TypeRef};
+
+        let mut import_sections = Vec::new();
+        let mut global_sections = Vec::new();
+
+        let mut current = Some(parsed);
+        while let Some(parsed) = current {
+            match parsed.get_data() {
+                Payload::ImportSection(section) => import_sections.push(section.clone()),
+                Payload::GlobalSection(section) => global_sections.push(section.clone()),
+                _ => {}
+            }
+            current = parsed.get_next();
+        }
+
+        let mut globals = GlobalIndexSpace::default();
+
+        for section in import_sections.into_iter().rev() {
+            for import in section {
+                let import = import
+                    .map_err(|err| anyhow::anyhow!("Failed to parse import section: {:?}", err))?;
+
+                if let TypeRef::Global(ty) = import.ty {
+                    globals.0.push(GlobalVar {
+                        name: imported_global_name(&import),
+                        ty: UasmType::try_from(ty.content_type)?,
+                        mutable: ty.mutable,
+                        imported: true,
+                        value: None,
+                    });
+                }
+            }
+        }
+
+        for section in global_sections.into_iter().rev() {
+            for global in section {
+                let global = global
+                    .map_err(|err| anyhow::anyhow!("Failed to parse global section: {:?}", err))?;
+
+                let global_index = globals.0.len();
+                // an initializer only refers to the globals before it
+                let value = const_eval::eval(&global.init_expr, &globals)?;
+                globals.0.push(GlobalVar {
+                    name: UasmVarName::new(
+                        generate_variable_name(VarInfo::Global {
+                            global_index,
+                            name: None,
+                        })
+                        .into(),
+                    ),
+                    ty: UasmType::try_from(global.ty.content_type)?,
+                    mutable: global.ty.mutable,
+                    imported: false,
+                    value,
+                });
+            }
+        }
+
+        Ok(globals)
+    }
+
+    pub fn get(&self, global_index: u32) -> anyhow::Result<&GlobalVar> {
+        self.0
+            .get(global_index as usize)
+            .ok_or_else(|| anyhow::anyhow!("Unknown global: {}", global_index))
+    }
+
+    pub fn imported_count(&self) -> usize {
+        self.0.iter().filter(|global| global.imported).count()
+    }
+
+    /// Whether a global of the module is initialized at startup,
+    /// as its initializer reads an imported global or takes the address of a function.
+    pub fn is_initialized_at_startup(&self) -> bool {
+        self.0
+            .iter()
+            .any(|global| !global.imported && global.value.is_none())
+    }
+
+    /// Lower `global.get`, pushing the variable of the global.
+    pub fn global_get(&self, global_index: u32) -> anyhow::Result<Vec<UasmInstruction>> {
+        let global = self.get(global_index)?;
+
+        Ok(vec![UasmInstruction::new(UasmOpcode::Push(
+            global.name.clone(),
+        ))])
+    }
+
+    /// Lower `global.set`, copying the variable on the top of the stack into the variable of the global.
+    pub fn global_set(&self, global_index: u32) -> anyhow::Result<Vec<UasmInstruction>> {
+        let global = self.get(global_index)?;
+
+        if !global.mutable {
+            anyhow::bail!("Cannot set an immutable global: {}", global_index)
+        }
+
+        Ok(vec![
+            UasmInstruction::new(UasmOpcode::Push(global.name.clone())),
+            UasmInstruction::new(UasmOpcode::Copy),
+        ])
+    }
+}
+
+/// Get the name of the Udon variable bound to an imported global.
+///
+/// A global imported from another module than [`EXTERN_IMPORT_MODULE`] is named `{module}.{name}`,
+/// so that the imports of different modules don't collide.
+fn imported_global_name(import: &wasmparser::Import<'_>) -> UasmVarName {
+    if import.module == EXTERN_IMPORT_MODULE {
+        public_var_name(import.name)
+    } else {
+        public_var_name(&format!("{}.{}", import.module, import.name))
+    }
+}
+
+/// Get the name of an Udon variable visible from outside of the program, mangled if needed.
+fn public_var_name(name: &str) -> UasmVarName {
+    UasmVarName::new(mangle_str(name, ManglingRule::Identifier).into())
+}
+
+/// Get the name and the attribute of the variable bound to an exported global.
+///
+/// A global exported as `sync.{mode}.{name}` is synced over the network.
+fn exported_global(name: &str) -> anyhow::Result<(UasmVarName, UasmDataAttribute)> {
+    let Some(synced) = name.strip_prefix("sync.") else {
+        return Ok((public_var_name(name), UasmDataAttribute::export()));
+    };
+
+    let (mode, name) = synced
+        .split_once('.')
+        .ok_or_else(|| anyhow::anyhow!("Missing sync mode: {:?}", name))?;
+
+    Ok((
+        public_var_name(name),
+        UasmDataAttribute {
+            exported: true,
+            sync: Some(UasmDataAttributeSync::try_from(mode)?),
+        },
+    ))
+}
+
+/// Whether an exported function is exposed as an Udon event (e.g. `_start` or `_interact`).
+///
+/// The names starting with `__` are left out, as they are used by toolchains (e.g. `__wasm_call_ctors`).
+/// The other functions are exposed through the metadata (see [`Metadata`]).
+fn is_event_name(name: &str) -> bool {
+    name.starts_with('_') && !name.starts_with("__")
+}
+
+fn interpret_export_section(
+    export_section: &wasmparser::SectionLimited<'_, wasmparser::Export>,
+    globals: &GlobalIndexSpace,
+    functions: &FunctionIndexSpace,
+) -> anyhow::Result<Uasm> {
+    use wasmparser::ExternalKind;
+
+    let mut data_section = UasmDataSection::new();
+    let mut events = Vec::new();
+
+    for export in export_section.clone() {
+        let export =
+            export.map_err(|err| anyhow::anyhow!("Failed to parse export section: {:?}", err))?;
+
+        match export.kind {
+            ExternalKind::Global => {
+                // an imported global exported under another name is renamed
+                let global = globals.get(export.index)?;
+                let (name, attribute) = exported_global(export.name)?;
+                data_section.push_alias(UasmAlias {
+                    internal: global.name.clone(),
+                    exported: name,
+                    attribute,
+                    ty: None,
+                });
+            }
+            ExternalKind::Func if is_event_name(export.name) => {
+                events.push(functions.event(export.name, export.index)?)
+            }
+            ExternalKind::Func => log::info!(
+                "Function {} is not exposed as an Udon event, as its name doesn't start with `_`",
+                export.name
+            ),
+            // The other exports are not visible from Udon.
+            _ => {}
+        }
+    }
+
+    let code_section = if events.is_empty() {
+        None
+    } else {
+        Some(UasmCodeSection::new(UasmCode::from_blocks(events)?))
+    };
+
+    Ok(Uasm::new(Some(data_section), code_section))
+}
+
+/// Bind the globals and the functions described by the metadata to Udon variables and events.
+fn interpret_metadata_section(
+    metadata: &Metadata,
+    globals: &GlobalIndexSpace,
+    functions: &FunctionIndexSpace,
+) -> anyhow::Result<Uasm> {
+    let mut data_section = UasmDataSection::new();
+
+    for global_metadata in metadata.globals.iter() {
+        let global = globals.get(global_metadata.index)?;
+
+        if global_metadata.ty.is_some() && global.ty != UasmType::Object {
+            anyhow::bail!(
+                "Cannot set the type of a global which is not an externref: {}",
+                global_metadata.index
+            )
+        }
+
+        let exported = match &global_metadata.name {
+            Some(name) => public_var_name(name),
+            None => global.name.clone(),
+        };
+
+        let attribute = UasmDataAttribute {
+            exported: global_metadata.name.is_some(),
+            sync: global_metadata.sync.clone(),
+        };
+
+        data_section.push_alias(UasmAlias {
+            internal: global.name.clone(),
+            exported,
+            attribute,
+            ty: global_metadata.ty.clone(),
+        });
+    }
+
+    let events = metadata
+        .events
+        .iter()
+        .map(|event| functions.event(&event.name, event.function))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let code_section = if events.is_empty() {
+        None
+    } else {
+        Some(UasmCodeSection::new(UasmCode::from_blocks(events)?))
+    };
+
+    Ok(Uasm::new(Some(data_section), code_section))
+}
+
+/// Declare the variables holding the return addresses and the results of the functions defined in this section.
+fn interpret_function_section(
+    function_section: &wasmparser::SectionLimited<'_, u32>,
+    functions: &FunctionIndexSpace,
+) -> anyhow::Result<Uasm> {
+    // the functions of this section come after the imported ones
+    let offset = functions.imported_count();
+    let count = function_section.clone().into_iter().count();
+
+    let mut data_section = UasmDataSection::new();
+
+    for function_index in (offset..offset + count).map(|index| index as u32) {
+        data_section.push_data(&UasmData {
+            attribute: UasmDataAttribute::default(),
+            variable: UasmVariable::new(
+                return_address_var(function_index),
+                UasmType::from_udon_name("SystemUInt32"),
+            ),
+        });
+
+        for (result_index, ty) in functions
+            .get(function_index)?
+            .ty
+            .results()
+            .iter()
+            .enumerate()
+        {
+            data_section.push_data(&UasmData {
+                attribute: UasmDataAttribute::default(),
+                variable: UasmVariable::new(
+                    result_var(function_index, result_index),
+                    UasmType::try_from(*ty)?,
+                ),
+            });
+        }
+    }
+
+    Ok(Uasm::new(Some(data_section), None))
+}
+
+fn interpret_import_section(
+    import_section: &wasmparser::SectionLimited<'_, wasmparser::Import>,
+) -> anyhow::Result<Uasm> {
+    use wasmparser::TypeRef;
+
+    let mut data_section = UasmDataSection::new();
+
+    for import in import_section.clone() {
+        let import =
+            import.map_err(|err| anyhow::anyhow!("Failed to parse import section: {:?}", err))?;
+
+        // Functions are lowered at each call and the other imports are not supported yet.
+        let TypeRef::Global(global_type) = import.ty else {
+            continue;
+        };
+
+        let uasm_data = UasmData {
+            attribute: UasmDataAttribute::export(),
+            variable: UasmVariable::new(
+                imported_global_name(&import),
+                UasmType::try_from(global_type.content_type)?,
+            ),
+        };
+
+        data_section.push_data(&uasm_data);
+    }
+
+    Ok(Uasm::new(Some(data_section), None))
+}
+
 fn interpret_global_section(
     global_section: &wasmparser::SectionLimited<'_, wasmparser::Global>,
+    globals: &GlobalIndexSpace,
+    functions: &FunctionIndexSpace,
 ) -> anyhow::Result<Uasm> {
-    let mut data_section = UasmDataSection::new();
+    let mut code = CodeBuilder::new("G", init_label("globals"));
+    code.declare(
+        &init_return_var("globals"),
+        UasmType::UInt32,
+        UasmValue::Null,
+    );
 
-    let mut code = UasmCode::new();
+    // the globals of this section come after the imported ones
+    let offset = globals.imported_count();
+    let mut initialized = false;
 
     for (index, global) in global_section.clone().into_iter().enumerate() {
         if global.is_err() {
@@ -72,65 +719,344 @@ fn interpret_global_section(
 
         let global = global.unwrap();
 
+        let global_index = offset + index;
+
         let var_info = VarInfo::Global {
-            global_index: index,
+            global_index,
+            name: None,
         };
 
-        let var_name = generate_variable_name(var_info).into();
+        let var_name = UasmVarName::new(generate_variable_name(var_info).into());
+
+        let var_type = UasmType::try_from(global.ty.content_type)?;
+
+        // a value known at translate time is the initial value of the variable, and needs no code
+        match globals.get(global_index as u32)?.value {
+            Some(value) => code.declare(&var_name, var_type, value.into()),
+            None => {
+                code.declare(&var_name, var_type, UasmValue::Null);
+                global_initializer(&mut code, &global.init_expr, &var_name, globals, functions)?;
+                initialized = true;
+            }
+        }
+    }
+
+    code.push([jump_indirect(&init_return_var("globals"))]);
 
-        let global_type = global.ty;
+    let mut uasm = code.finish()?;
+    if !initialized {
+        uasm.code_section = None;
+    }
 
-        let global_init = global.init_expr; // TODO: implement global initializer
-        let mut operators = ParsedData::from(global_init.get_operators_reader().into_iter());
+    Ok(uasm)
+}
 
-        let initializer = operators.interpret_all()?;
+/// Lower the initializer of a global whose value is only known at run time,
+/// as it reads an imported global or takes the address of a function.
+///
+/// It runs at startup, in the order of the global index space (see [`Startup`]).
+fn global_initializer(
+    code: &mut CodeBuilder,
+    expr: &wasmparser::ConstExpr<'_>,
+    var_name: &UasmVarName,
+    globals: &GlobalIndexSpace,
+    functions: &FunctionIndexSpace,
+) -> anyhow::Result<()> {
+    use wasmparser::Operator;
 
-        code.set_block_with_label(
-            UasmCodeLabel::new(format!("__INIT_{var_name}").into()),
-            initializer.into(),
-        );
+    let mut stack: Vec<(UasmVarName, UasmType)> = Vec::new();
 
-        let var_name = UasmVarName::new(var_name);
+    for operator in expr.get_operators_reader() {
+        let operator = operator
+            .map_err(|err| anyhow::anyhow!("Failed to parse constant expression: {:?}", err))?;
 
-        let var_type = UasmType::try_from(global_type.content_type)?;
+        let value = match operator {
+            Operator::I32Const { value } => {
+                let ty = UasmType::Int32;
+                (code.constant(ty.clone(), UasmValue::Int(value.into())), ty)
+            }
+            Operator::I64Const { value } => {
+                let ty = UasmType::Int64;
+                (code.constant(ty.clone(), UasmValue::Int(value)), ty)
+            }
+            Operator::F32Const { value } => {
+                let ty = UasmType::Single;
+                let value = UasmValue::Float(f32::from_bits(value.bits()));
+                (code.constant(ty.clone(), value), ty)
+            }
+            Operator::F64Const { value } => {
+                let ty = UasmType::Double;
+                let value = UasmValue::Double(f64::from_bits(value.bits()));
+                (code.constant(ty.clone(), value), ty)
+            }
+            Operator::RefNull { .. } => {
+                let ty = UasmType::Object;
+                (code.constant(ty.clone(), UasmValue::Null), ty)
+            }
+            Operator::GlobalGet { global_index } => {
+                let global = globals.get(global_index)?;
+                (global.name.clone(), global.ty.clone())
+            }
+            // a function reference is the address of the function
+            Operator::RefFunc { function_index } => {
+                if (function_index as usize) < functions.imported_count() {
+                    anyhow::bail!(
+                        "Unsupported reference to the imported function {}",
+                        function_index
+                    )
+                }
 
-        let uasm_data = UasmData {
-            attribute: UasmDataAttribute::None,
-            variable: UasmVariable::new(var_name, var_type),
+                let ty = UasmType::UInt32;
+                let address = UasmValue::Address(function_label(function_index));
+                (code.constant(ty.clone(), address), ty)
+            }
+            Operator::I32Add
+            | Operator::I32Sub
+            | Operator::I32Mul
+            | Operator::I64Add
+            | Operator::I64Sub
+            | Operator::I64Mul => {
+                let op = match operator {
+                    Operator::I32Add | Operator::I64Add => "Addition",
+                    Operator::I32Sub | Operator::I64Sub => "Subtraction",
+                    _ => "Multiplication",
+                };
+
+                let (rhs, ty) = stack
+                    .pop()
+                    .ok_or_else(|| anyhow::anyhow!("Stack underflow in constant expression"))?;
+                let (lhs, _) = stack
+                    .pop()
+                    .ok_or_else(|| anyhow::anyhow!("Stack underflow in constant expression"))?;
+
+                let result = code.temp(ty.clone());
+                code.call(&externs::binary(&ty, op), None, &[lhs, rhs], Some(&result))?;
+
+                (result, ty)
+            }
+            Operator::End => break,
+            operator => anyhow::bail!("Unsupported constant expression: {:?}", operator),
         };
 
-        data_section.push_data(&uasm_data);
+        stack.push(value);
     }
 
-    Ok(Uasm::new(Some(data_section), None))
+    match stack.as_slice() {
+        [(value, _)] => {
+            code.push(copy(value, var_name));
+            Ok(())
+        }
+        _ => anyhow::bail!("Invalid constant expression leaving {} values", stack.len()),
+    }
 }
 
-impl<'a> From<wasmparser::OperatorsIterator<'a>> for ParsedData<wasmparser::Operator<'a>> {
-    fn from(value: wasmparser::OperatorsIterator<'a>) -> Self {
-        let mut value_iter = value.into_iter();
+/// Lower the body of a function defined in the module.
+fn interpret_code_section_entry(
+    context: &ModuleContext<'_>,
+    body: &wasmparser::FunctionBody<'_>,
+) -> anyhow::Result<Uasm> {
+    lower_function(
+        FunctionContext {
+            function_index: context.function_index(body)?,
+            functions: &context.functions,
+            globals: &context.globals,
+            memory: context.memory.as_ref(),
+            extern_db: context.extern_db,
+        },
+        body,
+    )
+}
 
-        // FIXME: handle error
-        let mut parsed_data = ParsedData::new(value_iter.next().unwrap().unwrap());
+/// What the translation of every section knows of the whole module, collected once.
+#[derive(Debug)]
+pub struct ModuleContext<'a> {
+    pub functions: FunctionIndexSpace,
+    pub globals: GlobalIndexSpace,
+    pub memory: Option<LinearMemory>,
+    /// the index of each function defined in the module, by the offset of its body
+    bodies: HashMap<usize, u32>,
+    /// the externs known to UdonVM, which tell how the imports are lowered if given
+    pub extern_db: Option<&'a ExternDatabase>,
+}
 
-        for value in value_iter {
-            let value = value.unwrap();
+impl<'a> ModuleContext<'a> {
+    /// Collect what is known of the whole module, which ends with `parsed`.
+    pub fn new(
+        parsed: &ParsedData<wasmparser::Payload<'_>>,
+        extern_db: Option<&'a ExternDatabase>,
+    ) -> anyhow::Result<ModuleContext<'a>> {
+        use wasmparser::Payload;
+
+        let functions = FunctionIndexSpace::new(parsed)?;
 
-            parsed_data = parsed_data.update(ParsedData::new(value));
+        // the bodies are linked from the last one to the first one
+        let mut offsets = Vec::new();
+        let mut current = Some(parsed);
+        while let Some(parsed) = current {
+            if let Payload::CodeSectionEntry(body) = parsed.get_data() {
+                offsets.push(body.range().start);
+            }
+            current = parsed.get_next();
         }
+        let bodies = offsets
+            .into_iter()
+            .rev()
+            .enumerate()
+            .map(|(index, offset)| (offset, (functions.imported_count() + index) as u32))
+            .collect();
 
-        parsed_data
+        Ok(ModuleContext {
+            functions,
+            globals: GlobalIndexSpace::new(parsed)?,
+            memory: LinearMemory::find(parsed)?,
+            bodies,
+            extern_db,
+        })
+    }
+
+    /// the index of the function defined by `body`
+    fn function_index(&self, body: &wasmparser::FunctionBody<'_>) -> anyhow::Result<u32> {
+        self.bodies
+            .get(&body.range().start)
+            .copied()
+            .ok_or_else(|| {
+                anyhow::anyhow!("Unknown function body at offset {}", body.range().start)
+            })
     }
 }
 
-impl InterpretableAs<Uasm> for ParsedData<wasmparser::Payload<'_>> {
-    fn interpret(&self) -> anyhow::Result<Uasm> {
+impl InterpretableAs<Uasm, ModuleContext<'_>> for ParsedData<wasmparser::Payload<'_>> {
+    fn interpret(&self, context: &ModuleContext<'_>) -> anyhow::Result<Uasm> {
         use wasmparser::Payload;
 
         match self.get_data() {
-            Payload::GlobalSection(global_section) => interpret_global_section(global_section),
-            x => {
-                unimplemented!("Unknown payload: {:?}", x)
+            Payload::ImportSection(import_section) => interpret_import_section(import_section),
+            Payload::GlobalSection(global_section) => {
+                interpret_global_section(global_section, &context.globals, &context.functions)
+            }
+            Payload::FunctionSection(function_section) => {
+                interpret_function_section(function_section, &context.functions)
+            }
+            Payload::MemorySection(_) => match &context.memory {
+                Some(memory) => memory.uasm(),
+                None => Ok(Uasm::default()),
+            },
+            Payload::ExportSection(export_section) => {
+                interpret_export_section(export_section, &context.globals, &context.functions)
             }
+            Payload::CodeSectionEntry(body) => interpret_code_section_entry(context, body),
+            Payload::DataSection(data_section) => context
+                .memory
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Data section without a memory"))?
+                .data_uasm(data_section, &context.globals),
+            Payload::CustomSection(section) if section.name() == METADATA_SECTION => {
+                interpret_metadata_section(
+                    &Metadata::parse(section.data())?,
+                    &context.globals,
+                    &context.functions,
+                )
+            }
+            Payload::End(_) => {
+                // the whole module is known at the end
+                check_recursion(self)?;
+                Startup::new(self, context)?.uasm()
+            }
+            // the other custom sections are not used
+            Payload::CustomSection(_) => Ok(Uasm::default()),
+            // the types are read by the sections using them, and the start function is called at startup
+            Payload::Version { .. }
+            | Payload::TypeSection(_)
+            | Payload::StartSection { .. }
+            | Payload::CodeSectionStart { .. }
+            | Payload::DataCountSection { .. } => Ok(Uasm::default()),
+            // the tables are only used by `call_indirect`, which is rejected
+            Payload::TableSection(_) | Payload::ElementSection(_) => Ok(Uasm::default()),
+            payload => anyhow::bail!("Unsupported section: {:?}", payload),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udon::uasm::linker::Linker;
+    use crate::wasm::parser::{WasmEntry, WasmParser};
+
+    #[test]
+    fn binds_the_imported_globals() {
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        // (import "udon" "config_speed" (global f32))
+        // (import "env" "speed" (global f32))
+        wasm.extend([0x02, 0x23, 0x02, 0x04]);
+        wasm.extend(b"udon");
+        wasm.push(0x0C);
+        wasm.extend(b"config_speed");
+        wasm.extend([0x03, 0x7D, 0x00, 0x03]);
+        wasm.extend(b"env");
+        wasm.push(0x05);
+        wasm.extend(b"speed");
+        wasm.extend([0x03, 0x7D, 0x00]);
+        // (export "speed" (global 0))
+        wasm.extend([0x07, 0x09, 0x01, 0x05]);
+        wasm.extend(b"speed");
+        wasm.extend([0x03, 0x00]);
+
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+        let context = ModuleContext::new(&parsed, None).unwrap();
+
+        let mut linker = Linker::new();
+        linker.add_units(parsed.interpret_all(&context).unwrap());
+        let mut uasm = linker.link().unwrap();
+        uasm.resolve_aliases().unwrap();
+
+        let exported = uasm
+            .data_section
+            .unwrap()
+            .get_data()
+            .iter()
+            .filter(|data| data.attribute.exported)
+            .map(|data| data.variable.name.to_string())
+            .collect::<Vec<_>>();
+        // the import of `udon` is renamed by its export
+        assert_eq!(exported, ["speed", "__M12_env_2e_speed"]);
+    }
+
+    #[test]
+    fn exposes_the_event_names_only() {
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        // (type (func (param i32))) (type (func))
+        wasm.extend([0x01, 0x08, 0x02, 0x60, 0x01, 0x7F, 0x00, 0x60, 0x00, 0x00]);
+        wasm.extend([0x03, 0x03, 0x02, 0x00, 0x01]);
+        // (export "hello" (func 0)) (export "_interact" (func 1))
+        wasm.extend([0x07, 0x15, 0x02, 0x05]);
+        wasm.extend(b"hello");
+        wasm.extend([0x00, 0x00, 0x09]);
+        wasm.extend(b"_interact");
+        wasm.extend([0x00, 0x01]);
+        wasm.extend([0x0A, 0x07, 0x02, 0x02, 0x00, 0x0B, 0x02, 0x00, 0x0B]);
+
+        let mut parser = WasmParser::from(WasmEntry::new(&wasm, 0));
+        let parsed = parser.parse_all().unwrap();
+        let context = ModuleContext::new(&parsed, None).unwrap();
+
+        let mut linker = Linker::new();
+        linker.add_units(parsed.interpret_all(&context).unwrap());
+        crate::core::runtime::helpers()
+            .unwrap()
+            .into_iter()
+            .for_each(|helper| linker.add_helper(helper));
+        let uasm = linker.link().unwrap();
+
+        let exported = uasm
+            .code_section
+            .unwrap()
+            .get_code()
+            .blocks()
+            .filter(|(_, block)| block.is_exported())
+            .map(|(label, _)| label.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(exported, ["_interact"]);
+    }
+}
@@ -0,0 +1,194 @@
+//! Generate the Unity assets wrapping a program as an `UdonAssemblyProgramAsset`.
+//!
+//! The `.asset` holds the Udon Assembly, which the VRChat SDK assembles when importing it,
+//! and the `.meta` gives it a GUID derived from the path of the asset in the Unity project,
+//! so that regenerating an asset keeps the references to it.
+
+use ::alloc::{format, string::String, vec::Vec};
+use ::core::fmt::Write;
+
+/// the GUID of the `UdonAssemblyProgramAsset` script in the VRChat SDK
+///
+/// It's the `guid` of `UdonAssemblyProgramAsset.cs.meta` in the `com.vrchat.worlds` package.
+/// When the installed SDK gives it another one, read it with [`read_meta_guid`].
+pub const UDON_ASSEMBLY_PROGRAM_ASSET_GUID: &str = "22203902d63dec94194fefc3e155c43b";
+
+/// the file ID of the main object of a `ScriptableObject` asset
+const MAIN_OBJECT_FILE_ID: u32 = 11400000;
+
+/// Generate the `.asset` named `name` holding `uasm`, which is an instance of the script of GUID `script_guid`
+/// (usually [`UDON_ASSEMBLY_PROGRAM_ASSET_GUID`]).
+pub fn generate_asset(name: &str, uasm: &str, script_guid: &str) -> String {
+    format!(
+        "%YAML 1.1
+%TAG !u! tag:unity3d.com,2011:
+--- !u!114 &{MAIN_OBJECT_FILE_ID}
+MonoBehaviour:
+  m_ObjectHideFlags: 0
+  m_CorrespondingSourceObject: {{fileID: 0}}
+  m_PrefabInstance: {{fileID: 0}}
+  m_PrefabAsset: {{fileID: 0}}
+  m_GameObject: {{fileID: 0}}
+  m_Enabled: 1
+  m_EditorHideFlags: 0
+  m_Script: {{fileID: 11500000, guid: {}, type: 3}}
+  m_Name: {}
+  m_EditorClassIdentifier:
+  serializedUdonProgramAsset: {{fileID: 0}}
+  udonAssembly: {}
+  assemblyError:
+",
+        script_guid,
+        yaml_string(name),
+        yaml_string(uasm)
+    )
+}
+
+/// Generate the `.meta` of the asset at `project_path` (see [`project_path`]).
+pub fn generate_meta(project_path: &str) -> String {
+    format!(
+        "fileFormatVersion: 2
+guid: {}
+NativeFormatImporter:
+  externalObjects: {{}}
+  mainObjectFileID: {MAIN_OBJECT_FILE_ID}
+  userData:
+  assetBundleName:
+  assetBundleVariant:
+",
+        asset_guid(project_path)
+    )
+}
+
+/// Get the path of the asset at `path` relative to its Unity project (e.g. `Assets/Udon/Program.asset`),
+/// with `/` as the separator.
+///
+/// It starts from the `Assets` or `Packages` folder, and is `None` when `path` is in neither.
+pub fn project_path(path: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    let segments = path.split('/').collect::<Vec<_>>();
+    let start = segments
+        .iter()
+        .position(|segment| *segment == "Assets" || *segment == "Packages")?;
+
+    Some(segments[start..].join("/"))
+}
+
+/// Derive the GUID of the asset at `project_path`, as 32 lowercase hexadecimal digits.
+pub fn asset_guid(project_path: &str) -> String {
+    // two FNV-1a hashes with different offset bases make the 128 bits
+    let hash = |offset_basis: u64| {
+        project_path.bytes().fold(offset_basis, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01B3)
+        })
+    };
+
+    format!(
+        "{:016x}{:016x}",
+        hash(0xCBF2_9CE4_8422_2325),
+        hash(0x6C62_272E_07BB_0142)
+    )
+}
+
+/// Read the GUID of the `.meta` file `meta`, such as the one of the `UdonAssemblyProgramAsset` script.
+pub fn read_meta_guid(meta: &str) -> anyhow::Result<&str> {
+    let guid = meta
+        .lines()
+        .find_map(|line| line.strip_prefix("guid:"))
+        .map(str::trim)
+        .ok_or_else(|| anyhow::anyhow!("No GUID in the meta file"))?;
+
+    if guid.len() != 32 || !guid.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        anyhow::bail!("Malformed GUID in the meta file: {:?}", guid)
+    }
+
+    Ok(guid)
+}
+
+/// Quote `value` as a YAML double-quoted scalar on a single line.
+fn yaml_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                write!(quoted, "\\u{:04X}", c as u32).expect("writing into a String doesn't fail")
+            }
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_the_guid_from_the_project_path() {
+        let path = |path: &str| project_path(path).unwrap();
+
+        assert_eq!(
+            path("/home/user/World/Assets/Udon/Program.asset"),
+            "Assets/Udon/Program.asset"
+        );
+        assert_eq!(
+            path("C:\\World\\Assets\\Program.asset"),
+            "Assets/Program.asset"
+        );
+        assert_eq!(
+            path("Packages/com.example/Program.asset"),
+            "Packages/com.example/Program.asset"
+        );
+        assert_eq!(project_path("/tmp/Program.asset"), None);
+
+        let guid = asset_guid("Assets/Udon/Program.asset");
+        assert_eq!(guid.len(), 32);
+        assert!(guid.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_eq!(
+            guid,
+            asset_guid(&path("/other/World/Assets/Udon/Program.asset"))
+        );
+        // assets of the same name in different folders don't collide
+        assert_ne!(guid, asset_guid("Assets/Other/Program.asset"));
+    }
+
+    #[test]
+    fn reads_the_guid_of_a_script() {
+        let meta = "fileFormatVersion: 2\nguid: 22203902d63dec94194fefc3e155c43b\nMonoImporter:\n";
+        assert_eq!(
+            read_meta_guid(meta).unwrap(),
+            UDON_ASSEMBLY_PROGRAM_ASSET_GUID
+        );
+        assert!(read_meta_guid("fileFormatVersion: 2\n").is_err());
+        assert!(read_meta_guid("guid: 1234\n").is_err());
+    }
+
+    #[test]
+    fn generates_the_asset_and_its_meta() {
+        let asset = generate_asset(
+            "Program",
+            ".code_start\n  \"x\"\n",
+            UDON_ASSEMBLY_PROGRAM_ASSET_GUID,
+        );
+        assert!(asset.contains(
+            "m_Script: {fileID: 11500000, guid: 22203902d63dec94194fefc3e155c43b, type: 3}"
+        ));
+        assert!(asset.contains("m_Name: \"Program\"\n"));
+        assert!(asset.contains("udonAssembly: \".code_start\\n  \\\"x\\\"\\n\"\n"));
+
+        let meta = generate_meta("Assets/Program.asset");
+        assert_eq!(
+            read_meta_guid(&meta).unwrap(),
+            asset_guid("Assets/Program.asset")
+        );
+        assert!(meta.contains("mainObjectFileID: 11400000\n"));
+    }
+}
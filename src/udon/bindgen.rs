@@ -0,0 +1,477 @@
+//! Generate guest bindings for the externs in an [`ExternDatabase`].
+//!
+//! Every exposed extern becomes a function imported from [`EXTERN_IMPORT_MODULE`]
+//! whose import name is the full extern name, so that the translator can lower calls to it directly.
+//! The intrinsics of [`INTRINSIC_IMPORT_MODULE`] are bound as well.
+//! The externs taking or returning a value type (see [`UasmType::is_value_type`]) are skipped.
+
+use ::alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use ::core::fmt::{self, Write};
+use hashbrown::HashMap;
+
+use crate::core::intrinsic::INTRINSIC_IMPORT_MODULE;
+use crate::udon::extern_db::{ExternDatabase, ExternEntry};
+use crate::udon::uasm::data::UasmType;
+use crate::udon::EXTERN_IMPORT_MODULE;
+
+/// A binding to be generated for an extern.
+struct Binding<'a> {
+    entry: &'a ExternEntry,
+    /// the module of the safe wrapper (e.g. `unity_engine_debug`)
+    module: String,
+    /// the name of the safe wrapper (e.g. `log`)
+    function: String,
+    /// the name of the raw import (e.g. `unity_engine_debug_log`)
+    raw: String,
+}
+
+/// A value crossing the boundary, as seen by the raw import, the C header and the safe wrapper.
+struct Value {
+    /// the parameters of the raw import, with their Rust and C types
+    raw: Vec<(String, &'static str, &'static str)>,
+    /// the parameter of the safe wrapper
+    wrapper: String,
+    /// the arguments passed to the raw import by the safe wrapper
+    args: String,
+}
+
+impl Value {
+    fn param(name: &str, ty: &UasmType) -> Value {
+        match ty {
+            // see `docs/string.md`
+            UasmType::String => Value {
+                raw: vec![
+                    (format!("{name}_ptr"), "*const u8", "const char *"),
+                    (format!("{name}_len"), "usize", "size_t"),
+                ],
+                wrapper: format!("{name}: &str"),
+                args: format!("{name}.as_ptr(), {name}.len()"),
+            },
+            ty => Value {
+                raw: vec![(name.to_string(), rust_type(ty), c_type(ty))],
+                wrapper: format!("{}: {}", name, rust_type(ty)),
+                args: name.to_string(),
+            },
+        }
+    }
+
+    /// the buffer receiving a string result
+    fn string_buffer() -> Value {
+        Value {
+            raw: vec![
+                ("out_ptr".to_string(), "*mut u8", "char *"),
+                ("out_cap".to_string(), "usize", "size_t"),
+            ],
+            wrapper: "out: &mut [u8]".to_string(),
+            args: "out.as_mut_ptr(), out.len()".to_string(),
+        }
+    }
+}
+
+impl Binding<'_> {
+    /// the parameters, starting with the instance if any
+    fn params(&self) -> Vec<Value> {
+        let signature = &self.entry.signature;
+
+        let mut params = self
+            .entry
+            .instance
+            .then(|| Value::param("this", &UasmType::from_udon_name(&signature.class)))
+            .into_iter()
+            .chain(
+                signature
+                    .params
+                    .iter()
+                    .enumerate()
+                    .map(|(index, ty)| Value::param(&format!("arg{index}"), ty)),
+            )
+            .collect::<Vec<_>>();
+
+        if signature.ret == Some(UasmType::String) {
+            params.push(Value::string_buffer());
+        }
+
+        params
+    }
+
+    /// the Rust and C types of the result, if any
+    fn ret(&self) -> Option<(&'static str, &'static str)> {
+        match self.entry.signature.ret.as_ref()? {
+            // the whole length of the string in bytes
+            UasmType::String => Some(("usize", "size_t")),
+            ty => Some((rust_type(ty), c_type(ty))),
+        }
+    }
+}
+
+/// Whether a value of every type of the extern can cross the boundary of the guest,
+/// which isn't the case of the value types such as `UnityEngineVector3`.
+fn is_bindable(entry: &ExternEntry) -> bool {
+    let signature = &entry.signature;
+    let instance = entry
+        .instance
+        .then(|| UasmType::from_udon_name(&signature.class));
+
+    !instance
+        .iter()
+        .chain(signature.params.iter())
+        .chain(signature.ret.iter())
+        .any(UasmType::is_value_type)
+}
+
+fn collect_bindings(db: &ExternDatabase) -> Vec<Binding<'_>> {
+    let mut entries = db
+        .iter()
+        .filter(|entry| entry.exposed && is_bindable(entry))
+        .collect::<Vec<_>>();
+    entries.sort_by_cached_key(|entry| entry.signature.to_string());
+
+    let mut overloads = HashMap::<(&str, &str), usize>::new();
+    for entry in entries.iter() {
+        let key = (
+            entry.signature.class.as_str(),
+            entry.signature.method.as_str(),
+        );
+        *overloads.entry(key).or_default() += 1;
+    }
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let signature = &entry.signature;
+            let module = snake_case(&signature.class);
+
+            let mut function = match signature.method.as_str() {
+                "ctor" => "new".to_string(),
+                method => snake_case(method),
+            };
+
+            if overloads[&(signature.class.as_str(), signature.method.as_str())] > 1 {
+                for param in signature.params.iter() {
+                    function.push('_');
+                    function.push_str(&snake_case(&param.udon_name()));
+                }
+            }
+
+            let raw = format!("{module}_{function}");
+
+            Binding {
+                entry,
+                module,
+                function,
+                raw,
+            }
+        })
+        .collect()
+}
+
+/// Generate the `Cargo.toml` of the binding crate.
+pub fn generate_cargo_toml(crate_name: &str) -> String {
+    format!(
+        r#"[package]
+name = "{crate_name}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+"#
+    )
+}
+
+/// Generate the `src/lib.rs` of the binding crate.
+pub fn generate_rust(db: &ExternDatabase) -> String {
+    let mut out = String::new();
+    write_rust(&mut out, &collect_bindings(db)).expect("writing to a String never fails");
+
+    out
+}
+
+/// Generate a C header declaring the same imports as [`generate_rust`].
+pub fn generate_c_header(db: &ExternDatabase) -> String {
+    let mut out = String::new();
+    write_c_header(&mut out, &collect_bindings(db)).expect("writing to a String never fails");
+
+    out
+}
+
+fn write_rust(out: &mut impl Write, bindings: &[Binding<'_>]) -> fmt::Result {
+    writeln!(
+        out,
+        "//! Bindings to Udon externs. Generated by wasdon-bindgen, do not edit."
+    )?;
+    writeln!(out)?;
+    writeln!(out, "#![no_std]")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/// A handle to an Udon object owned by the translator."
+    )?;
+    writeln!(out, "#[repr(transparent)]")?;
+    writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq)]")?;
+    writeln!(out, "pub struct Handle(pub i32);")?;
+    writeln!(out)?;
+    writeln!(out, "pub mod intrinsics {{")?;
+    writeln!(
+        out,
+        "    #[link(wasm_import_module = {:?})]",
+        INTRINSIC_IMPORT_MODULE
+    )?;
+    writeln!(out, "    extern \"C\" {{")?;
+    writeln!(out, "        #[link_name = \"request_serialization\"]")?;
+    writeln!(out, "        fn raw_request_serialization();")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    /// Send the synced variables of this behaviour to the other players."
+    )?;
+    writeln!(out, "    #[inline]")?;
+    writeln!(out, "    pub fn request_serialization() {{")?;
+    writeln!(out, "        unsafe {{ raw_request_serialization() }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "pub mod raw {{")?;
+    writeln!(out, "    #[allow(unused_imports)]")?;
+    writeln!(out, "    use super::Handle;")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    #[link(wasm_import_module = {:?})]",
+        EXTERN_IMPORT_MODULE
+    )?;
+    writeln!(out, "    extern \"C\" {{")?;
+    for binding in bindings.iter() {
+        let params = binding
+            .params()
+            .into_iter()
+            .flat_map(|value| value.raw)
+            .map(|(name, ty, _)| format!("{name}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        writeln!(
+            out,
+            "        #[link_name = \"{}\"]",
+            binding.entry.signature
+        )?;
+        write!(out, "        pub fn {}({})", binding.raw, params)?;
+        write_rust_ret(out, binding)?;
+        writeln!(out, ";")?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    let mut current_module = None;
+    for binding in bindings.iter() {
+        if current_module != Some(&binding.module) {
+            if current_module.is_some() {
+                writeln!(out, "}}")?;
+            }
+            writeln!(out)?;
+            writeln!(out, "pub mod {} {{", escape_keyword(&binding.module))?;
+            writeln!(out, "    #[allow(unused_imports)]")?;
+            writeln!(out, "    use super::Handle;")?;
+            current_module = Some(&binding.module);
+        }
+
+        let (params, args): (Vec<_>, Vec<_>) = binding
+            .params()
+            .into_iter()
+            .map(|value| (value.wrapper, value.args))
+            .unzip();
+
+        writeln!(out)?;
+        writeln!(out, "    /// `{}`", binding.entry.signature)?;
+        writeln!(out, "    #[inline]")?;
+        write!(
+            out,
+            "    pub fn {}({})",
+            escape_keyword(&binding.function),
+            params.join(", ")
+        )?;
+        write_rust_ret(out, binding)?;
+        writeln!(out, " {{")?;
+        writeln!(
+            out,
+            "        unsafe {{ super::raw::{}({}) }}",
+            binding.raw,
+            args.join(", ")
+        )?;
+        writeln!(out, "    }}")?;
+    }
+    if current_module.is_some() {
+        writeln!(out, "}}")?;
+    }
+
+    Ok(())
+}
+
+fn write_rust_ret(out: &mut impl Write, binding: &Binding<'_>) -> fmt::Result {
+    match binding.ret() {
+        Some((ret, _)) => write!(out, " -> {}", ret),
+        None => Ok(()),
+    }
+}
+
+fn write_c_header(out: &mut impl Write, bindings: &[Binding<'_>]) -> fmt::Result {
+    writeln!(
+        out,
+        "/* Bindings to Udon externs. Generated by wasdon-bindgen, do not edit. */"
+    )?;
+    writeln!(out)?;
+    writeln!(out, "#pragma once")?;
+    writeln!(out)?;
+    writeln!(out, "#include <stdbool.h>")?;
+    writeln!(out, "#include <stddef.h>")?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/* A handle to an Udon object owned by the translator. */"
+    )?;
+    writeln!(out, "typedef int32_t udon_handle;")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/* Send the synced variables of this behaviour to the other players. */"
+    )?;
+    writeln!(
+        out,
+        "__attribute__((import_module({:?}), import_name(\"request_serialization\")))",
+        INTRINSIC_IMPORT_MODULE
+    )?;
+    writeln!(out, "void udon_request_serialization(void);")?;
+
+    for binding in bindings.iter() {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "__attribute__((import_module({:?}), import_name(\"{}\")))",
+            EXTERN_IMPORT_MODULE, binding.entry.signature
+        )?;
+        let ret = binding.ret().map_or("void", |(_, ret)| ret);
+        let params = binding
+            .params()
+            .into_iter()
+            .flat_map(|value| value.raw)
+            .map(|(name, _, ty)| match ty.strip_suffix('*') {
+                Some(pointer) => format!("{pointer}*{name}"),
+                None => format!("{ty} {name}"),
+            })
+            .collect::<Vec<_>>();
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        writeln!(out, "{} {}({});", ret, binding.raw, params)?;
+    }
+
+    Ok(())
+}
+
+fn rust_type(ty: &UasmType) -> &'static str {
+    match ty {
+        UasmType::Boolean => "bool",
+        UasmType::Byte => "u8",
+        UasmType::SByte => "i8",
+        UasmType::Char | UasmType::UInt16 => "u16",
+        UasmType::Int16 => "i16",
+        UasmType::Int32 => "i32",
+        UasmType::UInt32 => "u32",
+        UasmType::Int64 => "i64",
+        UasmType::UInt64 => "u64",
+        UasmType::Single => "f32",
+        UasmType::Double => "f64",
+        // strings are lowered by `Value::param`
+        _ => "Handle",
+    }
+}
+
+fn c_type(ty: &UasmType) -> &'static str {
+    match ty {
+        UasmType::Boolean => "bool",
+        UasmType::Byte => "uint8_t",
+        UasmType::SByte => "int8_t",
+        UasmType::Char | UasmType::UInt16 => "uint16_t",
+        UasmType::Int16 => "int16_t",
+        UasmType::Int32 => "int32_t",
+        UasmType::UInt32 => "uint32_t",
+        UasmType::Int64 => "int64_t",
+        UasmType::UInt64 => "uint64_t",
+        UasmType::Single => "float",
+        UasmType::Double => "double",
+        _ => "udon_handle",
+    }
+}
+
+/// Convert an Udon name like `VRCPlayerApi` or `get_deltaTime` into snake case.
+fn snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut snake = String::new();
+
+    for (index, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && index > 0 {
+            let prev = chars[index - 1];
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next_is_lower)
+            {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+
+    snake
+}
+
+fn escape_keyword(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+        "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override",
+        "priv", "typeof", "unsized", "virtual", "yield", "try",
+    ];
+
+    if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `bindgen/externs.tsv` covers overloads, strings, instances, constructors,
+    /// a value type and a hidden extern, which are both skipped
+    fn extern_db() -> ExternDatabase {
+        ExternDatabase::from_tsv(include_str!("../../bindgen/externs.tsv")).unwrap()
+    }
+
+    #[test]
+    fn generates_the_rust_bindings() {
+        assert_eq!(
+            generate_rust(&extern_db()),
+            include_str!("../../bindgen/udon_sys.rs")
+        );
+    }
+
+    #[test]
+    fn generates_the_c_header() {
+        assert_eq!(
+            generate_c_header(&extern_db()),
+            include_str!("../../bindgen/udon.h")
+        );
+    }
+}
@@ -0,0 +1,535 @@
+use ::alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use hashbrown::HashMap;
+
+use crate::core::extern_abi::ImportAbi;
+use crate::core::intrinsic::{Intrinsic, INTRINSIC_IMPORT_MODULE};
+use crate::core::ParsedData;
+use crate::udon::uasm::data::UasmOpcode;
+use crate::udon::uasm::{ExternSignature, Uasm};
+use crate::udon::EXTERN_IMPORT_MODULE;
+
+/// An extern registered in the Udon node registry.
+#[derive(Debug, Clone)]
+pub struct ExternEntry {
+    pub signature: ExternSignature,
+    /// whether the extern takes an instance before its arguments
+    pub instance: bool,
+    /// whether the extern can be used in VRChat worlds
+    pub exposed: bool,
+}
+
+/// The database of the externs known to UdonVM.
+///
+/// It is loaded from a dump of the Udon node registry exported from the Unity editor,
+/// either as TSV (see [`ExternDatabase::from_tsv`]) or as JSON (see [`ExternDatabase::from_json`]).
+/// `tools/ExportUdonExterns.cs` exports the JSON from a VRChat world project.
+#[derive(Debug, Default)]
+pub struct ExternDatabase {
+    entries: HashMap<String, ExternEntry>,
+}
+
+impl ExternDatabase {
+    pub fn new() -> ExternDatabase {
+        ExternDatabase::default()
+    }
+
+    pub fn insert(&mut self, entry: ExternEntry) {
+        self.entries.insert(entry.signature.to_string(), entry);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ExternEntry> {
+        self.entries.get(name)
+    }
+
+    /// iterate over the externs in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &ExternEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Load the database from `src`, choosing JSON or TSV by the extension of `file_name`.
+    pub fn from_file(file_name: &str, src: &str) -> anyhow::Result<ExternDatabase> {
+        if file_name.ends_with(".json") {
+            ExternDatabase::from_json(src)
+        } else {
+            ExternDatabase::from_tsv(src)
+        }
+    }
+
+    /// Load the database from tab-separated values.
+    ///
+    /// Each line has the columns `fullName`, `static|instance` and `exposed|hidden`.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn from_tsv(src: &str) -> anyhow::Result<ExternDatabase> {
+        let mut db = ExternDatabase::new();
+
+        for (line_index, line) in src.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let columns = line.split('\t').collect::<Vec<_>>();
+            let [name, kind, exposure] = columns.as_slice() else {
+                anyhow::bail!(
+                    "Expected 3 columns at line {}, got {}",
+                    line_index + 1,
+                    columns.len()
+                )
+            };
+
+            let instance = match *kind {
+                "static" => false,
+                "instance" => true,
+                x => anyhow::bail!("Unknown extern kind at line {}: {:?}", line_index + 1, x),
+            };
+
+            let exposed = match *exposure {
+                "exposed" => true,
+                "hidden" => false,
+                x => anyhow::bail!("Unknown exposure at line {}: {:?}", line_index + 1, x),
+            };
+
+            db.insert(ExternEntry {
+                signature: ExternSignature::parse(name)?,
+                instance,
+                exposed,
+            });
+        }
+
+        Ok(db)
+    }
+
+    /// Load the database from JSON.
+    ///
+    /// The root is an array of objects like
+    /// `{ "fullName": "UnityEngineDebug.__Log__SystemObject__SystemVoid", "instance": false, "exposed": true }`.
+    pub fn from_json(src: &str) -> anyhow::Result<ExternDatabase> {
+        use serde_json::Value;
+
+        let root: Value = serde_json::from_str(src)
+            .map_err(|err| anyhow::anyhow!("Failed to parse extern database: {}", err))?;
+
+        let Value::Array(nodes) = root else {
+            anyhow::bail!("Expected an array of externs")
+        };
+
+        let mut db = ExternDatabase::new();
+
+        for node in nodes.iter() {
+            let name = node
+                .get("fullName")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("Missing `fullName` in extern: {}", node))?;
+
+            let flag = |key: &str| {
+                node.get(key)
+                    .and_then(Value::as_bool)
+                    .ok_or_else(|| anyhow::anyhow!("Missing `{}` in extern: {}", key, name))
+            };
+
+            db.insert(ExternEntry {
+                signature: ExternSignature::parse(name)?,
+                instance: flag("instance")?,
+                exposed: flag("exposed")?,
+            });
+        }
+
+        Ok(db)
+    }
+
+    /// Check that `signature` names an extern usable in VRChat worlds.
+    pub fn validate(&self, signature: &ExternSignature) -> anyhow::Result<&ExternEntry> {
+        let name = signature.to_string();
+
+        if let Some(entry) = self.entries.get(&name) {
+            if !entry.exposed {
+                anyhow::bail!("Extern is not exposed to VRChat worlds: {}", name)
+            }
+
+            return Ok(entry);
+        }
+
+        let overloads = self
+            .entries
+            .values()
+            .filter(|entry| {
+                entry.signature.class == signature.class
+                    && entry.signature.method == signature.method
+            })
+            .collect::<Vec<_>>();
+
+        if overloads.is_empty() {
+            anyhow::bail!("Unknown extern: {}", name)
+        }
+
+        let candidates = overloads
+            .iter()
+            .map(|entry| entry.signature.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if overloads
+            .iter()
+            .all(|entry| entry.signature.params.len() != signature.params.len())
+        {
+            anyhow::bail!(
+                "Wrong number of parameters for extern: {} (candidates: {})",
+                name,
+                candidates
+            )
+        }
+
+        anyhow::bail!(
+            "Wrong parameter or result types for extern: {} (candidates: {})",
+            name,
+            candidates
+        )
+    }
+
+    /// Check every `EXTERN` instruction in `uasm`.
+    pub fn validate_uasm(&self, uasm: &Uasm) -> anyhow::Result<()> {
+        let Some(code_section) = &uasm.code_section else {
+            return Ok(());
+        };
+
+        let errors = code_section
+            .get_code()
+            .blocks()
+            .flat_map(|(label, block)| {
+                block
+                    .get_instructions()
+                    .iter()
+                    .filter_map(move |instruction| match &instruction.opcode {
+                        UasmOpcode::Extern(signature) => self
+                            .validate(signature)
+                            .err()
+                            .map(|err| format!("{}: {}", label, err)),
+                        _ => None,
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        report(errors)
+    }
+
+    /// Check a function imported from [`EXTERN_IMPORT_MODULE`] against the extern it names,
+    /// and find how its values are lowered.
+    pub fn validate_import(
+        &self,
+        name: &str,
+        ty: &wasmparser::FuncType,
+    ) -> anyhow::Result<ImportAbi> {
+        let entry = self.validate(&ExternSignature::parse(name)?)?;
+
+        ImportAbi::new(&entry.signature, entry.instance, ty)
+    }
+
+    /// Check every function imported from [`EXTERN_IMPORT_MODULE`] in the parsed module.
+    pub fn validate_imports(
+        &self,
+        payloads: &ParsedData<wasmparser::Payload<'_>>,
+    ) -> anyhow::Result<()> {
+        use wasmparser::{Payload, Type, TypeRef};
+
+        let mut types = Vec::new();
+        let mut imports = Vec::new();
+
+        let mut current = Some(payloads);
+        while let Some(parsed) = current {
+            match parsed.get_data() {
+                Payload::TypeSection(section) => {
+                    for ty in section.clone() {
+                        let Type::Func(func_type) = ty.map_err(|err| {
+                            anyhow::anyhow!("Failed to parse type section: {:?}", err)
+                        })?;
+                        types.push(func_type);
+                    }
+                }
+                Payload::ImportSection(section) => {
+                    for import in section.clone() {
+                        imports.push(import.map_err(|err| {
+                            anyhow::anyhow!("Failed to parse import section: {:?}", err)
+                        })?);
+                    }
+                }
+                _ => {}
+            }
+            current = parsed.get_next();
+        }
+
+        let errors = imports
+            .iter()
+            .filter(|import| {
+                import.module == EXTERN_IMPORT_MODULE || import.module == INTRINSIC_IMPORT_MODULE
+            })
+            .filter_map(|import| {
+                let TypeRef::Func(type_index) = import.ty else {
+                    // globals imported from `udon` are bound to Udon variables
+                    if import.module == EXTERN_IMPORT_MODULE {
+                        return None;
+                    }
+                    return Some(format!(
+                        "Intrinsic import is not a function: {}",
+                        import.name
+                    ));
+                };
+
+                let Some(ty) = types.get(type_index as usize) else {
+                    return Some(format!(
+                        "Unknown type index {}: {}",
+                        type_index, import.name
+                    ));
+                };
+
+                let result = if import.module == INTRINSIC_IMPORT_MODULE {
+                    Intrinsic::new(import.name, ty).map(|_| ())
+                } else {
+                    self.validate_import(import.name, ty).map(|_| ())
+                };
+
+                result.err().map(|err| format!("{}: {}", import.name, err))
+            })
+            .collect::<Vec<_>>();
+
+        report(errors)
+    }
+}
+
+fn report(errors: Vec<String>) -> anyhow::Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    anyhow::bail!("{} invalid extern(s):\n{}", errors.len(), errors.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::{string::ToString, vec, vec::Vec};
+
+    use super::*;
+    use crate::udon::uasm::data::UasmCodeSection;
+    use crate::udon::uasm::data::{UasmCode, UasmCodeBlock, UasmCodeLabel, UasmInstruction};
+    use crate::wasm::parser::{WasmEntry, WasmParser};
+
+    const LOG: &str = "UnityEngineDebug.__Log__SystemObject__SystemVoid";
+    const GET_PARENT: &str = "UnityEngineTransform.__get_parent__UnityEngineTransform";
+    const QUIT: &str = "UnityEngineApplication.__Quit__SystemVoid";
+
+    fn extern_db() -> ExternDatabase {
+        ExternDatabase::from_tsv(&format!(
+            "# fullName\tkind\texposure\n\n{LOG}\tstatic\texposed\r\n{GET_PARENT}\tinstance\texposed\n{QUIT}\tstatic\thidden\n"
+        ))
+        .unwrap()
+    }
+
+    fn error(result: anyhow::Result<impl ::core::fmt::Debug>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    fn leb128(mut value: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn section(id: u8, contents: Vec<u8>, out: &mut Vec<u8>) {
+        out.push(id);
+        leb128(contents.len(), out);
+        out.extend(contents);
+    }
+
+    /// a module importing each function `(module, name, params)`, with a type of its own and no result
+    fn module(imports: &[(&str, &str, &[u8])]) -> Vec<u8> {
+        let mut types = Vec::new();
+        leb128(imports.len(), &mut types);
+        for (_, _, params) in imports {
+            types.push(0x60);
+            leb128(params.len(), &mut types);
+            types.extend(*params);
+            types.push(0x00);
+        }
+
+        let mut import_section = Vec::new();
+        leb128(imports.len(), &mut import_section);
+        for (type_index, (module, name, _)) in imports.iter().enumerate() {
+            for name in [module, name] {
+                leb128(name.len(), &mut import_section);
+                import_section.extend(name.as_bytes());
+            }
+            import_section.push(0x00);
+            leb128(type_index, &mut import_section);
+        }
+
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+        section(0x01, types, &mut wasm);
+        section(0x02, import_section, &mut wasm);
+
+        wasm
+    }
+
+    fn validate_imports(wasm: &[u8]) -> anyhow::Result<()> {
+        let mut parser = WasmParser::from(WasmEntry::new(wasm, 0));
+        extern_db().validate_imports(&parser.parse_all()?)
+    }
+
+    #[test]
+    fn loads_tsv() {
+        let extern_db = extern_db();
+        assert_eq!(extern_db.len(), 3);
+
+        let entry = extern_db.get(GET_PARENT).unwrap();
+        assert!(entry.instance);
+        assert!(entry.exposed);
+        let entry = extern_db.get(QUIT).unwrap();
+        assert!(!entry.instance);
+        assert!(!entry.exposed);
+
+        assert_eq!(
+            error(ExternDatabase::from_tsv(&format!("{LOG}\tstatic"))),
+            "Expected 3 columns at line 1, got 2"
+        );
+        assert_eq!(
+            error(ExternDatabase::from_tsv(&format!(
+                "\n{LOG}\tvirtual\texposed"
+            ))),
+            "Unknown extern kind at line 2: \"virtual\""
+        );
+    }
+
+    #[test]
+    fn loads_json() {
+        let extern_db = ExternDatabase::from_file(
+            "externs.json",
+            &format!(
+                r#"[
+                    {{ "fullName": "{LOG}", "instance": false, "exposed": true }},
+                    {{ "fullName": "{GET_PARENT}", "instance": true, "exposed": false }}
+                ]"#
+            ),
+        )
+        .unwrap();
+        assert_eq!(extern_db.len(), 2);
+        assert!(extern_db.get(GET_PARENT).unwrap().instance);
+        assert!(!extern_db.get(GET_PARENT).unwrap().exposed);
+
+        assert_eq!(
+            error(ExternDatabase::from_json(&format!(
+                r#"[{{ "fullName": "{LOG}", "exposed": true }}]"#
+            ))),
+            format!("Missing `instance` in extern: {LOG}")
+        );
+        assert_eq!(
+            error(ExternDatabase::from_json("{}")),
+            "Expected an array of externs"
+        );
+    }
+
+    #[test]
+    fn validates_externs() {
+        let extern_db = extern_db();
+        let validate = |name| extern_db.validate(&ExternSignature::parse(name).unwrap());
+
+        assert!(validate(LOG).is_ok());
+        assert_eq!(
+            error(validate(QUIT)),
+            format!("Extern is not exposed to VRChat worlds: {QUIT}")
+        );
+        assert_eq!(
+            error(validate(
+                "UnityEngineDebug.__LogWarning__SystemObject__SystemVoid"
+            )),
+            "Unknown extern: UnityEngineDebug.__LogWarning__SystemObject__SystemVoid"
+        );
+        assert_eq!(
+            error(validate(
+                "UnityEngineDebug.__Log__SystemObject_SystemObject__SystemVoid"
+            )),
+            format!(
+                "Wrong number of parameters for extern: \
+                UnityEngineDebug.__Log__SystemObject_SystemObject__SystemVoid (candidates: {LOG})"
+            )
+        );
+        assert_eq!(
+            error(validate("UnityEngineDebug.__Log__SystemString__SystemVoid")),
+            format!(
+                "Wrong parameter or result types for extern: \
+                UnityEngineDebug.__Log__SystemString__SystemVoid (candidates: {LOG})"
+            )
+        );
+    }
+
+    #[test]
+    fn validates_uasm() {
+        let mut block = UasmCodeBlock::new();
+        for name in [LOG, QUIT] {
+            block.push_instruction(&UasmInstruction::new(UasmOpcode::Extern(
+                ExternSignature::parse(name).unwrap(),
+            )));
+        }
+        let code = UasmCode::from_blocks([(UasmCodeLabel::new("_start".into()), block)]).unwrap();
+        let uasm = Uasm::new(None, Some(UasmCodeSection::new(code)));
+
+        assert_eq!(
+            error(extern_db().validate_uasm(&uasm)),
+            format!("1 invalid extern(s):\n_start: Extern is not exposed to VRChat worlds: {QUIT}")
+        );
+    }
+
+    #[test]
+    fn validates_imports() {
+        // externref and i32
+        let log = ("udon", LOG, &[0x6F][..]);
+        assert!(validate_imports(&module(&[log])).is_ok());
+
+        let wasm = module(&[
+            log,
+            ("udon", LOG, &[0x7F, 0x7F]),
+            ("udon", QUIT, &[]),
+            ("env", "not_an_extern", &[]),
+        ]);
+        assert_eq!(
+            error(validate_imports(&wasm)),
+            format!(
+                "2 invalid extern(s):\n\
+                {LOG}: Wrong arity for import of {LOG}: 2 params and 0 results\n\
+                {QUIT}: Extern is not exposed to VRChat worlds: {QUIT}"
+            )
+        );
+    }
+
+    #[test]
+    fn takes_the_instance_from_the_database() {
+        let extern_db = extern_db();
+        // the type of a static getter, which the extern database contradicts
+        let ty = wasmparser::FuncType::new([], [wasmparser::ValType::I32]);
+
+        assert!(ImportAbi::resolve(GET_PARENT, &ty, None).is_ok());
+        assert_eq!(
+            error(ImportAbi::resolve(GET_PARENT, &ty, Some(&extern_db))),
+            format!("Missing parameter of type UnityEngineTransform in import of {GET_PARENT}")
+        );
+
+        let ty = wasmparser::FuncType::new([wasmparser::ValType::I32], [wasmparser::ValType::I32]);
+        let abi = ImportAbi::resolve(GET_PARENT, &ty, Some(&extern_db)).unwrap();
+        assert!(abi.instance.is_some());
+    }
+}
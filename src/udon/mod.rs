@@ -1 +1,11 @@
+pub mod asset;
+pub mod bindgen;
+pub mod extern_db;
+pub mod program;
 pub mod uasm;
+
+/// The wasm import module whose function imports are lowered to Udon externs.
+///
+/// The name of each import is the full extern name,
+/// e.g. `(import "udon" "UnityEngineDebug.__Log__SystemObject__SystemVoid" (func ...))`.
+pub const EXTERN_IMPORT_MODULE: &str = "udon";
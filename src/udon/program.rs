@@ -0,0 +1,309 @@
+//! The binary form of an Udon program, in the UDNP format of this crate.
+
+use ::alloc::{format, string::String, vec::Vec};
+
+use crate::udon::uasm::data::{UasmDataAttributeSync, UasmType, UasmValue};
+
+/// the magic number starting a program serialized in the UDNP format
+pub const PROGRAM_MAGIC: &[u8; 4] = b"UDNP";
+
+/// the version of the serialized program format
+pub const PROGRAM_VERSION: u32 = 1;
+
+#[doc = include_str!("../../docs/program.md")]
+#[derive(Debug, Default, Clone)]
+pub struct UdonProgram {
+    /// the opcodes and their operands as big-endian 4-byte words
+    pub byte_code: Vec<u8>,
+    /// the variables, indexed by their heap address
+    pub heap: Vec<UdonHeapSlot>,
+    /// the names of the variables in the heap
+    pub symbols: Vec<UdonSymbol>,
+    /// the names of the code blocks, exported ones being the entry points
+    pub labels: Vec<UdonSymbol>,
+    pub sync_metadata: Vec<UdonSyncMetadata>,
+}
+
+/// A variable in the heap.
+#[derive(Debug, Clone)]
+pub struct UdonHeapSlot {
+    pub ty: UasmType,
+    /// the initial value
+    pub value: UasmValue,
+}
+
+/// A named address, either in the heap or in the byte code.
+#[derive(Debug, Clone)]
+pub struct UdonSymbol {
+    pub name: String,
+    pub address: u32,
+    pub exported: bool,
+}
+
+/// A variable synced over the network.
+#[derive(Debug, Clone)]
+pub struct UdonSyncMetadata {
+    pub name: String,
+    pub mode: UasmDataAttributeSync,
+}
+
+impl UdonProgram {
+    /// the entry points of the program
+    pub fn entry_points(&self) -> impl Iterator<Item = &UdonSymbol> {
+        self.labels.iter().filter(|label| label.exported)
+    }
+
+    /// Serialize the program as described in the [format](UdonProgram).
+    ///
+    /// The heap must not hold a [`UasmValue::Address`], which [`crate::udon::uasm::assemble`] resolves.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(PROGRAM_MAGIC);
+        write_u32(&mut bytes, PROGRAM_VERSION);
+
+        write_u32(&mut bytes, self.byte_code.len() as u32);
+        bytes.extend_from_slice(&self.byte_code);
+
+        write_u32(&mut bytes, self.heap.len() as u32);
+        for (address, slot) in self.heap.iter().enumerate() {
+            write_str(&mut bytes, &slot.ty.udon_name());
+            write_value(&mut bytes, &slot.value).map_err(|err| {
+                anyhow::anyhow!("Failed to serialize the heap slot {}: {}", address, err)
+            })?;
+        }
+
+        for symbols in [&self.symbols, &self.labels] {
+            write_u32(&mut bytes, symbols.len() as u32);
+            for symbol in symbols.iter() {
+                write_str(&mut bytes, &symbol.name);
+                write_u32(&mut bytes, symbol.address);
+                bytes.push(u8::from(symbol.exported));
+            }
+        }
+
+        write_u32(&mut bytes, self.sync_metadata.len() as u32);
+        for sync in self.sync_metadata.iter() {
+            write_str(&mut bytes, &sync.name);
+            write_str(&mut bytes, &format!("{}", sync.mode));
+        }
+
+        Ok(bytes)
+    }
+
+    /// Read a program serialized with [`UdonProgram::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<UdonProgram> {
+        let mut reader = Reader { bytes, offset: 0 };
+
+        if reader.read_bytes(PROGRAM_MAGIC.len())? != PROGRAM_MAGIC {
+            anyhow::bail!("Not a serialized Udon program")
+        }
+        let version = reader.read_u32()?;
+        if version != PROGRAM_VERSION {
+            anyhow::bail!("Unsupported program version: {}", version)
+        }
+
+        let byte_code_len = reader.read_u32()? as usize;
+        let byte_code = reader.read_bytes(byte_code_len)?.to_vec();
+
+        let heap = (0..reader.read_u32()?)
+            .map(|_| {
+                Ok(UdonHeapSlot {
+                    ty: UasmType::from_udon_name(reader.read_str()?),
+                    value: reader.read_value()?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut read_symbols = || {
+            (0..reader.read_u32()?)
+                .map(|_| {
+                    Ok(UdonSymbol {
+                        name: String::from(reader.read_str()?),
+                        address: reader.read_u32()?,
+                        exported: reader.read_u8()? != 0,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let symbols = read_symbols()?;
+        let labels = read_symbols()?;
+
+        let sync_metadata = (0..reader.read_u32()?)
+            .map(|_| {
+                Ok(UdonSyncMetadata {
+                    name: String::from(reader.read_str()?),
+                    mode: UasmDataAttributeSync::try_from(reader.read_str()?)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if reader.offset != bytes.len() {
+            anyhow::bail!("Trailing bytes after the program at {}", reader.offset)
+        }
+
+        Ok(UdonProgram {
+            byte_code,
+            heap,
+            symbols,
+            labels,
+            sync_metadata,
+        })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected end of the program at {}", self.offset))?;
+        self.offset += len;
+
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    fn read_u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_str(&mut self) -> anyhow::Result<&'a str> {
+        let len = self.read_u32()? as usize;
+        let offset = self.offset;
+
+        ::core::str::from_utf8(self.read_bytes(len)?)
+            .map_err(|_| anyhow::anyhow!("Invalid UTF-8 string at {}", offset))
+    }
+
+    fn read_value(&mut self) -> anyhow::Result<UasmValue> {
+        let value = match self.read_u8()? {
+            0 => UasmValue::Null,
+            1 => UasmValue::This,
+            2 => UasmValue::Bool(self.read_u8()? != 0),
+            3 => UasmValue::Int(i64::from_be_bytes(self.read_array()?)),
+            4 => UasmValue::Float(f32::from_bits(u32::from_be_bytes(self.read_array()?))),
+            5 => UasmValue::Double(f64::from_bits(u64::from_be_bytes(self.read_array()?))),
+            6 => UasmValue::String(String::from(self.read_str()?)),
+            7 => UasmValue::UInt(u64::from_be_bytes(self.read_array()?)),
+            tag => anyhow::bail!("Unknown value tag at {}: {}", self.offset - 1, tag),
+        };
+
+        Ok(value)
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_u32(bytes, value.len() as u32);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn write_value(bytes: &mut Vec<u8>, value: &UasmValue) -> anyhow::Result<()> {
+    match value {
+        UasmValue::Null => bytes.push(0),
+        UasmValue::This => bytes.push(1),
+        UasmValue::Bool(value) => bytes.extend_from_slice(&[2, u8::from(*value)]),
+        UasmValue::Int(value) => {
+            bytes.push(3);
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        UasmValue::Float(value) => {
+            bytes.push(4);
+            bytes.extend_from_slice(&value.to_bits().to_be_bytes());
+        }
+        UasmValue::Double(value) => {
+            bytes.push(5);
+            bytes.extend_from_slice(&value.to_bits().to_be_bytes());
+        }
+        UasmValue::String(value) => {
+            bytes.push(6);
+            write_str(bytes, value);
+        }
+        UasmValue::UInt(value) => {
+            bytes.push(7);
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        UasmValue::Address(label) => anyhow::bail!("Unresolved address of {}", label),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::{format, vec};
+
+    use super::*;
+    use crate::udon::uasm::data::UasmCodeLabel;
+
+    #[test]
+    fn round_trips_the_values() {
+        let values = [
+            UasmValue::Null,
+            UasmValue::This,
+            UasmValue::Bool(true),
+            UasmValue::Int(-2),
+            UasmValue::Float(1.5),
+            UasmValue::Double(-0.25),
+            UasmValue::String("hello".into()),
+            UasmValue::UInt(u64::MAX),
+        ];
+        let program = UdonProgram {
+            heap: values
+                .iter()
+                .map(|value| UdonHeapSlot {
+                    ty: UasmType::Object,
+                    value: value.clone(),
+                })
+                .collect(),
+            ..UdonProgram::default()
+        };
+
+        let read = UdonProgram::from_bytes(&program.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            read.heap
+                .iter()
+                .map(|slot| format!("{:?}", slot.value))
+                .collect::<Vec<_>>(),
+            values
+                .iter()
+                .map(|value| format!("{:?}", value))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn rejects_unresolved_addresses() {
+        let program = UdonProgram {
+            heap: vec![UdonHeapSlot {
+                ty: UasmType::UInt32,
+                value: UasmValue::Address(UasmCodeLabel::new("_start".into())),
+            }],
+            ..UdonProgram::default()
+        };
+
+        assert_eq!(
+            format!("{}", program.to_bytes().unwrap_err()),
+            "Failed to serialize the heap slot 0: Unresolved address of _start"
+        );
+    }
+}
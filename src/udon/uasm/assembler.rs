@@ -0,0 +1,280 @@
+//! Assemble [`Uasm`] into an [`UdonProgram`].
+//!
+//! The variables take the heap addresses in the order they are declared,
+//! followed by one `SystemString` for each distinct extern name, which `EXTERN` refers to.
+
+use ::alloc::format;
+use hashbrown::HashMap;
+
+use super::data::{UasmCodeLabel, UasmOpcode, UasmType, UasmValue, UasmVarName};
+use super::Uasm;
+use crate::udon::program::{UdonHeapSlot, UdonProgram, UdonSymbol, UdonSyncMetadata};
+
+/// Assemble `uasm`, once its aliases are resolved.
+pub fn assemble(uasm: &Uasm) -> anyhow::Result<UdonProgram> {
+    let mut program = UdonProgram::default();
+
+    let label_addresses = uasm
+        .code_section
+        .as_ref()
+        .map(|code_section| code_section.get_code().label_addresses())
+        .unwrap_or_default();
+    let code_address = |label: &UasmCodeLabel| {
+        label
+            .address()
+            .or_else(|| label_addresses.get(label).copied())
+            .ok_or_else(|| anyhow::anyhow!("Undefined label: {}", label))
+    };
+
+    let mut heap_addresses = HashMap::new();
+    if let Some(data_section) = &uasm.data_section {
+        for data in data_section.get_data().iter() {
+            let name = &data.variable.name;
+            let address = program.heap.len() as u32;
+            if heap_addresses.insert(name, address).is_some() {
+                anyhow::bail!("Duplicate variable: {}", name)
+            }
+
+            let value = match &data.variable.value {
+                // UdonVM reads the return addresses as `SystemUInt32`
+                UasmValue::Address(label) => UasmValue::UInt(code_address(label)?.into()),
+                value => value.clone(),
+            };
+            program.heap.push(UdonHeapSlot {
+                ty: data.variable.ty.clone(),
+                value,
+            });
+            program.symbols.push(UdonSymbol {
+                name: format!("{}", name),
+                address,
+                exported: data.attribute.exported,
+            });
+            if let Some(mode) = &data.attribute.sync {
+                program.sync_metadata.push(UdonSyncMetadata {
+                    name: format!("{}", name),
+                    mode: mode.clone(),
+                });
+            }
+        }
+    }
+
+    let Some(code_section) = &uasm.code_section else {
+        return Ok(program);
+    };
+    let code = code_section.get_code();
+
+    for (label, block) in code.blocks() {
+        program.labels.push(UdonSymbol {
+            name: format!("{}", label),
+            address: label_addresses[label],
+            exported: block.is_exported(),
+        });
+    }
+
+    let heap_address = |var_name: &UasmVarName| {
+        heap_addresses
+            .get(var_name)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Undeclared variable: {}", var_name))
+    };
+
+    let mut extern_addresses = HashMap::new();
+
+    for (_, block) in code.blocks() {
+        for instruction in block.get_instructions().iter() {
+            let opcode = &instruction.opcode;
+
+            let operand = match opcode {
+                UasmOpcode::Nop | UasmOpcode::Pop | UasmOpcode::Copy => None,
+                UasmOpcode::Push(var_name)
+                | UasmOpcode::Annotation(var_name)
+                | UasmOpcode::JumpIndirect(var_name) => Some(heap_address(var_name)?),
+                UasmOpcode::JumpIfFalse(label) | UasmOpcode::Jump(label) => {
+                    Some(code_address(label)?)
+                }
+                UasmOpcode::Extern(signature) => {
+                    let name = format!("{}", signature);
+                    let address = match extern_addresses.get(&name) {
+                        Some(address) => *address,
+                        None => {
+                            let address = program.heap.len() as u32;
+                            program.heap.push(UdonHeapSlot {
+                                ty: UasmType::String,
+                                value: UasmValue::String(name.clone()),
+                            });
+                            extern_addresses.insert(name, address);
+                            address
+                        }
+                    };
+                    Some(address)
+                }
+            };
+
+            program
+                .byte_code
+                .extend_from_slice(&opcode.code().to_be_bytes());
+            if let Some(operand) = operand {
+                program.byte_code.extend_from_slice(&operand.to_be_bytes());
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::alloc::{vec, vec::Vec};
+
+    use crate::udon::uasm::data::{
+        UasmCode, UasmCodeBlock, UasmCodeSection, UasmData, UasmDataAttribute, UasmDataSection,
+        UasmInstruction, UasmVariable,
+    };
+
+    fn words(program: &UdonProgram) -> Vec<u32> {
+        program
+            .byte_code
+            .chunks(4)
+            .map(|word| u32::from_be_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn lays_out_the_heap_and_the_code() {
+        let uasm: Uasm = "\
+.data_start
+  .export a
+  a: %SystemString, \"hello\"
+  .sync b, smooth
+  b: %SystemInt32, 0
+.data_end
+
+.code_start
+  .export _start
+  _start:
+    PUSH, a
+    EXTERN, \"UnityEngineDebug.__Log__SystemObject__SystemVoid\"
+    PUSH, a
+    EXTERN, \"UnityEngineDebug.__Log__SystemObject__SystemVoid\"
+    JUMP, _end
+  _end:
+    JUMP_INDIRECT, b
+    JUMP, 0xFFFFFFFC
+.code_end
+"
+        .parse()
+        .unwrap();
+
+        let program = assemble(&uasm).unwrap();
+
+        // the extern name is stored once, after the variables
+        assert_eq!(program.heap.len(), 3);
+        assert!(matches!(
+            &program.heap[2].value,
+            UasmValue::String(name) if name == "UnityEngineDebug.__Log__SystemObject__SystemVoid"
+        ));
+
+        assert_eq!(
+            words(&program),
+            [1, 0, 6, 2, 1, 0, 6, 2, 5, 40, 8, 1, 5, 0xFFFF_FFFC]
+        );
+
+        let symbols: Vec<_> = program
+            .symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.address, symbol.exported))
+            .collect();
+        assert_eq!(symbols, [("a", 0, true), ("b", 1, false)]);
+
+        let labels: Vec<_> = program
+            .labels
+            .iter()
+            .map(|label| (label.name.as_str(), label.address, label.exported))
+            .collect();
+        assert_eq!(labels, [("_start", 0, true), ("_end", 40, false)]);
+
+        assert_eq!(program.sync_metadata.len(), 1);
+        assert_eq!(program.sync_metadata[0].name, "b");
+    }
+
+    #[test]
+    fn places_the_instructions_at_the_addresses_udon_vm_reports() {
+        let var = |name: &str| UasmVarName::new(name.into());
+        let label = |name: &str| UasmCodeLabel::new(name.into());
+        let block = |opcodes: Vec<UasmOpcode>| {
+            let mut block = UasmCodeBlock::new();
+            for opcode in opcodes {
+                block.push_instruction(&UasmInstruction::new(opcode));
+            }
+            block
+        };
+
+        // a return address can't be written in Udon Assembly, so the program is built directly
+        let mut data_section = UasmDataSection::new();
+        for (name, ty, value) in [
+            ("a", UasmType::Int32, UasmValue::Int(0)),
+            ("ret", UasmType::UInt32, UasmValue::Address(label("_back"))),
+        ] {
+            data_section.push_data(&UasmData {
+                attribute: UasmDataAttribute::default(),
+                variable: UasmVariable {
+                    name: var(name),
+                    ty,
+                    value,
+                },
+            });
+        }
+        let code = UasmCode::from_blocks([
+            (
+                label("_start"),
+                block(vec![
+                    UasmOpcode::Nop,
+                    UasmOpcode::Push(var("a")),
+                    UasmOpcode::Pop,
+                    UasmOpcode::Jump(label("_back")),
+                ]),
+            ),
+            (
+                label("_back"),
+                block(vec![UasmOpcode::JumpIndirect(var("ret"))]),
+            ),
+        ])
+        .unwrap();
+        let uasm = Uasm::new(Some(data_section), Some(UasmCodeSection::new(code)));
+
+        let program = assemble(&uasm).unwrap();
+
+        // UdonVM reports the byte offset of an instruction, each word taking 4 bytes
+        let words = words(&program);
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        while offset / 4 < words.len() {
+            offsets.push(offset);
+            offset += match words[offset / 4] {
+                0 | 2 | 9 => 4,
+                _ => 8,
+            };
+        }
+        assert_eq!(offsets, [0, 4, 12, 16, 24]);
+        assert_eq!(words, [0, 1, 0, 2, 5, 24, 8, 1]);
+
+        // the return address is the offset of its label, which UdonVM reads from a `SystemUInt32`
+        assert!(matches!(program.heap[1].value, UasmValue::UInt(24)));
+        program.to_bytes().unwrap();
+    }
+
+    #[test]
+    fn rejects_undefined_names() {
+        let error = |src: &str| format!("{}", assemble(&src.parse().unwrap()).unwrap_err());
+
+        assert_eq!(
+            error(".code_start\n  _start:\n    PUSH, x\n.code_end\n"),
+            "Undeclared variable: x"
+        );
+        assert_eq!(
+            error(".code_start\n  _start:\n    JUMP, _nowhere\n.code_end\n"),
+            "Undefined label: _nowhere"
+        );
+    }
+}
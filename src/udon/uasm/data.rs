@@ -1,12 +1,13 @@
 use ::alloc::borrow::Cow;
-use ::alloc::{
-    string::{String, ToString},
-    vec::Vec,
-};
-use alloc::format;
+use ::alloc::{boxed::Box, format, string::String, vec::Vec};
+use ::core::fmt::{self, Write};
 use hashbrown::HashMap;
 
 use crate::core::Units;
+use crate::udon::extern_db::ExternDatabase;
+
+use super::printer::UasmPrinter;
+use super::signature::ExternSignature;
 
 /// the whole data structure of Udon Assembly
 #[derive(Debug, Default)]
@@ -17,39 +18,9 @@ pub struct Uasm {
     pub code_section: Option<UasmCodeSection>,
 }
 
-impl ToString for Uasm {
-    fn to_string(&self) -> String {
-        let mut code = String::new();
-        let data_section = if let Some(data_section) = &self.data_section {
-            format!(
-                r#"
-            .data_start
-            {}
-            .data_end
-            "#,
-                data_section.to_string()
-            )
-        } else {
-            String::new()
-        };
-
-        let code_section = if let Some(code_section) = &self.code_section {
-            format!(
-                r#"
-            .code_start
-            {}
-            .code_end
-            "#,
-                code_section.to_string()
-            )
-        } else {
-            String::new()
-        };
-
-        code.push_str(data_section.as_str());
-        code.push_str(code_section.as_str());
-
-        code
+impl fmt::Display for Uasm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        UasmPrinter::default().write_uasm(f, self)
     }
 }
 
@@ -71,79 +42,244 @@ impl Uasm {
     pub fn set_code_section(&mut self, code_section: UasmCodeSection) {
         self.code_section = Some(code_section);
     }
-}
 
-/// the code section of Udon Assembly
-#[derive(Debug)]
-pub enum UasmCodeSection {
-    /// the code section with export
-    Export(UasmCode),
-    /// the code section without export
-    NoExport(UasmCode),
-}
+    /// Export the variables with aliases under their exported names,
+    /// and replace the references to their internal names in the code section.
+    pub fn resolve_aliases(&mut self) -> anyhow::Result<()> {
+        let Some(data_section) = &mut self.data_section else {
+            return Ok(());
+        };
 
-impl ToString for UasmCodeSection {
-    fn to_string(&self) -> String {
-        match self {
-            UasmCodeSection::Export(code_section) => {
-                let mut code = String::new();
-                for (label, block) in code_section.0.iter() {
-                    let label = label.to_string();
-                    code.push_str(
-                        format!(
-                            r#"
-                        .export {}
-                        {}:
-                            {}
-                        "#,
-                            &label,
-                            &label,
-                            block.to_string()
-                        )
-                        .as_str(),
-                    );
+        data_section.apply_aliases()?;
+
+        let Some(code_section) = &mut self.code_section else {
+            return Ok(());
+        };
+
+        for (_, block) in code_section.get_code_mut().blocks_mut() {
+            for instruction in block.get_instructions_mut().iter_mut() {
+                if let UasmOpcode::Push(var_name)
+                | UasmOpcode::Annotation(var_name)
+                | UasmOpcode::JumpIndirect(var_name) = &mut instruction.opcode
+                {
+                    *var_name = data_section.resolve(var_name).clone();
                 }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the addresses of labels held by variables with numbers, once the code is laid out.
+    ///
+    /// The printed Udon Assembly can't refer to a label in the data section, so it's done before printing.
+    pub fn resolve_addresses(&mut self) -> anyhow::Result<()> {
+        let Some(data_section) = &mut self.data_section else {
+            return Ok(());
+        };
+
+        let addresses = self
+            .code_section
+            .as_ref()
+            .map(|code_section| code_section.get_code().label_addresses())
+            .unwrap_or_default();
+
+        for data in data_section.data.iter_mut() {
+            if let UasmValue::Address(label) = &data.variable.value {
+                let address = addresses
+                    .get(label)
+                    .ok_or_else(|| anyhow::anyhow!("Undefined label: {}", label))?;
+                data.variable.value = UasmValue::UInt((*address).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rename the variables and the labels found in `variables` and `labels`, wherever they are used.
+    ///
+    /// The new labels must not collide with the labels of the code section.
+    pub fn rename(
+        &mut self,
+        variables: &HashMap<UasmVarName, UasmVarName>,
+        labels: &HashMap<UasmCodeLabel, UasmCodeLabel>,
+    ) {
+        let rename_var = |name: &mut UasmVarName| {
+            if let Some(new_name) = variables.get(name) {
+                *name = new_name.clone();
+            }
+        };
 
-                code
+        let rename_label = |label: &mut UasmCodeLabel| {
+            if let Some(new_label) = labels.get(label) {
+                *label = new_label.clone();
             }
-            UasmCodeSection::NoExport(code_section) => {
-                let mut code = String::new();
-                for (label, block) in code_section.0.iter() {
-                    code.push_str(
-                        format!(
-                            r#"
-                        {}:
-                            {}
-                        "#,
-                            label.to_string(),
-                            block.to_string()
-                        )
-                        .as_str(),
-                    );
+        };
+
+        if let Some(data_section) = &mut self.data_section {
+            for data in data_section.data.iter_mut() {
+                rename_var(&mut data.variable.name);
+                if let UasmValue::Address(label) = &mut data.variable.value {
+                    rename_label(label);
                 }
+            }
+            for alias in data_section.aliases.iter_mut() {
+                rename_var(&mut alias.internal);
+            }
+        }
+
+        if let Some(code_section) = &mut self.code_section {
+            let code = code_section.get_code_mut();
 
-                code
+            for (label, block) in code.0.iter_mut() {
+                rename_label(label);
+
+                for instruction in block.instructions.iter_mut() {
+                    match &mut instruction.opcode {
+                        UasmOpcode::Push(var_name)
+                        | UasmOpcode::Annotation(var_name)
+                        | UasmOpcode::JumpIndirect(var_name) => rename_var(var_name),
+                        UasmOpcode::Jump(label) | UasmOpcode::JumpIfFalse(label) => {
+                            rename_label(label)
+                        }
+                        _ => {}
+                    }
+                }
             }
+
+            code.1 = code
+                .0
+                .iter()
+                .enumerate()
+                .map(|(index, (label, _))| (label.clone(), index))
+                .collect();
+        }
+    }
+}
+
+/// the code section of Udon Assembly
+///
+/// Each block is exported on its own (see [`UasmCodeBlock::is_exported`]).
+#[derive(Debug, Default)]
+pub struct UasmCodeSection(UasmCode);
+
+impl UasmCodeSection {
+    pub fn new(code: UasmCode) -> UasmCodeSection {
+        UasmCodeSection(code)
+    }
+
+    pub fn get_code(&self) -> &UasmCode {
+        &self.0
+    }
+
+    pub fn get_code_mut(&mut self) -> &mut UasmCode {
+        &mut self.0
+    }
+
+    /// Move the blocks of `other` after the blocks of this section.
+    ///
+    /// The last block of this section must not fall through, as it would run into the blocks of `other`.
+    pub fn merge(&mut self, other: UasmCodeSection) -> anyhow::Result<()> {
+        if let Some((label, _)) = self
+            .0
+            .blocks()
+            .last()
+            .filter(|(_, block)| block.falls_through())
+        {
+            anyhow::bail!(
+                "Cannot merge code after the block {}, which falls through",
+                label
+            )
+        }
+
+        for (label, block) in other.0 .0 {
+            self.0.set_block_with_label(label, block)?;
         }
+
+        Ok(())
     }
 }
 
-/// the map of code labels and their code blocks
-#[derive(Debug)]
-pub struct UasmCode(HashMap<UasmCodeLabel, UasmCodeBlock>);
+impl fmt::Display for UasmCodeSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        UasmPrinter::default().write_code_section(f, self)
+    }
+}
+
+/// the code labels and their code blocks, in the order they are laid out
+///
+/// A block which doesn't end with a jump falls through into the next one.
+#[derive(Debug, Default)]
+pub struct UasmCode(
+    Vec<(UasmCodeLabel, UasmCodeBlock)>,
+    HashMap<UasmCodeLabel, usize>,
+);
 
 impl UasmCode {
     pub fn new() -> UasmCode {
-        UasmCode(HashMap::new())
+        UasmCode::default()
     }
 
-    /// insert a code block with a label
-    pub fn set_block_with_label(&mut self, label: UasmCodeLabel, block: UasmCodeBlock) {
-        self.0.insert(label, block);
+    /// insert a code block with a label after the other blocks
+    ///
+    /// A label can only be defined once.
+    pub fn set_block_with_label(
+        &mut self,
+        label: UasmCodeLabel,
+        block: UasmCodeBlock,
+    ) -> anyhow::Result<()> {
+        if self.1.contains_key(&label) {
+            anyhow::bail!("Duplicate label: {}", label)
+        }
+
+        self.1.insert(label.clone(), self.0.len());
+        self.0.push((label, block));
+
+        Ok(())
+    }
+
+    /// Lay out `blocks` in order, failing on a label defined twice.
+    pub fn from_blocks(
+        blocks: impl IntoIterator<Item = (UasmCodeLabel, UasmCodeBlock)>,
+    ) -> anyhow::Result<UasmCode> {
+        let mut code = UasmCode::new();
+        for (label, block) in blocks {
+            code.set_block_with_label(label, block)?;
+        }
+
+        Ok(code)
     }
 
     pub fn get_block_with_label(&self, label: &UasmCodeLabel) -> Option<&UasmCodeBlock> {
-        self.0.get(label)
+        self.1.get(label).map(|index| &self.0[*index].1)
+    }
+
+    /// iterate over the labels and their code blocks in order
+    pub fn blocks(&self) -> impl Iterator<Item = (&UasmCodeLabel, &UasmCodeBlock)> {
+        self.0.iter().map(|(label, block)| (label, block))
+    }
+
+    /// iterate over the blocks in order with the address each one starts at in the program
+    pub fn blocks_with_addresses(
+        &self,
+    ) -> impl Iterator<Item = (u32, &UasmCodeLabel, &UasmCodeBlock)> {
+        self.blocks().scan(0, |address, (label, block)| {
+            let start = *address;
+            *address += block.size();
+
+            Some((start, label, block))
+        })
+    }
+
+    /// Find the address of each block in the program.
+    pub fn label_addresses(&self) -> HashMap<UasmCodeLabel, u32> {
+        self.blocks_with_addresses()
+            .map(|(address, label, _)| (label.clone(), address))
+            .collect()
+    }
+
+    pub fn blocks_mut(&mut self) -> impl Iterator<Item = (&UasmCodeLabel, &mut UasmCodeBlock)> {
+        self.0.iter_mut().map(|(label, block)| (&*label, block))
     }
 }
 
@@ -151,9 +287,9 @@ impl UasmCode {
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub struct UasmCodeLabel(String);
 
-impl ToString for UasmCodeLabel {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl fmt::Display for UasmCodeLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
     }
 }
 
@@ -161,34 +297,71 @@ impl UasmCodeLabel {
     pub fn new(label: Cow<'_, str>) -> UasmCodeLabel {
         UasmCodeLabel(label.into_owned())
     }
+
+    /// a literal address in the program (e.g. `0xFFFFFFFC`) used in place of a label
+    pub fn from_address(address: u32) -> UasmCodeLabel {
+        UasmCodeLabel(format!("0x{address:08X}"))
+    }
+
+    /// the address if the label is a literal address
+    pub fn address(&self) -> Option<u32> {
+        let digits = self
+            .0
+            .strip_prefix("0x")
+            .or_else(|| self.0.strip_prefix("0X"))?;
+
+        u32::from_str_radix(digits, 16).ok()
+    }
 }
 
 /// the code block of a code section
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct UasmCodeBlock {
     instructions: Vec<UasmInstruction>,
+    /// whether the label is an entry point of the program
+    export: bool,
+    /// the comment written above the label
+    comment: Option<String>,
 }
 
-impl ToString for UasmCodeBlock {
-    fn to_string(&self) -> String {
-        let mut code = String::new();
-
+impl fmt::Display for UasmCodeBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for instruction in self.instructions.iter() {
-            code.push_str("\n");
-            code.push_str(&instruction.to_string());
+            write!(f, "\n{}", instruction)?;
         }
 
-        code
+        Ok(())
     }
 }
 
 impl UasmCodeBlock {
     pub fn new() -> UasmCodeBlock {
+        UasmCodeBlock::default()
+    }
+
+    pub fn with_export(self, export: bool) -> UasmCodeBlock {
+        UasmCodeBlock { export, ..self }
+    }
+
+    pub fn with_comment(self, comment: impl Into<String>) -> UasmCodeBlock {
         UasmCodeBlock {
-            instructions: Vec::new(),
+            comment: Some(comment.into()),
+            ..self
         }
     }
 
+    pub fn is_exported(&self) -> bool {
+        self.export
+    }
+
+    pub fn set_export(&mut self, export: bool) {
+        self.export = export;
+    }
+
+    pub fn get_comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
     pub fn push_instruction(&mut self, instruction: &UasmInstruction) {
         self.instructions.push(instruction.clone());
     }
@@ -196,15 +369,53 @@ impl UasmCodeBlock {
     pub fn get_instructions(&self) -> &Vec<UasmInstruction> {
         &self.instructions
     }
+
+    pub fn get_instructions_mut(&mut self) -> &mut Vec<UasmInstruction> {
+        &mut self.instructions
+    }
+
+    /// the number of bytes the instructions take in the program
+    pub fn size(&self) -> u32 {
+        self.instructions
+            .iter()
+            .map(|instruction| instruction.opcode.size())
+            .sum()
+    }
+
+    /// iterate over the instructions with their address, the block starting at `address`
+    pub fn instructions_with_addresses(
+        &self,
+        address: u32,
+    ) -> impl Iterator<Item = (u32, &UasmInstruction)> {
+        self.instructions
+            .iter()
+            .scan(address, |address, instruction| {
+                let start = *address;
+                *address += instruction.opcode.size();
+
+                Some((start, instruction))
+            })
+    }
+
+    /// whether the execution continues into the next block after this one
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self.instructions.last(),
+            Some(UasmInstruction {
+                opcode: UasmOpcode::Jump(_) | UasmOpcode::JumpIndirect(_),
+                ..
+            })
+        )
+    }
 }
 
 impl From<Units<UasmInstruction>> for UasmCodeBlock {
     fn from(units: Units<UasmInstruction>) -> UasmCodeBlock {
         let mut block = UasmCodeBlock::new();
 
-        for unit in units.iter() {
-            let instruction = unit;
-            block.push_instruction(instruction);
+        // the units pop from the last one, which is the first instruction
+        for instruction in units {
+            block.push_instruction(&instruction);
         }
 
         block
@@ -215,81 +426,195 @@ impl From<Units<UasmInstruction>> for UasmCodeBlock {
 #[derive(Debug, Clone)]
 pub struct UasmInstruction {
     pub opcode: UasmOpcode,
+    /// the offset in the wasm binary of the operator the instruction is lowered from
+    pub offset: Option<usize>,
 }
 
-impl ToString for UasmInstruction {
-    fn to_string(&self) -> String {
-        self.opcode.to_string()
+impl fmt::Display for UasmInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.opcode.fmt(f)
     }
 }
 
 impl UasmInstruction {
     pub fn new(opcode: UasmOpcode) -> UasmInstruction {
-        UasmInstruction { opcode }
+        UasmInstruction {
+            opcode,
+            offset: None,
+        }
+    }
+
+    pub fn with_offset(self, offset: usize) -> UasmInstruction {
+        UasmInstruction {
+            offset: Some(offset),
+            ..self
+        }
     }
 }
 
 /// Udon Assembly opcode
+///
+/// The variants are the opcodes of UdonVM with their operand, in the order of their numeric values.
 #[derive(Debug, Clone)]
 pub enum UasmOpcode {
     Nop,
+    /// push the address of a variable
     Push(UasmVarName),
     Pop,
-    Jump(UasmCodeLabel),
+    /// pop a `SystemBoolean` and jump if it is false
     JumpIfFalse(UasmCodeLabel),
+    Jump(UasmCodeLabel),
+    /// pop the variables of the instance, the arguments and the result, then call the extern
+    Extern(ExternSignature),
+    /// metadata about the variable, which UdonVM skips over
+    Annotation(UasmVarName),
+    /// jump to the address held in a `SystemUInt32` variable
     JumpIndirect(UasmVarName),
+    /// pop the destination, then the source and copy the value of the source into the destination
     Copy,
-    Extern(String),
-    /// NOTE: I don't know what this really do.
-    Annotation,
 }
 
-impl ToString for UasmOpcode {
-    fn to_string(&self) -> String {
+/// the kind of the operand following an opcode in the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UasmOperandKind {
+    None,
+    /// the address of a variable in the heap
+    Variable,
+    /// the address of an instruction
+    Label,
+    /// the address of the variable holding the extern name
+    Extern,
+}
+
+/// the number of variable addresses an opcode pops from the stack and pushes onto it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UasmStackEffect {
+    pub pops: usize,
+    pub pushes: usize,
+}
+
+impl UasmOpcode {
+    /// the numeric value of the opcode in the program
+    pub fn code(&self) -> u32 {
+        match self {
+            UasmOpcode::Nop => 0,
+            UasmOpcode::Push(_) => 1,
+            UasmOpcode::Pop => 2,
+            UasmOpcode::JumpIfFalse(_) => 4,
+            UasmOpcode::Jump(_) => 5,
+            UasmOpcode::Extern(_) => 6,
+            UasmOpcode::Annotation(_) => 7,
+            UasmOpcode::JumpIndirect(_) => 8,
+            UasmOpcode::Copy => 9,
+        }
+    }
+
+    pub fn operand_kind(&self) -> UasmOperandKind {
         match self {
-            &UasmOpcode::Nop => "NOP".to_string(),
-            &UasmOpcode::Push(ref var_name) => format!("PUSH,{}", var_name.to_string()),
-            &UasmOpcode::Pop => "POP".to_string(),
-            &UasmOpcode::Jump(ref label) => format!("JUMP,{}", label.to_string()),
-            &UasmOpcode::JumpIfFalse(ref label) => format!("JUMP_IF_FALSE,{}", label.to_string()),
-            &UasmOpcode::JumpIndirect(ref var_name) => {
-                format!("JUMP_INDIRECT,{}", var_name.to_string())
+            UasmOpcode::Nop | UasmOpcode::Pop | UasmOpcode::Copy => UasmOperandKind::None,
+            UasmOpcode::Push(_) | UasmOpcode::Annotation(_) | UasmOpcode::JumpIndirect(_) => {
+                UasmOperandKind::Variable
             }
-            &UasmOpcode::Copy => "COPY".to_string(),
-            &UasmOpcode::Extern(ref name) => format!(r#"EXTERN,"{}""#, name),
-            &UasmOpcode::Annotation => unreachable!(),
+            UasmOpcode::JumpIfFalse(_) | UasmOpcode::Jump(_) => UasmOperandKind::Label,
+            UasmOpcode::Extern(_) => UasmOperandKind::Extern,
+        }
+    }
+
+    /// the size in bytes of the opcode and its operand in the program
+    pub fn size(&self) -> u32 {
+        match self.operand_kind() {
+            UasmOperandKind::None => 4,
+            _ => 8,
         }
     }
+
+    /// the effect of the opcode on the stack
+    ///
+    /// An extern method also pops its instance, which is taken from [`ExternSignature::instance`]
+    /// or else from `extern_db` (see [`crate::udon::extern_db::ExternEntry::instance`]).
+    /// It's `None` when neither tells.
+    pub fn stack_effect(&self, extern_db: Option<&ExternDatabase>) -> Option<UasmStackEffect> {
+        let (pops, pushes) = match self {
+            UasmOpcode::Nop
+            | UasmOpcode::Jump(_)
+            | UasmOpcode::Annotation(_)
+            | UasmOpcode::JumpIndirect(_) => (0, 0),
+            UasmOpcode::Push(_) => (0, 1),
+            UasmOpcode::Pop | UasmOpcode::JumpIfFalse(_) => (1, 0),
+            UasmOpcode::Extern(signature) => {
+                let instance = signature.instance.or_else(|| {
+                    let entry = extern_db?.get(&format!("{}", signature))?;
+                    Some(entry.instance)
+                })?;
+
+                (
+                    usize::from(instance)
+                        + signature.params.len()
+                        + usize::from(signature.has_result()),
+                    0,
+                )
+            }
+            UasmOpcode::Copy => (2, 0),
+        };
+
+        Some(UasmStackEffect { pops, pushes })
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            UasmOpcode::Nop => "NOP",
+            UasmOpcode::Push(_) => "PUSH",
+            UasmOpcode::Pop => "POP",
+            UasmOpcode::JumpIfFalse(_) => "JUMP_IF_FALSE",
+            UasmOpcode::Jump(_) => "JUMP",
+            UasmOpcode::Extern(_) => "EXTERN",
+            UasmOpcode::Annotation(_) => "ANNOTATION",
+            UasmOpcode::JumpIndirect(_) => "JUMP_INDIRECT",
+            UasmOpcode::Copy => "COPY",
+        }
+    }
+
+    /// the operand as written in Udon Assembly
+    pub fn operand(&self) -> Option<String> {
+        match self {
+            UasmOpcode::Nop | UasmOpcode::Pop | UasmOpcode::Copy => None,
+            UasmOpcode::Push(var_name)
+            | UasmOpcode::Annotation(var_name)
+            | UasmOpcode::JumpIndirect(var_name) => Some(format!("{}", var_name)),
+            UasmOpcode::JumpIfFalse(label) | UasmOpcode::Jump(label) => Some(format!("{}", label)),
+            UasmOpcode::Extern(signature) => Some(format!(r#""{}""#, signature)),
+        }
+    }
+}
+
+impl fmt::Display for UasmOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())?;
+        if let Some(operand) = self.operand() {
+            write!(f, ", {}", operand)?;
+        }
+
+        Ok(())
+    }
 }
 
 /// the data section of Udon Assembly
 #[derive(Debug, Default)]
 pub struct UasmDataSection {
     data: Vec<UasmData>,
+    /// the variables to export under another name
+    aliases: Vec<UasmAlias>,
 }
 
-impl ToString for UasmDataSection {
-    fn to_string(&self) -> String {
-        let mut code = String::new();
-        for data in self.data.iter() {
-            code.push_str(format!(r#"{}"#, data.attribute.to_string(),).as_str());
-            code.push_str(
-                format!(
-                    r#"{}: {}"#,
-                    data.variable.name.to_string(),
-                    data.variable.ty.to_string()
-                )
-                .as_str(),
-            );
-        }
-
-        code
+impl fmt::Display for UasmDataSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        UasmPrinter::default().write_data_section(f, self)
     }
 }
 
 impl UasmDataSection {
     pub fn new() -> UasmDataSection {
-        UasmDataSection { data: Vec::new() }
+        UasmDataSection::default()
     }
 
     pub fn push_data(&mut self, data: &UasmData) {
@@ -299,6 +624,106 @@ impl UasmDataSection {
     pub fn get_data(&self) -> &Vec<UasmData> {
         &self.data
     }
+
+    /// change a variable declared in any section once the aliases are applied
+    pub fn push_alias(&mut self, alias: UasmAlias) {
+        self.aliases.push(alias);
+    }
+
+    pub fn get_aliases(&self) -> &Vec<UasmAlias> {
+        &self.aliases
+    }
+
+    /// get the name a variable is declared with
+    pub fn resolve<'a>(&'a self, name: &'a UasmVarName) -> &'a UasmVarName {
+        resolve(&self.aliases, name)
+    }
+
+    /// rename the variables with aliases and add their attributes and types
+    ///
+    /// The aliases of a variable are merged, so that one can rename it and another one sync it.
+    pub fn apply_aliases(&mut self) -> anyhow::Result<()> {
+        for (index, alias) in self.aliases.iter().enumerate() {
+            if alias.exported == alias.internal {
+                continue;
+            }
+
+            let name = resolve(&self.aliases[..index], &alias.internal);
+            if name == &alias.exported {
+                continue;
+            }
+            if name != &alias.internal {
+                // a variable has a single name, so the first one is kept
+                log::warn!(
+                    "Variable {} is already exported as {}, not as {}",
+                    alias.internal,
+                    name,
+                    alias.exported
+                );
+                continue;
+            }
+
+            if self
+                .data
+                .iter()
+                .any(|data| data.variable.name == alias.exported)
+            {
+                anyhow::bail!("Duplicate variable: {}", alias.exported)
+            }
+
+            self.data
+                .iter_mut()
+                .find(|data| data.variable.name == alias.internal)
+                .ok_or_else(|| anyhow::anyhow!("Unknown variable to export: {}", alias.internal))?
+                .variable
+                .name = alias.exported.clone();
+        }
+
+        for (index, alias) in self.aliases.iter().enumerate() {
+            let name = resolve(&self.aliases, &alias.internal);
+            let data = self
+                .data
+                .iter_mut()
+                .find(|data| &data.variable.name == name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown variable to export: {}", alias.internal))?;
+
+            data.attribute.merge(&alias.attribute);
+
+            if let Some(ty) = &alias.ty {
+                let conflicting = self.aliases[..index].iter().any(|other| {
+                    other.internal == alias.internal
+                        && other.ty.as_ref().is_some_and(|other| other != ty)
+                });
+                if conflicting {
+                    anyhow::bail!("Conflicting types for variable: {}", alias.internal)
+                }
+                data.variable.ty = ty.clone();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the name given to the variable `name` by the first alias renaming it, if any.
+fn resolve<'a>(aliases: &'a [UasmAlias], name: &'a UasmVarName) -> &'a UasmVarName {
+    aliases
+        .iter()
+        .find(|alias| &alias.internal == name && alias.exported != alias.internal)
+        .map_or(name, |alias| &alias.exported)
+}
+
+/// a variable visible from outside of the program under another name
+#[derive(Debug, Clone)]
+pub struct UasmAlias {
+    /// the name the variable is declared with
+    pub internal: UasmVarName,
+    /// the name the variable is visible as, which may be the same
+    pub exported: UasmVarName,
+    /// the attributes added to the declared ones
+    pub attribute: UasmDataAttribute,
+    /// the type replacing the declared one, if any
+    pub ty: Option<UasmType>,
 }
 
 /// the data section of Udon Assembly
@@ -313,6 +738,10 @@ impl UasmData {
         self.attribute = attribute;
     }
 
+    pub fn merge_attribute(&mut self, attribute: &UasmDataAttribute) {
+        self.attribute.merge(attribute);
+    }
+
     pub fn set_variable(&mut self, variable: UasmVariable) {
         self.variable = variable;
     }
@@ -323,21 +752,82 @@ impl UasmData {
 pub struct UasmVariable {
     pub name: UasmVarName,
     pub ty: UasmType,
+    /// the initial value
+    pub value: UasmValue,
 }
 
 impl UasmVariable {
+    /// a variable initialized with `null`
     pub fn new(name: UasmVarName, ty: UasmType) -> Self {
-        UasmVariable { name, ty }
+        UasmVariable {
+            name,
+            ty,
+            value: UasmValue::Null,
+        }
+    }
+
+    pub fn with_value(self, value: UasmValue) -> Self {
+        UasmVariable { value, ..self }
+    }
+}
+
+/// the initial value of a variable
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum UasmValue {
+    /// `null`, or the default value of a value type
+    #[default]
+    Null,
+    /// the behaviour running the program (or its `GameObject` or `Transform`, by the type of the variable)
+    This,
+    Bool(bool),
+    Int(i64),
+    /// an integer above [`i64::MAX`], e.g. a large `SystemUInt64`
+    UInt(u64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    /// the address of a label, e.g. a return address,
+    /// replaced with a number once the code is laid out (see [`Uasm::resolve_addresses`])
+    Address(UasmCodeLabel),
+}
+
+impl fmt::Display for UasmValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UasmValue::Null => write!(f, "null"),
+            UasmValue::This => write!(f, "this"),
+            UasmValue::Bool(value) => write!(f, "{}", value),
+            UasmValue::Int(value) => write!(f, "{}", value),
+            UasmValue::UInt(value) => write!(f, "{}", value),
+            UasmValue::Float(value) => write_float(f, *value as f64, value),
+            UasmValue::Double(value) => write_float(f, *value, value),
+            UasmValue::String(value) => {
+                f.write_char('"')?;
+                for c in value.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            UasmValue::Address(label) => write!(f, "{}", label),
+        }
     }
 }
 
 /// the name of a variable
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct UasmVarName(String);
 
-impl ToString for UasmVarName {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl fmt::Display for UasmVarName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
     }
 }
 
@@ -348,25 +838,151 @@ impl UasmVarName {
 }
 
 /// the typped value of a variable
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum UasmType {
+    Boolean,
+    Byte,
+    SByte,
+    /// a UTF-16 code unit
+    Char,
+    Int16,
+    UInt16,
     Int32,
+    UInt32,
     Int64,
+    UInt64,
     Single,
     Double,
     String,
+    Object,
+    Type,
+    /// an array of the element type (e.g. `SystemInt32Array`)
+    Array(Box<UasmType>),
+    /// any other type, identified by its Udon type name (e.g. `UnityEngineGameObject` or `VRCSDKBaseVRCPlayerApi`)
+    Other(String),
 }
 
-impl ToString for UasmType {
-    fn to_string(&self) -> String {
+/// the types named `System{name}` and their names
+const SYSTEM_TYPES: &[(UasmType, &str)] = &[
+    (UasmType::Boolean, "SystemBoolean"),
+    (UasmType::Byte, "SystemByte"),
+    (UasmType::SByte, "SystemSByte"),
+    (UasmType::Char, "SystemChar"),
+    (UasmType::Int16, "SystemInt16"),
+    (UasmType::UInt16, "SystemUInt16"),
+    (UasmType::Int32, "SystemInt32"),
+    (UasmType::UInt32, "SystemUInt32"),
+    (UasmType::Int64, "SystemInt64"),
+    (UasmType::UInt64, "SystemUInt64"),
+    (UasmType::Single, "SystemSingle"),
+    (UasmType::Double, "SystemDouble"),
+    (UasmType::String, "SystemString"),
+    (UasmType::Object, "SystemObject"),
+    (UasmType::Type, "SystemType"),
+];
+
+/// the value types (structs) outside of the types above which Udon exposes
+const VALUE_TYPES: &[&str] = &[
+    "SystemDateTime",
+    "SystemDateTimeOffset",
+    "SystemDecimal",
+    "SystemGuid",
+    "SystemTimeSpan",
+    "UnityEngineAnimatorClipInfo",
+    "UnityEngineAnimatorStateInfo",
+    "UnityEngineBoneWeight",
+    "UnityEngineBounds",
+    "UnityEngineBoundsInt",
+    "UnityEngineColor",
+    "UnityEngineColor32",
+    "UnityEngineContactPoint",
+    "UnityEngineContactPoint2D",
+    "UnityEngineGradientAlphaKey",
+    "UnityEngineGradientColorKey",
+    "UnityEngineKeyframe",
+    "UnityEngineLayerMask",
+    "UnityEngineMatrix4x4",
+    "UnityEngineParticleSystemEmitParams",
+    "UnityEngineParticleSystemMinMaxCurve",
+    "UnityEngineParticleSystemMinMaxGradient",
+    "UnityEngineParticleSystemParticle",
+    "UnityEnginePlane",
+    "UnityEngineQuaternion",
+    "UnityEngineRay",
+    "UnityEngineRay2D",
+    "UnityEngineRaycastHit",
+    "UnityEngineRaycastHit2D",
+    "UnityEngineRect",
+    "UnityEngineRectInt",
+    "UnityEngineResolution",
+    "UnityEngineVector2",
+    "UnityEngineVector2Int",
+    "UnityEngineVector3",
+    "UnityEngineVector3Int",
+    "UnityEngineVector4",
+    "VRCSDKBaseVRCPlayerApiTrackingData",
+];
+
+impl UasmType {
+    /// Get the type from its Udon type name (e.g. `SystemInt32`).
+    ///
+    /// A name ending with `Array` is an array, as Udon doesn't tell apart
+    /// an array of `UnityEngineTexture2D` and a `UnityEngineTexture2DArray`.
+    pub fn from_udon_name(name: &str) -> UasmType {
+        if let Some((ty, _)) = SYSTEM_TYPES
+            .iter()
+            .find(|(_, udon_name)| *udon_name == name)
+        {
+            return ty.clone();
+        }
+
+        match name.strip_suffix("Array") {
+            Some(element) if !element.is_empty() => {
+                UasmType::Array(Box::new(UasmType::from_udon_name(element)))
+            }
+            _ => UasmType::Other(name.into()),
+        }
+    }
+
+    /// Get the Udon type name (e.g. `SystemInt32`) used in extern signatures.
+    pub fn udon_name(&self) -> Cow<'_, str> {
+        match self {
+            UasmType::Array(element) => Cow::Owned(format!("{}Array", element.udon_name())),
+            UasmType::Other(name) => Cow::Borrowed(name),
+            ty => Cow::Borrowed(
+                SYSTEM_TYPES
+                    .iter()
+                    .find(|(other, _)| other == ty)
+                    .map(|(_, udon_name)| *udon_name)
+                    .expect("every other type is a system type"),
+            ),
+        }
+    }
+
+    /// Whether a variable of the type holds a reference to an object rather than a value.
+    ///
+    /// The other types are assumed to be Unity objects unless they are one of the known value types.
+    pub fn is_reference(&self) -> bool {
+        match self {
+            UasmType::String | UasmType::Object | UasmType::Type | UasmType::Array(_) => true,
+            UasmType::Other(name) => !VALUE_TYPES.contains(&name.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Whether the type is a struct such as `UnityEngineVector3`,
+    /// which is neither a number nor a reference and so cannot cross the boundary of the guest.
+    pub fn is_value_type(&self) -> bool {
         match self {
-            &UasmType::Int32 => "%SystemInt32",
-            &UasmType::Int64 => "%SystemInt64",
-            &UasmType::Single => "%SystemSingle",
-            &UasmType::Double => "$SystemDouble",
-            &UasmType::String => "%SystemString",
+            UasmType::Other(name) => VALUE_TYPES.contains(&name.as_str()),
+            _ => false,
         }
-        .to_string()
+    }
+}
+
+impl fmt::Display for UasmType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.udon_name())
     }
 }
 
@@ -380,40 +996,72 @@ impl TryFrom<wasmparser::ValType> for UasmType {
             ValType::I64 => UasmType::Int64,
             ValType::F32 => UasmType::Single,
             ValType::F64 => UasmType::Double,
+            // host objects are held as they are
+            ValType::ExternRef => UasmType::Object,
+            // null or the address of a function set by a global initializer (see `docs/variable.md`)
+            ValType::FuncRef => UasmType::Object,
             ValType::V128 => anyhow::bail!("Unsupported type: {:?}", value), // TODO: Support V128
-            _ => anyhow::bail!("Unsupported type: {:?}", value),
         };
 
         Ok(ty)
     }
 }
 
-/// the attributes of a data section
-#[derive(Debug, Default, Clone)]
-pub enum UasmDataAttribute {
-    /// the data section with no attribute
-    #[default]
-    None,
-    /// the data section with export
-    Export,
-    /// the data section without export
-    Sync(UasmDataAttributeSync),
+/// Write a float the way .NET parses it, with `value` printed in its shortest form.
+fn write_float(f: &mut fmt::Formatter<'_>, wide: f64, value: &dyn fmt::Display) -> fmt::Result {
+    if wide.is_nan() {
+        write!(f, "NaN")
+    } else if wide.is_infinite() {
+        write!(f, "{}Infinity", if wide < 0.0 { "-" } else { "" })
+    } else {
+        write!(f, "{}", value)
+    }
 }
 
-impl ToString for UasmDataAttribute {
-    fn to_string(&self) -> String {
-        match self {
-            &UasmDataAttribute::None => "",
-            &UasmDataAttribute::Export => ".export",
-            // TODO: Support Sync
-            &UasmDataAttribute::Sync(ref sync) => todo!(),
+/// the attributes of a variable
+///
+/// A variable can be both exported and synced, like a public synced field of UdonSharp.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UasmDataAttribute {
+    /// whether the variable is visible from the other behaviours and the inspector (`.export`)
+    pub exported: bool,
+    /// how the variable is synced over the network, if it is (`.sync`)
+    pub sync: Option<UasmDataAttributeSync>,
+}
+
+impl UasmDataAttribute {
+    /// the attribute of an exported variable
+    pub fn export() -> UasmDataAttribute {
+        UasmDataAttribute {
+            exported: true,
+            sync: None,
+        }
+    }
+
+    /// the attribute of a variable synced with `sync`, which isn't exported
+    pub fn sync(sync: UasmDataAttributeSync) -> UasmDataAttribute {
+        UasmDataAttribute {
+            exported: false,
+            sync: Some(sync),
+        }
+    }
+
+    /// whether the variable is visible from outside of the program in any way
+    pub fn is_public(&self) -> bool {
+        self.exported || self.sync.is_some()
+    }
+
+    /// Add the attributes set in `other`, its sync mode replacing this one.
+    pub fn merge(&mut self, other: &UasmDataAttribute) {
+        self.exported |= other.exported;
+        if let Some(sync) = &other.sync {
+            self.sync = Some(sync.clone());
         }
-        .to_string()
     }
 }
 
 /// the variation of a sync attribute
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub enum UasmDataAttributeSync {
     #[default]
     None,
@@ -421,13 +1069,140 @@ pub enum UasmDataAttributeSync {
     Smooth,
 }
 
-impl ToString for UasmDataAttributeSync {
-    fn to_string(&self) -> String {
+impl fmt::Display for UasmDataAttributeSync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            &UasmDataAttributeSync::None => "none",
-            &UasmDataAttributeSync::Linear => "linear",
-            &UasmDataAttributeSync::Smooth => "smooth",
+            UasmDataAttributeSync::None => write!(f, "none"),
+            UasmDataAttributeSync::Linear => write!(f, "linear"),
+            UasmDataAttributeSync::Smooth => write!(f, "smooth"),
         }
-        .to_string()
+    }
+}
+
+impl TryFrom<&str> for UasmDataAttributeSync {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let sync = match value {
+            "none" => UasmDataAttributeSync::None,
+            "linear" => UasmDataAttributeSync::Linear,
+            "smooth" => UasmDataAttributeSync::Smooth,
+            _ => anyhow::bail!("Unknown sync mode: {:?}", value),
+        };
+
+        Ok(sync)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::alloc::string::ToString;
+
+    fn label(name: &str) -> UasmCodeLabel {
+        UasmCodeLabel::new(name.into())
+    }
+
+    fn halting_block() -> UasmCodeBlock {
+        let mut block = UasmCodeBlock::new();
+        block.push_instruction(&UasmInstruction::new(UasmOpcode::Jump(
+            UasmCodeLabel::from_address(0xFFFF_FFFC),
+        )));
+
+        block
+    }
+
+    #[test]
+    fn rejects_duplicate_labels() {
+        let mut code = UasmCode::new();
+        code.set_block_with_label(label("a"), UasmCodeBlock::new())
+            .unwrap();
+        code.set_block_with_label(label("b"), halting_block())
+            .unwrap();
+
+        let err = code
+            .set_block_with_label(label("a"), halting_block())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Duplicate label: a");
+
+        // the first block is kept where it was
+        assert!(code
+            .get_block_with_label(&label("a"))
+            .unwrap()
+            .falls_through());
+        let labels: Vec<_> = code.blocks().map(|(label, _)| label.to_string()).collect();
+        assert_eq!(labels, ["a", "b"]);
+    }
+
+    #[test]
+    fn merges_after_a_block_not_falling_through() {
+        let other = || {
+            UasmCodeSection::new(UasmCode::from_blocks([(label("b"), halting_block())]).unwrap())
+        };
+
+        let mut section =
+            UasmCodeSection::new(UasmCode::from_blocks([(label("a"), halting_block())]).unwrap());
+        section.merge(other()).unwrap();
+        assert!(section
+            .get_code()
+            .get_block_with_label(&label("b"))
+            .is_some());
+
+        let mut section = UasmCodeSection::new(
+            UasmCode::from_blocks([(label("a"), UasmCodeBlock::new())]).unwrap(),
+        );
+        assert!(section.merge(other()).is_err());
+
+        // merging a label defined twice fails too
+        let mut section =
+            UasmCodeSection::new(UasmCode::from_blocks([(label("b"), halting_block())]).unwrap());
+        assert!(section.merge(other()).is_err());
+    }
+
+    #[test]
+    fn tells_apart_the_value_types() {
+        let vector = UasmType::from_udon_name("UnityEngineVector3");
+        assert!(vector.is_value_type());
+        assert!(!vector.is_reference());
+
+        let transform = UasmType::from_udon_name("UnityEngineTransform");
+        assert!(!transform.is_value_type());
+        assert!(transform.is_reference());
+
+        // an array of value types is an object all the same
+        let vectors = UasmType::from_udon_name("UnityEngineVector3Array");
+        assert!(!vectors.is_value_type());
+        assert!(vectors.is_reference());
+        assert!(!UasmType::Int32.is_value_type());
+    }
+
+    #[test]
+    fn counts_the_instance_of_an_extern() {
+        let name = "UnityEngineTransform.__get_position__UnityEngineVector3";
+        let signature = ExternSignature::parse(name).unwrap();
+        let opcode = UasmOpcode::Extern(signature.clone());
+        assert_eq!(opcode.stack_effect(None), None);
+
+        let mut extern_db = ExternDatabase::new();
+        extern_db.insert(crate::udon::extern_db::ExternEntry {
+            signature: signature.clone(),
+            instance: true,
+            exposed: true,
+        });
+        assert_eq!(
+            opcode.stack_effect(Some(&extern_db)),
+            Some(UasmStackEffect { pops: 2, pushes: 0 })
+        );
+
+        let var = UasmVarName::new("position".into());
+        let called = signature.call(None, &[], Some(&var)).unwrap();
+        assert_eq!(
+            called.last().unwrap().opcode.stack_effect(Some(&extern_db)),
+            Some(UasmStackEffect { pops: 1, pushes: 0 })
+        );
+        assert_eq!(
+            UasmOpcode::Copy.stack_effect(None),
+            Some(UasmStackEffect { pops: 2, pushes: 0 })
+        );
     }
 }
@@ -0,0 +1,483 @@
+//! Link several [`Uasm`] units into one program.
+//!
+//! A unit can use the variables and the labels declared by the others.
+//! The private declarations sharing a name are renamed apart, the first one keeping the name,
+//! while the exported ones must be unique.
+//! A helper unit, such as a subroutine of the runtime, is only linked in when the other units use it.
+
+use ::alloc::{format, string::String, vec::Vec};
+use hashbrown::{HashMap, HashSet};
+
+use super::data::{UasmCodeLabel, UasmInstruction, UasmOpcode, UasmValue, UasmVarName};
+use super::Uasm;
+use crate::core::runtime::HALT_ADDRESS;
+use crate::core::Units;
+
+/// The linker of [`Uasm`] units.
+#[derive(Debug, Default)]
+pub struct Linker {
+    /// the units in the order they are laid out, with whether they are helpers
+    units: Vec<(Uasm, bool)>,
+}
+
+/// the names a unit declares and uses
+#[derive(Debug, Default)]
+struct Symbols {
+    /// the variables with whether they are visible from outside of the program
+    variables: Vec<(UasmVarName, bool)>,
+    /// the labels with whether they are exported
+    labels: Vec<(UasmCodeLabel, bool)>,
+    used_variables: HashSet<UasmVarName>,
+    used_labels: HashSet<UasmCodeLabel>,
+}
+
+impl Symbols {
+    fn new(uasm: &Uasm) -> Symbols {
+        let mut symbols = Symbols::default();
+
+        if let Some(data_section) = &uasm.data_section {
+            let aliased = data_section
+                .get_aliases()
+                .iter()
+                .filter(|alias| alias.attribute.is_public())
+                .map(|alias| &alias.internal)
+                .collect::<HashSet<_>>();
+
+            for data in data_section.get_data().iter() {
+                let name = &data.variable.name;
+                let public = data.attribute.is_public() || aliased.contains(name);
+                symbols.variables.push((name.clone(), public));
+
+                if let UasmValue::Address(label) = &data.variable.value {
+                    symbols.used_labels.insert(label.clone());
+                }
+            }
+
+            symbols.used_variables.extend(
+                data_section
+                    .get_aliases()
+                    .iter()
+                    .map(|alias| alias.internal.clone()),
+            );
+        }
+
+        if let Some(code_section) = &uasm.code_section {
+            for (label, block) in code_section.get_code().blocks() {
+                symbols.labels.push((label.clone(), block.is_exported()));
+
+                for instruction in block.get_instructions().iter() {
+                    match &instruction.opcode {
+                        UasmOpcode::Push(var_name)
+                        | UasmOpcode::Annotation(var_name)
+                        | UasmOpcode::JumpIndirect(var_name) => {
+                            symbols.used_variables.insert(var_name.clone());
+                        }
+                        UasmOpcode::Jump(label) | UasmOpcode::JumpIfFalse(label)
+                            if label.address().is_none() =>
+                        {
+                            symbols.used_labels.insert(label.clone());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        symbols
+    }
+
+    fn declares_variable(&self, name: &UasmVarName) -> bool {
+        self.variables.iter().any(|(other, _)| other == name)
+    }
+
+    fn declares_label(&self, label: &UasmCodeLabel) -> bool {
+        self.labels.iter().any(|(other, _)| other == label)
+    }
+
+    /// Whether this unit uses a name declared by `other`.
+    fn uses(&self, other: &Symbols) -> bool {
+        self.used_variables
+            .iter()
+            .any(|name| !self.declares_variable(name) && other.declares_variable(name))
+            || self
+                .used_labels
+                .iter()
+                .any(|label| !self.declares_label(label) && other.declares_label(label))
+    }
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker::default()
+    }
+
+    /// Add a unit which is always linked in.
+    pub fn add_unit(&mut self, uasm: Uasm) {
+        self.units.push((uasm, false));
+    }
+
+    /// Add the units interpreted from a module, in the order of the module.
+    pub fn add_units(&mut self, units: Units<Uasm>) {
+        // the units pop from the first payload
+        for uasm in units {
+            self.add_unit(uasm);
+        }
+    }
+
+    /// Add a unit which is only linked in when the other linked units use it.
+    pub fn add_helper(&mut self, uasm: Uasm) {
+        self.units.push((uasm, true));
+    }
+
+    pub fn link(self) -> anyhow::Result<Uasm> {
+        let symbols = self
+            .units
+            .iter()
+            .map(|(uasm, _)| Symbols::new(uasm))
+            .collect::<Vec<_>>();
+
+        // link in the helpers used by the linked units, until no more are needed
+        let mut linked = self
+            .units
+            .iter()
+            .map(|(_, helper)| !helper)
+            .collect::<Vec<_>>();
+        loop {
+            let used = (0..symbols.len())
+                .filter(|index| !linked[*index])
+                .filter(|index| {
+                    (0..symbols.len())
+                        .any(|other| linked[other] && symbols[other].uses(&symbols[*index]))
+                })
+                .collect::<Vec<_>>();
+
+            if used.is_empty() {
+                break;
+            }
+            used.into_iter().for_each(|index| linked[index] = true);
+        }
+
+        let (mut units, symbols): (Vec<_>, Vec<_>) = self
+            .units
+            .into_iter()
+            .zip(symbols)
+            .zip(linked)
+            .filter(|(_, linked)| *linked)
+            .map(|(((uasm, _), symbols), _)| (uasm, symbols))
+            .unzip();
+
+        rename_private_symbols(&mut units, &symbols)?;
+
+        let mut uasm = Uasm::default();
+        let last_code = units.iter().rposition(|unit| unit.code_section.is_some());
+
+        for (index, mut unit) in units.into_iter().enumerate() {
+            if let Some(data_section) = unit.data_section.take() {
+                match &mut uasm.data_section {
+                    Some(uasm_data_section) => {
+                        data_section
+                            .get_data()
+                            .iter()
+                            .for_each(|data| uasm_data_section.push_data(data));
+                        data_section
+                            .get_aliases()
+                            .iter()
+                            .for_each(|alias| uasm_data_section.push_alias(alias.clone()));
+                    }
+                    None => uasm.set_data_section(data_section),
+                }
+            }
+
+            if let Some(mut code_section) = unit.code_section.take() {
+                // running past the end of the unit halts, rather than running into the next unit
+                if Some(index) != last_code {
+                    let last_block = code_section
+                        .get_code_mut()
+                        .blocks_mut()
+                        .last()
+                        .map(|(_, block)| block);
+                    if let Some(block) = last_block.filter(|block| block.falls_through()) {
+                        block.push_instruction(&UasmInstruction::new(UasmOpcode::Jump(
+                            UasmCodeLabel::from_address(HALT_ADDRESS),
+                        )));
+                    }
+                }
+
+                match &mut uasm.code_section {
+                    Some(uasm_code_section) => uasm_code_section.merge(code_section)?,
+                    None => uasm.set_code_section(code_section),
+                }
+            }
+        }
+
+        check_references(&uasm)?;
+
+        Ok(uasm)
+    }
+}
+
+/// Give a new name to each private declaration sharing its name with another declaration.
+///
+/// The exported declaration keeps the name, or else the first one does.
+fn rename_private_symbols(units: &mut [Uasm], symbols: &[Symbols]) -> anyhow::Result<()> {
+    let mut variables: HashMap<&UasmVarName, Vec<(usize, bool)>> = HashMap::new();
+    let mut labels: HashMap<&UasmCodeLabel, Vec<(usize, bool)>> = HashMap::new();
+
+    for (index, symbols) in symbols.iter().enumerate() {
+        for (name, public) in symbols.variables.iter() {
+            variables.entry(name).or_default().push((index, *public));
+        }
+        for (label, exported) in symbols.labels.iter() {
+            labels.entry(label).or_default().push((index, *exported));
+        }
+    }
+
+    let mut variable_renames = units.iter().map(|_| HashMap::new()).collect::<Vec<_>>();
+    let mut label_renames = units.iter().map(|_| HashMap::new()).collect::<Vec<_>>();
+
+    for (name, declarations) in variables.iter() {
+        for index in renamed(declarations, || format!("Duplicate export: {}", name))? {
+            let new_name = UasmVarName::new(
+                fresh_name(&format!("{}", name), index, |new_name| {
+                    variables.contains_key(&UasmVarName::new(new_name.into()))
+                })
+                .into(),
+            );
+            variable_renames[index].insert((*name).clone(), new_name);
+        }
+    }
+
+    for (label, declarations) in labels.iter() {
+        for index in renamed(declarations, || {
+            format!("Duplicate exported label: {}", label)
+        })? {
+            let new_label = UasmCodeLabel::new(
+                fresh_name(&format!("{}", label), index, |new_label| {
+                    labels.contains_key(&UasmCodeLabel::new(new_label.into()))
+                })
+                .into(),
+            );
+            label_renames[index].insert((*label).clone(), new_label);
+        }
+    }
+
+    for ((unit, variables), labels) in units.iter_mut().zip(variable_renames).zip(label_renames) {
+        unit.rename(&variables, &labels);
+    }
+
+    Ok(())
+}
+
+/// Find the units whose declaration of a name is renamed.
+fn renamed(
+    declarations: &[(usize, bool)],
+    duplicate: impl Fn() -> String,
+) -> anyhow::Result<Vec<usize>> {
+    let public = declarations
+        .iter()
+        .filter(|(_, public)| *public)
+        .collect::<Vec<_>>();
+    if public.len() > 1 {
+        anyhow::bail!(duplicate())
+    }
+
+    let kept = public.first().unwrap_or(&&declarations[0]).0;
+
+    Ok(declarations
+        .iter()
+        .map(|(index, _)| *index)
+        .filter(|index| *index != kept)
+        .collect())
+}
+
+/// Make a name for the declaration of `name` in the unit `index`, which `is_taken` doesn't have yet.
+fn fresh_name(name: &str, index: usize, is_taken: impl Fn(&str) -> bool) -> String {
+    let mut new_name = format!("{}__{}", name, index);
+    while is_taken(&new_name) {
+        new_name.push('_');
+    }
+
+    new_name
+}
+
+/// Check that every variable and label used by the program is declared.
+fn check_references(uasm: &Uasm) -> anyhow::Result<()> {
+    let symbols = Symbols::new(uasm);
+
+    let mut undefined = symbols
+        .used_variables
+        .iter()
+        .filter(|name| !symbols.declares_variable(name))
+        .map(|name| format!("{}", name))
+        .chain(
+            symbols
+                .used_labels
+                .iter()
+                .filter(|label| !symbols.declares_label(label))
+                .map(|label| format!("{}", label)),
+        )
+        .collect::<Vec<_>>();
+
+    if undefined.is_empty() {
+        return Ok(());
+    }
+
+    undefined.sort();
+    anyhow::bail!("Undefined symbol(s): {}", undefined.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(src: &str) -> Uasm {
+        src.parse().unwrap()
+    }
+
+    fn variables(uasm: &Uasm) -> Vec<String> {
+        uasm.data_section
+            .iter()
+            .flat_map(|data_section| data_section.get_data())
+            .map(|data| format!("{}", data.variable.name))
+            .collect()
+    }
+
+    fn labels(uasm: &Uasm) -> Vec<String> {
+        uasm.code_section
+            .iter()
+            .flat_map(|code_section| code_section.get_code().blocks())
+            .map(|(label, _)| format!("{}", label))
+            .collect()
+    }
+
+    #[test]
+    fn renames_private_symbols_apart() {
+        let mut linker = Linker::new();
+        linker.add_unit(unit(
+            "\
+.data_start
+  tmp: %SystemInt32, 0
+.data_end
+.code_start
+  _loop:
+    PUSH, tmp
+    POP
+    JUMP, _loop
+.code_end
+",
+        ));
+        linker.add_unit(unit(
+            "\
+.data_start
+  .export tmp
+  tmp: %SystemInt32, 0
+.data_end
+.code_start
+  _loop:
+    PUSH, tmp
+    POP
+.code_end
+",
+        ));
+
+        let uasm = linker.link().unwrap();
+
+        // the exported variable keeps its name, else the first declaration does
+        assert_eq!(variables(&uasm), ["tmp__0", "tmp"]);
+        assert_eq!(labels(&uasm), ["_loop", "_loop__1"]);
+
+        let blocks: Vec<_> = uasm
+            .code_section
+            .as_ref()
+            .unwrap()
+            .get_code()
+            .blocks()
+            .map(|(_, block)| format!("{}", block))
+            .collect();
+        assert_eq!(blocks[0], "\nPUSH, tmp__0\nPOP\nJUMP, _loop");
+        // the last unit is not followed by another one
+        assert_eq!(blocks[1], "\nPUSH, tmp\nPOP");
+    }
+
+    #[test]
+    fn links_the_used_helpers_only() {
+        let mut linker = Linker::new();
+        linker.add_unit(unit(
+            "\
+.code_start
+  _start:
+    JUMP, __RT__a
+.code_end
+",
+        ));
+        linker.add_helper(unit(
+            "\
+.code_start
+  __RT__a:
+    JUMP, __RT__b
+.code_end
+",
+        ));
+        linker.add_helper(unit(
+            "\
+.code_start
+  __RT__b:
+    JUMP, 0xFFFFFFFC
+.code_end
+",
+        ));
+        linker.add_helper(unit(
+            "\
+.data_start
+  __RT__unused: %SystemInt32, 0
+.data_end
+.code_start
+  __RT__c:
+    PUSH, __RT__unused
+    POP
+.code_end
+",
+        ));
+
+        let uasm = linker.link().unwrap();
+
+        assert!(variables(&uasm).is_empty());
+        assert_eq!(labels(&uasm), ["_start", "__RT__a", "__RT__b"]);
+    }
+
+    #[test]
+    fn rejects_duplicate_exports() {
+        let error = |first: &str, second: &str| {
+            let mut linker = Linker::new();
+            linker.add_unit(unit(first));
+            linker.add_unit(unit(second));
+            format!("{}", linker.link().unwrap_err())
+        };
+
+        let variable = ".data_start\n  .export hp\n  hp: %SystemInt32, 0\n.data_end\n";
+        assert_eq!(error(variable, variable), "Duplicate export: hp");
+
+        let label = ".code_start\n  .export _update\n  _update:\n    JUMP, 0xFFFFFFFC\n.code_end\n";
+        assert_eq!(error(label, label), "Duplicate exported label: _update");
+    }
+
+    #[test]
+    fn rejects_undefined_symbols() {
+        let mut linker = Linker::new();
+        linker.add_unit(unit(
+            "\
+.code_start
+  _start:
+    PUSH, x
+    POP
+    JUMP, _nowhere
+.code_end
+",
+        ));
+
+        assert_eq!(
+            format!("{}", linker.link().unwrap_err()),
+            "Undefined symbol(s): _nowhere, x"
+        );
+    }
+}
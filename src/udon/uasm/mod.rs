@@ -1,4 +1,14 @@
-pub mod codegen;
+pub mod assembler;
 pub mod data;
+pub mod linker;
+pub mod parser;
+pub mod printer;
+pub mod signature;
+pub mod validate;
+#[cfg(test)]
+pub(crate) mod vm;
 
+pub use assembler::assemble;
 pub use data::Uasm;
+pub use signature::ExternSignature;
+pub use validate::validate;
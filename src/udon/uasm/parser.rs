@@ -0,0 +1,487 @@
+//! Read Udon Assembly text into [`Uasm`].
+//!
+//! Comments start with `#` and run to the end of the line.
+//! A jump can target a literal address (e.g. `JUMP, 0xFFFFFFFC`), kept as a label by
+//! [`UasmCodeLabel::from_address`].
+
+use ::alloc::{format, string::String, vec::Vec};
+use ::core::str::FromStr;
+
+use super::data::{
+    UasmCode, UasmCodeBlock, UasmCodeLabel, UasmCodeSection, UasmData, UasmDataAttribute,
+    UasmDataAttributeSync, UasmDataSection, UasmInstruction, UasmOpcode, UasmType, UasmValue,
+    UasmVarName, UasmVariable,
+};
+use super::{ExternSignature, Uasm};
+
+impl FromStr for Uasm {
+    type Err = anyhow::Error;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        parse(src)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Section {
+    None,
+    Data,
+    Code,
+}
+
+/// Parse Udon Assembly text.
+pub fn parse(src: &str) -> anyhow::Result<Uasm> {
+    let mut uasm = Uasm::default();
+
+    let mut section = Section::None;
+
+    let mut data_section = UasmDataSection::new();
+    let mut attributes = Vec::new();
+
+    let mut code = UasmCode::new();
+    let mut exports = Vec::new();
+    let mut block: Option<(UasmCodeLabel, UasmCodeBlock)> = None;
+
+    for (line_index, line) in src.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let result = match (&section, line) {
+            (Section::None, ".data_start") => {
+                section = Section::Data;
+                Ok(())
+            }
+            (Section::None, ".code_start") => {
+                section = Section::Code;
+                Ok(())
+            }
+            (Section::None, _) => Err(anyhow::anyhow!("Expected a section: {:?}", line)),
+            (Section::Data, ".data_end") => {
+                apply_attributes(&mut data_section, &attributes).map(|()| {
+                    uasm.set_data_section(::core::mem::take(&mut data_section));
+                    attributes.clear();
+                    section = Section::None;
+                })
+            }
+            (Section::Data, line) => parse_data_line(line).map(|parsed| match parsed {
+                DataLine::Attribute(name, attribute) => attributes.push((name, attribute)),
+                DataLine::Data(data) => data_section.push_data(&data),
+            }),
+            (Section::Code, ".code_end") => block
+                .take()
+                .map_or(Ok(()), |(label, block)| {
+                    code.set_block_with_label(label, block)
+                })
+                .and_then(|()| apply_exports(&mut code, &exports))
+                .map(|()| {
+                    uasm.set_code_section(UasmCodeSection::new(::core::mem::take(&mut code)));
+                    exports.clear();
+                    section = Section::None;
+                }),
+            (Section::Code, line) => {
+                if let Some(label) = line.strip_prefix(".export") {
+                    parse_identifier(label.trim())
+                        .map(|label| exports.push(UasmCodeLabel::new(label.into())))
+                } else if let Some(label) = line.strip_suffix(':') {
+                    parse_identifier(label.trim()).and_then(|label| {
+                        // checked here, as the block is only added to the code on the line ending it
+                        let label = UasmCodeLabel::new(label.into());
+                        if code.get_block_with_label(&label).is_some()
+                            || block.as_ref().is_some_and(|(other, _)| other == &label)
+                        {
+                            anyhow::bail!("Duplicate label: {}", label)
+                        }
+                        if let Some((label, block)) = block.take() {
+                            code.set_block_with_label(label, block)?;
+                        }
+                        block = Some((label, UasmCodeBlock::new()));
+                        Ok(())
+                    })
+                } else {
+                    parse_instruction(line).and_then(|instruction| match &mut block {
+                        Some((_, block)) => {
+                            block.push_instruction(&instruction);
+                            Ok(())
+                        }
+                        None => Err(anyhow::anyhow!("Instruction outside of a block")),
+                    })
+                }
+            }
+        };
+
+        result.map_err(|err| anyhow::anyhow!("{} at line {}", err, line_number))?;
+    }
+
+    if section != Section::None {
+        anyhow::bail!("Unterminated section at the end of the input")
+    }
+
+    Ok(uasm)
+}
+
+enum DataLine {
+    Attribute(UasmVarName, UasmDataAttribute),
+    Data(UasmData),
+}
+
+fn parse_data_line(line: &str) -> anyhow::Result<DataLine> {
+    if let Some(name) = line.strip_prefix(".export") {
+        let name = parse_identifier(name.trim())?;
+        return Ok(DataLine::Attribute(
+            UasmVarName::new(name.into()),
+            UasmDataAttribute::export(),
+        ));
+    }
+
+    if let Some(rest) = line.strip_prefix(".sync") {
+        let (name, mode) = rest
+            .split_once(',')
+            .ok_or_else(|| anyhow::anyhow!("Missing sync mode: {:?}", line))?;
+        let name = parse_identifier(name.trim())?;
+        return Ok(DataLine::Attribute(
+            UasmVarName::new(name.into()),
+            UasmDataAttribute::sync(UasmDataAttributeSync::try_from(mode.trim())?),
+        ));
+    }
+
+    let (name, rest) = line
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Expected a variable: {:?}", line))?;
+    let name = parse_identifier(name.trim())?;
+
+    let (ty, value) = rest
+        .split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("Missing initial value: {:?}", line))?;
+    let ty = ty
+        .trim()
+        .strip_prefix('%')
+        .ok_or_else(|| anyhow::anyhow!("Expected a type: {:?}", ty.trim()))?;
+    let ty = UasmType::from_udon_name(parse_identifier(ty)?);
+
+    let value = parse_value(value.trim(), &ty)?;
+
+    Ok(DataLine::Data(UasmData {
+        attribute: UasmDataAttribute::default(),
+        variable: UasmVariable::new(UasmVarName::new(name.into()), ty).with_value(value),
+    }))
+}
+
+/// Set the attributes declared anywhere in a data section.
+fn apply_attributes(
+    data_section: &mut UasmDataSection,
+    attributes: &[(UasmVarName, UasmDataAttribute)],
+) -> anyhow::Result<()> {
+    let mut data = data_section.get_data().clone();
+
+    for (name, attribute) in attributes.iter() {
+        data.iter_mut()
+            .find(|data| &data.variable.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown variable: {}", name))?
+            .merge_attribute(attribute);
+    }
+
+    let mut new_data_section = UasmDataSection::new();
+    data.iter()
+        .for_each(|data| new_data_section.push_data(data));
+    *data_section = new_data_section;
+
+    Ok(())
+}
+
+fn apply_exports(code: &mut UasmCode, exports: &[UasmCodeLabel]) -> anyhow::Result<()> {
+    for label in exports.iter() {
+        code.blocks_mut()
+            .find(|(other, _)| *other == label)
+            .ok_or_else(|| anyhow::anyhow!("Unknown label: {}", label))?
+            .1
+            .set_export(true);
+    }
+
+    Ok(())
+}
+
+/// Parse the initial value of a variable of type `ty`.
+fn parse_value(value: &str, ty: &UasmType) -> anyhow::Result<UasmValue> {
+    let value = match value {
+        "null" => UasmValue::Null,
+        "this" => UasmValue::This,
+        "true" => UasmValue::Bool(true),
+        "false" => UasmValue::Bool(false),
+        value if value.starts_with('"') => UasmValue::String(parse_string(value)?),
+        value => {
+            let int = parse_int(value);
+
+            match (ty, int) {
+                (UasmType::Single, _) => UasmValue::Float(parse_float(value)? as f32),
+                (UasmType::Double, _) => UasmValue::Double(parse_float(value)?),
+                (_, Some(int)) => int,
+                (_, None) => anyhow::bail!("Invalid value: {:?}", value),
+            }
+        }
+    };
+
+    Ok(value)
+}
+
+/// Parse an integer from `i64::MIN` to `u64::MAX`, in decimal or in hexadecimal.
+fn parse_int(value: &str) -> Option<UasmValue> {
+    let (sign, digits) = match value.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", value),
+    };
+
+    // the sign is parsed with the digits, so that `i64::MIN` doesn't overflow
+    let int = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i128::from_str_radix(&format!("{sign}{hex}"), 16).ok()?,
+        None => format!("{sign}{digits}").parse::<i128>().ok()?,
+    };
+
+    i64::try_from(int)
+        .map(UasmValue::Int)
+        .or_else(|_| u64::try_from(int).map(UasmValue::UInt))
+        .ok()
+}
+
+fn parse_float(value: &str) -> anyhow::Result<f64> {
+    match value {
+        "NaN" => Ok(f64::NAN),
+        "Infinity" => Ok(f64::INFINITY),
+        "-Infinity" => Ok(f64::NEG_INFINITY),
+        value => value
+            .parse::<f64>()
+            .map_err(|_| anyhow::anyhow!("Invalid number: {:?}", value)),
+    }
+}
+
+/// Parse a quoted string literal with its escapes.
+fn parse_string(literal: &str) -> anyhow::Result<String> {
+    let inner = literal
+        .strip_prefix('"')
+        .and_then(|literal| literal.strip_suffix('"'))
+        .filter(|_| literal.len() >= 2)
+        .ok_or_else(|| anyhow::anyhow!("Unterminated string: {}", literal))?;
+
+    let mut string = String::new();
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let digits = chars.by_ref().take(4).collect::<String>();
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .filter(|_| digits.len() == 4)
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid escape: \\u{}", digits))?
+            }
+            other => anyhow::bail!("Invalid escape in string: {:?}", other),
+        };
+        string.push(escaped);
+    }
+
+    Ok(string)
+}
+
+fn parse_instruction(line: &str) -> anyhow::Result<UasmInstruction> {
+    let (mnemonic, operand) = match line.split_once(',') {
+        Some((mnemonic, operand)) => (mnemonic.trim(), Some(operand.trim())),
+        None => (line, None),
+    };
+
+    let label = |operand: &str| -> anyhow::Result<UasmCodeLabel> {
+        let label = UasmCodeLabel::new(operand.into());
+        if label.address().is_none() {
+            parse_identifier(operand)?;
+        }
+        Ok(label)
+    };
+    let var_name = |operand: &str| -> anyhow::Result<UasmVarName> {
+        Ok(UasmVarName::new(parse_identifier(operand)?.into()))
+    };
+
+    let opcode = match (mnemonic, operand) {
+        ("NOP", None) => UasmOpcode::Nop,
+        ("POP", None) => UasmOpcode::Pop,
+        ("COPY", None) => UasmOpcode::Copy,
+        ("PUSH", Some(operand)) => UasmOpcode::Push(var_name(operand)?),
+        ("JUMP", Some(operand)) => UasmOpcode::Jump(label(operand)?),
+        ("JUMP_IF_FALSE", Some(operand)) => UasmOpcode::JumpIfFalse(label(operand)?),
+        ("ANNOTATION", Some(operand)) => UasmOpcode::Annotation(var_name(operand)?),
+        ("JUMP_INDIRECT", Some(operand)) => UasmOpcode::JumpIndirect(var_name(operand)?),
+        ("EXTERN", Some(operand)) => {
+            UasmOpcode::Extern(ExternSignature::parse(&parse_string(operand)?)?)
+        }
+        ("NOP" | "POP" | "COPY", Some(_)) => {
+            anyhow::bail!("Unexpected operand for {}", mnemonic)
+        }
+        ("PUSH" | "JUMP" | "JUMP_IF_FALSE" | "JUMP_INDIRECT" | "EXTERN" | "ANNOTATION", None) => {
+            anyhow::bail!("Missing operand for {}", mnemonic)
+        }
+        _ => anyhow::bail!("Unknown opcode: {}", mnemonic),
+    };
+
+    Ok(UasmInstruction::new(opcode))
+}
+
+fn parse_identifier(name: &str) -> anyhow::Result<&str> {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if !is_identifier {
+        anyhow::bail!("Invalid identifier: {:?}", name)
+    }
+
+    Ok(name)
+}
+
+/// Remove a comment outside of string literals.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udon::uasm::printer::{UasmPrinter, UasmStyle};
+
+    const SOURCE: &str = "\
+.data_start
+  .export hp
+  .sync hp, linear
+  hp: %SystemSingle, 1.5
+  .sync count, none
+  count: %SystemInt32, -0x10
+  big: %SystemUInt64, 18446744073709551615
+  message: %SystemString, \"a # b, \\\"c\\\"\"
+  target: %UnityEngineTransform, this
+  items: %SystemObjectArray, null
+.data_end
+
+.code_start
+  .export _start
+  _start:
+    PUSH, message
+    EXTERN, \"UnityEngineDebug.__Log__SystemObject__SystemVoid\"
+    JUMP_IF_FALSE, _loop # not taken
+  _loop:
+    ANNOTATION, count
+    JUMP_INDIRECT, count
+    JUMP, 0xFFFFFFFC
+.code_end
+";
+
+    #[test]
+    fn round_trips_through_the_printer() {
+        let uasm: Uasm = SOURCE.parse().unwrap();
+
+        for printer in [
+            UasmPrinter::new(),
+            UasmPrinter::new().with_style(UasmStyle::Compact),
+            UasmPrinter::new().with_comments(true),
+        ] {
+            let text = printer.print(&uasm);
+            let reparsed: Uasm = text.parse().unwrap();
+
+            assert_eq!(printer.print(&reparsed), text);
+        }
+    }
+
+    #[test]
+    fn reads_values_and_attributes() {
+        let uasm: Uasm = SOURCE.parse().unwrap();
+        let data = uasm.data_section.as_ref().unwrap().get_data();
+
+        assert_eq!(
+            data[0].attribute,
+            UasmDataAttribute {
+                exported: true,
+                sync: Some(UasmDataAttributeSync::Linear),
+            }
+        );
+        assert_eq!(
+            data[1].attribute,
+            UasmDataAttribute::sync(UasmDataAttributeSync::None)
+        );
+        assert_eq!(data[2].attribute, UasmDataAttribute::default());
+        assert!(matches!(data[1].variable.value, UasmValue::Int(-16)));
+        assert!(matches!(data[2].variable.value, UasmValue::UInt(u64::MAX)));
+        assert!(
+            matches!(&data[3].variable.value, UasmValue::String(message) if message == "a # b, \"c\"")
+        );
+
+        let code = uasm.code_section.as_ref().unwrap().get_code();
+        let labels: Vec<_> = code
+            .blocks()
+            .map(|(label, block)| (format!("{}", label), block.is_exported()))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (String::from("_start"), true),
+                (String::from("_loop"), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        let error = |src: &str| format!("{}", src.parse::<Uasm>().unwrap_err());
+
+        assert_eq!(
+            error(".data_start\n  x: %SystemInt32, 0\n  x y\n.data_end\n")
+                .split(" at ")
+                .last(),
+            Some("line 3")
+        );
+        assert!(error(".code_start\n  PUSH, x\n.code_end\n").contains("outside of a block"));
+        assert!(error(".data_start\n").contains("Unterminated section"));
+        assert_eq!(
+            error(".data_start\n  .sync x, none\n.data_end\n"),
+            "Unknown variable: x at line 3"
+        );
+        assert_eq!(
+            error(".code_start\n  .export a\n  b:\n    NOP\n.code_end\n"),
+            "Unknown label: a at line 5"
+        );
+        assert_eq!(
+            error(".code_start\n  a:\n    NOP\n  a:\n    NOP\n.code_end\n"),
+            "Duplicate label: a at line 4"
+        );
+        assert_eq!(
+            error(".code_start\n  a:\n  a:\n.code_end\n"),
+            "Duplicate label: a at line 3"
+        );
+    }
+}
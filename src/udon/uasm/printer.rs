@@ -0,0 +1,283 @@
+//! Write [`Uasm`] as Udon Assembly text.
+//!
+//! The [`fmt::Display`] impls of the sections use [`UasmPrinter::default`].
+
+use ::alloc::{string::String, vec::Vec};
+use ::core::fmt::{self, Write};
+
+use super::data::{UasmCode, UasmCodeSection, UasmDataSection, UasmInstruction};
+use super::Uasm;
+
+/// how much whitespace the printer puts around the code
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UasmStyle {
+    /// no indentation, blank lines or spaces after commas
+    Compact,
+    /// indented blocks and sections separated with blank lines
+    #[default]
+    Readable,
+}
+
+/// The printer of Udon Assembly text.
+#[derive(Debug, Clone)]
+pub struct UasmPrinter {
+    /// the number of spaces for each level of indentation in [`UasmStyle::Readable`]
+    indent: usize,
+    style: UasmStyle,
+    /// whether to annotate the code with the addresses and the wasm offsets
+    comments: bool,
+}
+
+impl Default for UasmPrinter {
+    fn default() -> UasmPrinter {
+        UasmPrinter {
+            indent: 2,
+            style: UasmStyle::default(),
+            comments: false,
+        }
+    }
+}
+
+impl UasmPrinter {
+    pub fn new() -> UasmPrinter {
+        UasmPrinter::default()
+    }
+
+    pub fn with_indent(self, indent: usize) -> UasmPrinter {
+        UasmPrinter { indent, ..self }
+    }
+
+    pub fn with_style(self, style: UasmStyle) -> UasmPrinter {
+        UasmPrinter { style, ..self }
+    }
+
+    pub fn with_comments(self, comments: bool) -> UasmPrinter {
+        UasmPrinter { comments, ..self }
+    }
+
+    pub fn print(&self, uasm: &Uasm) -> String {
+        self.print_with_lines(uasm).0
+    }
+
+    /// Print `uasm`, along with the line of each instruction in the text, starting from 1,
+    /// in the order of the code section.
+    pub fn print_with_lines(&self, uasm: &Uasm) -> (String, Vec<usize>) {
+        let mut text = String::new();
+        let mut lines = Vec::new();
+        self.write_uasm_with_lines(&mut LineWriter::new(&mut text), uasm, &mut lines)
+            .expect("writing into a String doesn't fail");
+
+        (text, lines)
+    }
+
+    pub fn write_uasm(&self, f: &mut impl Write, uasm: &Uasm) -> fmt::Result {
+        self.write_uasm_with_lines(&mut LineWriter::new(f), uasm, &mut Vec::new())
+    }
+
+    fn write_uasm_with_lines(
+        &self,
+        f: &mut LineWriter<'_, impl Write>,
+        uasm: &Uasm,
+        lines: &mut Vec<usize>,
+    ) -> fmt::Result {
+        if let Some(data_section) = &uasm.data_section {
+            writeln!(f, ".data_start")?;
+            self.write_data_section(f, data_section)?;
+            writeln!(f, ".data_end")?;
+        }
+
+        if let Some(code_section) = &uasm.code_section {
+            if uasm.data_section.is_some() && self.style == UasmStyle::Readable {
+                writeln!(f)?;
+            }
+            writeln!(f, ".code_start")?;
+            self.write_code(f, code_section.get_code(), lines)?;
+            writeln!(f, ".code_end")?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the declarations of a data section, without `.data_start` and `.data_end`.
+    pub fn write_data_section(
+        &self,
+        f: &mut impl Write,
+        data_section: &UasmDataSection,
+    ) -> fmt::Result {
+        for data in data_section.get_data().iter() {
+            if data.attribute.exported {
+                self.write_indent(f, 1)?;
+                writeln!(f, ".export {}", data.variable.name)?;
+            }
+            if let Some(sync) = &data.attribute.sync {
+                self.write_indent(f, 1)?;
+                writeln!(
+                    f,
+                    ".sync {}{}{}",
+                    data.variable.name,
+                    self.separator(),
+                    sync
+                )?;
+            }
+
+            self.write_indent(f, 1)?;
+            writeln!(
+                f,
+                "{}: {}{}{}",
+                data.variable.name,
+                data.variable.ty,
+                self.separator(),
+                data.variable.value
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the blocks of a code section, without `.code_start` and `.code_end`.
+    pub fn write_code_section(
+        &self,
+        f: &mut impl Write,
+        code_section: &UasmCodeSection,
+    ) -> fmt::Result {
+        self.write_code(
+            &mut LineWriter::new(f),
+            code_section.get_code(),
+            &mut Vec::new(),
+        )
+    }
+
+    /// Write the blocks of `code`, pushing the line of each instruction to `lines`.
+    fn write_code(
+        &self,
+        f: &mut LineWriter<'_, impl Write>,
+        code: &UasmCode,
+        lines: &mut Vec<usize>,
+    ) -> fmt::Result {
+        for (address, label, block) in code.blocks_with_addresses() {
+            if self.comments {
+                self.write_indent(f, 1)?;
+                writeln!(f, "# 0x{:08X}", address)?;
+
+                for line in block.get_comment().into_iter().flat_map(str::lines) {
+                    self.write_indent(f, 1)?;
+                    writeln!(f, "# {}", line)?;
+                }
+            }
+            if block.is_exported() {
+                self.write_indent(f, 1)?;
+                writeln!(f, ".export {}", label)?;
+            }
+            self.write_indent(f, 1)?;
+            writeln!(f, "{}:", label)?;
+
+            for instruction in block.get_instructions().iter() {
+                lines.push(f.line);
+                self.write_indent(f, 2)?;
+                self.write_instruction(f, instruction)?;
+                if let (true, Some(offset)) = (self.comments, instruction.offset) {
+                    write!(f, " # wasm 0x{:X}", offset)?;
+                }
+                writeln!(f)?;
+            }
+
+            if self.comments && block.falls_through() {
+                self.write_indent(f, 2)?;
+                writeln!(f, "# falls through")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_instruction(&self, f: &mut impl Write, instruction: &UasmInstruction) -> fmt::Result {
+        let opcode = &instruction.opcode;
+
+        f.write_str(opcode.mnemonic())?;
+        if let Some(operand) = opcode.operand() {
+            write!(f, "{}{}", self.separator(), operand)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_indent(&self, f: &mut impl Write, level: usize) -> fmt::Result {
+        if self.style == UasmStyle::Readable {
+            write!(f, "{:1$}", "", self.indent * level)?;
+        }
+
+        Ok(())
+    }
+
+    fn separator(&self) -> &'static str {
+        match self.style {
+            UasmStyle::Compact => ",",
+            UasmStyle::Readable => ", ",
+        }
+    }
+}
+
+/// A writer counting the lines it writes, starting from 1.
+struct LineWriter<'a, W> {
+    inner: &'a mut W,
+    /// the line being written
+    line: usize,
+}
+
+impl<'a, W: Write> LineWriter<'a, W> {
+    fn new(inner: &'a mut W) -> LineWriter<'a, W> {
+        LineWriter { inner, line: 1 }
+    }
+}
+
+impl<W: Write> Write for LineWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.line += s.matches('\n').count();
+        self.inner.write_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_line_of_each_instruction() {
+        let uasm: Uasm = "\
+.data_start
+  .export x
+  x: %SystemInt32, 0
+.data_end
+
+.code_start
+  .export _start
+  _start:
+    PUSH, x
+    PUSH, x
+    COPY
+  _next:
+    JUMP, 0xFFFFFFFC
+.code_end
+"
+        .parse()
+        .unwrap();
+
+        for printer in [
+            UasmPrinter::new(),
+            UasmPrinter::new().with_style(UasmStyle::Compact),
+            UasmPrinter::new().with_comments(true).with_indent(4),
+        ] {
+            let (text, lines) = printer.print_with_lines(&uasm);
+            let printed: Vec<_> = lines
+                .iter()
+                .map(|line| text.lines().nth(line - 1).unwrap().trim())
+                .collect();
+
+            assert_eq!(text, printer.print(&uasm));
+            assert!(printed[0].starts_with("PUSH,"), "{text}");
+            assert!(printed[1].starts_with("PUSH,"), "{text}");
+            assert_eq!(printed[2], "COPY");
+            assert!(printed[3].starts_with("JUMP,"), "{text}");
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use ::alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use ::core::fmt;
+
+use super::data::{UasmInstruction, UasmOpcode, UasmType, UasmVarName};
+
+/// The signature of an Udon extern.
+///
+/// Udon identifies an extern with a mangled name such as
+/// `SystemObjectArray.__SetValue__SystemObject_SystemInt32__SystemVoid`,
+/// which has the layout `{class}.__{method}__{params}__{ret}`.
+/// The `{params}` segment is omitted when the method takes no parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExternSignature {
+    /// the Udon type name of the declaring class (e.g. `UnityEngineDebug`)
+    pub class: String,
+    /// the method name (e.g. `Log`, `get_deltaTime` or `ctor`)
+    pub method: String,
+    /// the types of the parameters, excluding the instance
+    pub params: Vec<UasmType>,
+    /// the type of the result (`None` means `SystemVoid`)
+    pub ret: Option<UasmType>,
+    /// whether the extern takes an instance, which the name doesn't tell
+    ///
+    /// It's known for the externs called through [`ExternSignature::call`],
+    /// and unknown (`None`) for the ones parsed from their name.
+    pub instance: Option<bool>,
+}
+
+/// The Udon type name of the result of a method returning nothing.
+const VOID: &str = "SystemVoid";
+
+impl ExternSignature {
+    pub fn new(
+        class: impl Into<String>,
+        method: impl Into<String>,
+        params: Vec<UasmType>,
+        ret: Option<UasmType>,
+    ) -> ExternSignature {
+        ExternSignature {
+            class: class.into(),
+            method: method.into(),
+            params,
+            ret,
+            instance: None,
+        }
+    }
+
+    /// Parse an extern name like `UnityEngineDebug.__Log__SystemObject__SystemVoid`.
+    pub fn parse(name: &str) -> anyhow::Result<ExternSignature> {
+        let (class, rest) = name
+            .split_once('.')
+            .ok_or_else(|| anyhow::anyhow!("Missing class name in extern: {:?}", name))?;
+
+        let rest = rest
+            .strip_prefix("__")
+            .ok_or_else(|| anyhow::anyhow!("Missing method name in extern: {:?}", name))?;
+
+        let segments = rest.split("__").collect::<Vec<_>>();
+
+        let (method, params, ret) = match segments.as_slice() {
+            [method, ret] => (*method, None, *ret),
+            [method, params, ret] => (*method, Some(*params), *ret),
+            _ => anyhow::bail!("Malformed extern name: {:?}", name),
+        };
+
+        // a result starting with `_` comes from a `___` separator
+        if class.is_empty() || method.is_empty() || ret.is_empty() || ret.starts_with('_') {
+            anyhow::bail!("Malformed extern name: {:?}", name)
+        }
+
+        let params = match params {
+            Some(params) => params
+                .split('_')
+                .map(|param| {
+                    if param.is_empty() {
+                        anyhow::bail!("Empty parameter type in extern: {:?}", name)
+                    }
+                    Ok(UasmType::from_udon_name(param))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+
+        let ret = if ret == VOID {
+            None
+        } else {
+            Some(UasmType::from_udon_name(ret))
+        };
+
+        Ok(ExternSignature::new(class, method, params, ret))
+    }
+
+    /// Whether the extern pushes its result into a variable.
+    pub fn has_result(&self) -> bool {
+        self.ret.is_some()
+    }
+
+    /// Build the instructions calling this extern.
+    ///
+    /// Udon expects the operands on the stack in this order:
+    /// the instance (if any), the arguments from left to right and finally the variable receiving the result.
+    pub fn call(
+        &self,
+        instance: Option<&UasmVarName>,
+        args: &[UasmVarName],
+        result: Option<&UasmVarName>,
+    ) -> anyhow::Result<Vec<UasmInstruction>> {
+        if args.len() != self.params.len() {
+            anyhow::bail!(
+                "Wrong number of arguments for {}: expected {}, got {}",
+                self,
+                self.params.len(),
+                args.len()
+            )
+        }
+
+        if result.is_some() != self.has_result() {
+            anyhow::bail!("Result variable mismatch for {}", self)
+        }
+
+        let instance_given = instance.is_some();
+        match self.instance {
+            Some(true) if !instance_given => anyhow::bail!("Missing instance for {}", self),
+            Some(false) if instance_given => anyhow::bail!("{} takes no instance", self),
+            _ => {}
+        }
+
+        let mut instructions = instance
+            .into_iter()
+            .chain(args.iter())
+            .chain(result)
+            .map(|var_name| UasmInstruction::new(UasmOpcode::Push(var_name.clone())))
+            .collect::<Vec<_>>();
+
+        instructions.push(UasmInstruction::new(UasmOpcode::Extern(ExternSignature {
+            instance: Some(instance_given),
+            ..self.clone()
+        })));
+
+        Ok(instructions)
+    }
+}
+
+impl fmt::Display for ExternSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.__{}__", self.class, self.method)?;
+
+        if !self.params.is_empty() {
+            let params = self
+                .params
+                .iter()
+                .map(|param| param.udon_name().to_string())
+                .collect::<Vec<_>>()
+                .join("_");
+            write!(f, "{params}__")?;
+        }
+
+        match &self.ret {
+            Some(ret) => write!(f, "{}", ret.udon_name()),
+            None => write!(f, "{VOID}"),
+        }
+    }
+}
+
+impl TryFrom<&str> for ExternSignature {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        ExternSignature::parse(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::alloc::{format, vec};
+
+    #[test]
+    fn parses_extern_names() {
+        let signature = ExternSignature::parse(
+            "SystemInt32.__op_Addition__SystemInt32_SystemInt32__SystemInt32",
+        )
+        .unwrap();
+        assert_eq!(
+            signature,
+            ExternSignature::new(
+                "SystemInt32",
+                "op_Addition",
+                vec![UasmType::Int32, UasmType::Int32],
+                Some(UasmType::Int32)
+            )
+        );
+
+        let signature =
+            ExternSignature::parse("UnityEngineTime.__get_deltaTime__SystemSingle").unwrap();
+        assert!(signature.params.is_empty());
+        assert_eq!(signature.ret, Some(UasmType::Single));
+
+        let signature =
+            ExternSignature::parse("UnityEngineDebug.__Log__SystemObject__SystemVoid").unwrap();
+        assert_eq!(signature.params, [UasmType::Object]);
+        assert!(!signature.has_result());
+    }
+
+    #[test]
+    fn prints_back_the_extern_name() {
+        for name in [
+            "SystemInt32.__op_Addition__SystemInt32_SystemInt32__SystemInt32",
+            "UnityEngineTime.__get_deltaTime__SystemSingle",
+            "UnityEngineDebug.__Log__SystemObject__SystemVoid",
+            "SystemObjectArray.__ctor__SystemInt32__SystemObjectArray",
+            "UnityEngineTransform.__set_position__UnityEngineVector3__SystemVoid",
+        ] {
+            assert_eq!(format!("{}", ExternSignature::parse(name).unwrap()), name);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_extern_names() {
+        for name in [
+            "UnityEngineDebug",
+            "UnityEngineDebug.Log__SystemVoid",
+            ".__Log__SystemVoid",
+            "UnityEngineDebug.__Log",
+            "UnityEngineDebug.__Log__SystemObject__SystemVoid__SystemVoid",
+            "UnityEngineDebug.__Log__SystemObject___SystemVoid",
+            "UnityEngineDebug.__Log____SystemVoid",
+        ] {
+            assert!(ExternSignature::parse(name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn pushes_the_operands_in_order() {
+        let signature =
+            ExternSignature::parse("SystemObjectArray.__Get__SystemInt32__SystemObject").unwrap();
+        let var = |name: &str| UasmVarName::new(name.into());
+
+        let instructions = signature
+            .call(Some(&var("array")), &[var("index")], Some(&var("value")))
+            .unwrap()
+            .iter()
+            .map(|instruction| format!("{}", instruction))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            instructions,
+            [
+                "PUSH, array",
+                "PUSH, index",
+                "PUSH, value",
+                "EXTERN, \"SystemObjectArray.__Get__SystemInt32__SystemObject\"",
+            ]
+        );
+
+        assert!(signature.call(None, &[], Some(&var("value"))).is_err());
+        assert!(signature.call(None, &[var("index")], None).is_err());
+    }
+
+    #[test]
+    fn records_whether_the_call_takes_an_instance() {
+        let signature =
+            ExternSignature::parse("UnityEngineTime.__get_deltaTime__SystemSingle").unwrap();
+        let var = |name: &str| UasmVarName::new(name.into());
+
+        let instructions = signature.call(None, &[], Some(&var("value"))).unwrap();
+        let Some(UasmOpcode::Extern(called)) = instructions.last().map(|i| &i.opcode) else {
+            panic!("the call ends with the extern")
+        };
+        assert_eq!(called.instance, Some(false));
+
+        // a known signature checks the instance
+        assert!(called
+            .call(Some(&var("time")), &[], Some(&var("value")))
+            .is_err());
+    }
+}
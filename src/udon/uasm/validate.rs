@@ -0,0 +1,437 @@
+//! Check generated Udon Assembly before UdonVM gets to run it.
+//!
+//! UdonVM loads a program with undefined names or an unbalanced stack without complaint
+//! and only fails (or silently misbehaves) once the broken code runs in a world,
+//! so the translator runs [`validate`] on its output in debug builds.
+
+use ::alloc::{format, string::String, vec::Vec};
+use hashbrown::{HashMap, HashSet};
+
+use super::data::{UasmCodeBlock, UasmCodeLabel, UasmOpcode, UasmType, UasmVarName};
+use super::{ExternSignature, Uasm};
+use crate::udon::extern_db::ExternDatabase;
+
+/// Check `uasm`, once its aliases are resolved.
+///
+/// The externs are only checked against the known signatures when `extern_db` is given.
+/// Whether an extern takes an instance is known for the code built by the translator
+/// (see [`ExternSignature::instance`]), and otherwise only from `extern_db`.
+/// When neither tells, the operands below the arguments of the extern aren't checked.
+pub fn validate(uasm: &Uasm, extern_db: Option<&ExternDatabase>) -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+
+    let mut variables = HashMap::new();
+    if let Some(data_section) = &uasm.data_section {
+        for data in data_section.get_data().iter() {
+            let name = &data.variable.name;
+            if variables.insert(name, &data.variable.ty).is_some() {
+                errors.push(format!("Duplicate variable: {}", name));
+            }
+        }
+    }
+
+    if let Some(code_section) = &uasm.code_section {
+        let mut labels = HashSet::new();
+        for (label, _) in code_section.get_code().blocks() {
+            if !labels.insert(label) {
+                errors.push(format!("Duplicate label: {}", label));
+            }
+        }
+
+        let validator = Validator {
+            variables: &variables,
+            labels: &labels,
+            extern_db,
+        };
+        for (label, block) in code_section.get_code().blocks() {
+            validator.validate_block(label, block, &mut errors);
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "{} error(s) in Udon Assembly:\n{}",
+        errors.len(),
+        errors.join("\n")
+    )
+}
+
+struct Validator<'a> {
+    variables: &'a HashMap<&'a UasmVarName, &'a UasmType>,
+    labels: &'a HashSet<&'a UasmCodeLabel>,
+    extern_db: Option<&'a ExternDatabase>,
+}
+
+impl Validator<'_> {
+    /// Check the instructions of a block, which must leave the stack empty.
+    fn validate_block(
+        &self,
+        label: &UasmCodeLabel,
+        block: &UasmCodeBlock,
+        errors: &mut Vec<String>,
+    ) {
+        let mut stack: Vec<&UasmVarName> = Vec::new();
+
+        for (index, instruction) in block.get_instructions().iter().enumerate() {
+            let mut error = |message: String| {
+                errors.push(format!("{}+{}: {}: {}", label, index, instruction, message))
+            };
+
+            let effect = instruction.opcode.stack_effect(self.extern_db);
+
+            if let UasmOpcode::Extern(signature) = &instruction.opcode {
+                let pops = effect.map(|effect| effect.pops);
+                for message in self.validate_extern(signature, pops, &mut stack) {
+                    error(message);
+                }
+                continue;
+            }
+
+            let effect = effect.expect("only an extern can have an unknown effect");
+            if stack.len() < effect.pops {
+                error(String::from("Stack underflow"));
+                stack.clear();
+            } else {
+                let operands = stack.split_off(stack.len() - effect.pops);
+                if let Some(message) = self.check_operands(&instruction.opcode, &operands) {
+                    error(message);
+                }
+            }
+
+            if let UasmOpcode::Push(var_name) = &instruction.opcode {
+                stack.push(var_name);
+            }
+
+            if matches!(
+                instruction.opcode,
+                UasmOpcode::Jump(_) | UasmOpcode::JumpIndirect(_)
+            ) && !stack.is_empty()
+            {
+                error(format!("{} value(s) left on the stack", stack.len()));
+                stack.clear();
+            }
+        }
+
+        if !stack.is_empty() {
+            errors.push(format!(
+                "{}: {} value(s) left on the stack at the end of the block",
+                label,
+                stack.len()
+            ));
+        }
+    }
+
+    /// Check an opcode other than `EXTERN` and the operands it pops.
+    fn check_operands(&self, opcode: &UasmOpcode, operands: &[&UasmVarName]) -> Option<String> {
+        match (opcode, operands) {
+            (UasmOpcode::Push(var_name) | UasmOpcode::Annotation(var_name), [])
+                if !self.variables.contains_key(var_name) =>
+            {
+                Some(format!("Undeclared variable: {}", var_name))
+            }
+            (UasmOpcode::Copy, [src, dst]) => self.check_assignable(src, self.ty(dst)),
+            (UasmOpcode::JumpIfFalse(target), [condition]) => self
+                .check_assignable(condition, Some(&UasmType::Boolean))
+                .or_else(|| self.check_label(target)),
+            (UasmOpcode::Jump(target), []) => self.check_label(target),
+            (UasmOpcode::JumpIndirect(var_name), []) => match self.ty(var_name) {
+                None => Some(format!("Undeclared variable: {}", var_name)),
+                Some(UasmType::UInt32) => None,
+                Some(ty) => Some(format!("Jumping to an address of type {}", ty)),
+            },
+            _ => None,
+        }
+    }
+
+    /// Pop the `pops` operands of an extern and check them against its signature.
+    ///
+    /// The operands are popped even when the extern is wrong, so that its errors don't cascade into the next instructions.
+    /// When `pops` is unknown, the arguments and the result are checked,
+    /// and the rest of the stack is dropped, as it may or may not hold the instance.
+    fn validate_extern(
+        &self,
+        signature: &ExternSignature,
+        pops: Option<usize>,
+        stack: &mut Vec<&UasmVarName>,
+    ) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if let Some(extern_db) = self.extern_db {
+            match extern_db.validate(signature) {
+                Ok(entry)
+                    if signature
+                        .instance
+                        .is_some_and(|instance| instance != entry.instance) =>
+                {
+                    errors.push(format!(
+                        "Called {} an instance, but the extern database says otherwise",
+                        if entry.instance { "without" } else { "with" }
+                    ))
+                }
+                Ok(_) => {}
+                Err(err) => errors.push(format!("{}", err)),
+            }
+        }
+
+        let arity = signature.params.len() + usize::from(signature.has_result());
+        let count = pops.unwrap_or(arity);
+        if stack.len() < count {
+            errors.push(format!(
+                "Expected {} operand(s) on the stack, found {}",
+                count,
+                stack.len()
+            ));
+            stack.clear();
+            return errors;
+        }
+
+        let operands = stack.split_off(stack.len() - arity);
+        let (args, result) = operands.split_at(signature.params.len());
+
+        for (arg, param) in args.iter().zip(signature.params.iter()) {
+            errors.extend(self.check_assignable(arg, Some(param)));
+        }
+
+        if let (Some(result), Some(ret)) = (result.first(), &signature.ret) {
+            if let Some(ty) = self.ty(result) {
+                if !is_assignable(ret, ty) {
+                    errors.push(format!(
+                        "Result of type {} stored in {} of type {}",
+                        ret, result, ty
+                    ));
+                }
+            }
+        }
+
+        match pops {
+            Some(pops) if pops > arity => {
+                let instance = stack.pop().expect("the operands were counted");
+                let class = UasmType::from_udon_name(&signature.class);
+                errors.extend(
+                    self.check_assignable(instance, Some(&class))
+                        .map(|message| format!("Instance: {}", message)),
+                );
+            }
+            Some(_) => {}
+            None => stack.clear(),
+        }
+
+        errors
+    }
+
+    fn ty(&self, var_name: &UasmVarName) -> Option<&UasmType> {
+        self.variables.get(var_name).copied()
+    }
+
+    /// Check that the value in `var_name` can be used as a `ty`.
+    ///
+    /// Undeclared variables are reported by their `PUSH`.
+    fn check_assignable(&self, var_name: &UasmVarName, ty: Option<&UasmType>) -> Option<String> {
+        match (self.ty(var_name), ty) {
+            (Some(from), Some(to)) if !is_assignable(from, to) => {
+                Some(format!("{} of type {} used as {}", var_name, from, to))
+            }
+            _ => None,
+        }
+    }
+
+    fn check_label(&self, label: &UasmCodeLabel) -> Option<String> {
+        if label.address().is_some() || self.labels.contains(label) {
+            None
+        } else {
+            Some(format!("Undefined label: {}", label))
+        }
+    }
+}
+
+/// Whether a value of type `from` can be stored in a variable of type `to`.
+///
+/// Without the class hierarchy, any reference is taken as assignable to any other one.
+fn is_assignable(from: &UasmType, to: &UasmType) -> bool {
+    from == to || *to == UasmType::Object || (from.is_reference() && to.is_reference())
+}
+
+#[cfg(test)]
+mod tests {
+    use ::alloc::{string::ToString, vec};
+
+    use super::*;
+    use crate::udon::extern_db::ExternEntry;
+    use crate::udon::uasm::data::{
+        UasmCode, UasmCodeSection, UasmData, UasmDataAttribute, UasmDataSection, UasmInstruction,
+        UasmValue, UasmVariable,
+    };
+
+    fn var(name: &str) -> UasmVarName {
+        UasmVarName::new(name.into())
+    }
+
+    fn label(name: &str) -> UasmCodeLabel {
+        UasmCodeLabel::new(name.into())
+    }
+
+    /// Build a program declaring `variables`, with a single block `_start` running `instructions`.
+    fn program(variables: &[(&str, UasmType)], instructions: Vec<UasmInstruction>) -> Uasm {
+        let mut data_section = UasmDataSection::new();
+        for (name, ty) in variables {
+            data_section.push_data(&UasmData {
+                attribute: UasmDataAttribute::default(),
+                variable: UasmVariable {
+                    name: var(name),
+                    ty: ty.clone(),
+                    value: UasmValue::Null,
+                },
+            });
+        }
+
+        let mut block = UasmCodeBlock::new();
+        for instruction in &instructions {
+            block.push_instruction(instruction);
+        }
+        let code = UasmCode::from_blocks([(label("_start"), block)]).unwrap();
+
+        Uasm::new(Some(data_section), Some(UasmCodeSection::new(code)))
+    }
+
+    fn push(name: &str) -> UasmInstruction {
+        UasmInstruction::new(UasmOpcode::Push(var(name)))
+    }
+
+    fn halt() -> UasmInstruction {
+        UasmInstruction::new(UasmOpcode::Jump(UasmCodeLabel::from_address(0xFFFF_FFFC)))
+    }
+
+    fn errors(uasm: &Uasm, extern_db: Option<&ExternDatabase>) -> String {
+        validate(uasm, extern_db).unwrap_err().to_string()
+    }
+
+    const GET_POSITION: &str = "UnityEngineTransform.__get_position__UnityEngineVector3";
+
+    fn extern_db() -> ExternDatabase {
+        let mut extern_db = ExternDatabase::new();
+        extern_db.insert(ExternEntry {
+            signature: ExternSignature::parse(GET_POSITION).unwrap(),
+            instance: true,
+            exposed: true,
+        });
+
+        extern_db
+    }
+
+    fn get_position(instance: Option<&str>) -> Vec<UasmInstruction> {
+        ExternSignature::parse(GET_POSITION)
+            .unwrap()
+            .call(instance.map(var).as_ref(), &[], Some(&var("position")))
+            .unwrap()
+    }
+
+    const VARIABLES: &[(&str, UasmType)] = &[
+        ("a", UasmType::Int32),
+        ("b", UasmType::Int32),
+        ("flag", UasmType::Boolean),
+    ];
+
+    #[test]
+    fn accepts_balanced_code() {
+        let mut instructions = vec![push("a"), push("b"), UasmInstruction::new(UasmOpcode::Copy)];
+        instructions.extend(get_position(Some("transform")));
+        instructions.push(halt());
+
+        let uasm = program(
+            &[
+                ("a", UasmType::Int32),
+                ("b", UasmType::Int32),
+                ("transform", UasmType::Other("UnityEngineTransform".into())),
+                ("position", UasmType::Other("UnityEngineVector3".into())),
+            ],
+            instructions,
+        );
+
+        validate(&uasm, None).unwrap();
+        validate(&uasm, Some(&extern_db())).unwrap();
+    }
+
+    #[test]
+    fn reports_unbalanced_code() {
+        let uasm = program(VARIABLES, vec![push("a"), halt()]);
+        assert!(errors(&uasm, None).contains("1 value(s) left on the stack"));
+
+        let uasm = program(
+            VARIABLES,
+            vec![push("a"), UasmInstruction::new(UasmOpcode::Copy)],
+        );
+        assert!(errors(&uasm, None).contains("Stack underflow"));
+    }
+
+    #[test]
+    fn reports_unknown_labels() {
+        let jump = UasmInstruction::new(UasmOpcode::Jump(label("nowhere")));
+        let uasm = program(VARIABLES, vec![jump]);
+        assert!(errors(&uasm, None).contains("Undefined label: nowhere"));
+
+        let jump = UasmInstruction::new(UasmOpcode::JumpIfFalse(label("nowhere")));
+        let uasm = program(VARIABLES, vec![push("flag"), jump, halt()]);
+        assert!(errors(&uasm, None).contains("Undefined label: nowhere"));
+    }
+
+    #[test]
+    fn rejects_duplicate_labels() {
+        // the code can't hold two blocks with the same label,
+        // so they're rejected when it's built, before it gets validated
+        let blocks = [
+            (label("_start"), UasmCodeBlock::new()),
+            (label("_start"), UasmCodeBlock::new()),
+        ];
+        assert!(UasmCode::from_blocks(blocks).is_err());
+
+        let src = ".code_start\n  a:\n    NOP\n  a:\n    NOP\n.code_end\n";
+        assert!(src.parse::<Uasm>().is_err());
+    }
+
+    #[test]
+    fn checks_the_instance_of_an_extern() {
+        let variables = [
+            ("a", UasmType::Int32),
+            ("transform", UasmType::Other("UnityEngineTransform".into())),
+            ("position", UasmType::Other("UnityEngineVector3".into())),
+        ];
+
+        let mut instructions = get_position(Some("a"));
+        instructions.push(halt());
+        let uasm = program(&variables, instructions);
+        assert!(errors(&uasm, None).contains("Instance: a of type %SystemInt32"));
+
+        // the extern database tells the instance of a parsed extern
+        let mut instructions = vec![push("transform"), push("position")];
+        instructions.push(UasmInstruction::new(UasmOpcode::Extern(
+            ExternSignature::parse(GET_POSITION).unwrap(),
+        )));
+        instructions.push(halt());
+        let uasm = program(&variables, instructions);
+        validate(&uasm, Some(&extern_db())).unwrap();
+        // without it, the operand below the result isn't checked
+        validate(&uasm, None).unwrap();
+
+        let mut instructions = get_position(None);
+        instructions.push(halt());
+        let uasm = program(&variables, instructions);
+        assert!(errors(&uasm, Some(&extern_db())).contains("Called without an instance"));
+    }
+
+    #[test]
+    fn pops_the_operands_of_a_failed_extern() {
+        let mut instructions = vec![push("a")];
+        instructions.push(UasmInstruction::new(UasmOpcode::Extern(
+            ExternSignature::parse("UnityEngineDebug.__Log__SystemObject__SystemVoid").unwrap(),
+        )));
+        instructions.push(halt());
+        let uasm = program(VARIABLES, instructions);
+
+        // only the unknown extern is reported, not the operand it leaves
+        let errors = errors(&uasm, Some(&extern_db()));
+        assert!(errors.starts_with("1 error(s)"), "{errors}");
+    }
+}
@@ -0,0 +1,771 @@
+//! A small UdonVM running a linked program in the tests.
+//!
+//! It implements the externs called by the lowered code and the runtime with the semantics of .NET,
+//! so that the tests can check what the translated code computes rather than how it's written.
+//! An exception thrown by an extern stops the program with an error, as it does in VRChat.
+
+extern crate std;
+
+use ::alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use ::core::cell::RefCell;
+use ::core::cmp::Ordering;
+use hashbrown::HashMap;
+
+use super::data::{UasmCodeLabel, UasmOpcode, UasmType, UasmValue, UasmVarName};
+use super::{ExternSignature, Uasm};
+
+/// The number of instructions a run may execute before it's considered stuck in a loop.
+const MAX_STEPS: usize = 10_000_000;
+
+/// The first address which stops the program when jumped to.
+const HALT_ADDRESS: u32 = 0xFFFF_FFFC;
+
+/// A value held by a variable of the heap.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Boolean(bool),
+    Byte(u8),
+    SByte(i8),
+    Char(u16),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Single(f32),
+    Double(f64),
+    String(String),
+    Array(Rc<RefCell<Vec<Value>>>),
+    /// `System.Text.Encoding.UTF8`
+    Utf8,
+    /// the behaviour running the program
+    This,
+}
+
+impl Value {
+    /// The default value of `ty`, which is `null` for a reference.
+    pub(crate) fn default_of(ty: &UasmType) -> Value {
+        match ty {
+            UasmType::Boolean => Value::Boolean(false),
+            UasmType::Single => Value::Single(0.0),
+            UasmType::Double => Value::Double(0.0),
+            ty => Value::wrapping(ty, 0).unwrap_or(Value::Null),
+        }
+    }
+
+    fn array(values: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(values)))
+    }
+
+    /// The integer held by an integral value.
+    fn integer(&self) -> Option<i128> {
+        Some(match self {
+            Value::Byte(value) => (*value).into(),
+            Value::SByte(value) => (*value).into(),
+            Value::Char(value) => (*value).into(),
+            Value::Int16(value) => (*value).into(),
+            Value::UInt16(value) => (*value).into(),
+            Value::Int32(value) => (*value).into(),
+            Value::UInt32(value) => (*value).into(),
+            Value::Int64(value) => (*value).into(),
+            Value::UInt64(value) => (*value).into(),
+            _ => return None,
+        })
+    }
+
+    fn float(&self) -> Option<f64> {
+        match self {
+            Value::Single(value) => Some((*value).into()),
+            Value::Double(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The integer `value` of `ty`, truncated to its width as the unchecked arithmetic of C# does.
+    fn wrapping(ty: &UasmType, value: i128) -> Option<Value> {
+        Some(match ty {
+            UasmType::Byte => Value::Byte(value as u8),
+            UasmType::SByte => Value::SByte(value as i8),
+            UasmType::Char => Value::Char(value as u16),
+            UasmType::Int16 => Value::Int16(value as i16),
+            UasmType::UInt16 => Value::UInt16(value as u16),
+            UasmType::Int32 => Value::Int32(value as i32),
+            UasmType::UInt32 => Value::UInt32(value as u32),
+            UasmType::Int64 => Value::Int64(value as i64),
+            UasmType::UInt64 => Value::UInt64(value as u64),
+            _ => return None,
+        })
+    }
+
+    /// The integer `value` of `ty`, or `None` if it's out of the range of `ty`.
+    fn checked(ty: &UasmType, value: i128) -> Option<Value> {
+        Value::wrapping(ty, value).filter(|wrapped| wrapped.integer() == Some(value))
+    }
+
+    fn from_uasm(ty: &UasmType, value: &UasmValue) -> anyhow::Result<Value> {
+        let value = match value {
+            UasmValue::Null => Value::default_of(ty),
+            UasmValue::This => Value::This,
+            UasmValue::Bool(value) => Value::Boolean(*value),
+            UasmValue::Int(value) => Value::checked(ty, (*value).into())
+                .ok_or_else(|| anyhow::anyhow!("{} doesn't fit in {}", value, ty))?,
+            UasmValue::UInt(value) => Value::checked(ty, (*value).into())
+                .ok_or_else(|| anyhow::anyhow!("{} doesn't fit in {}", value, ty))?,
+            UasmValue::Float(value) if *ty == UasmType::Double => Value::Double((*value).into()),
+            UasmValue::Float(value) => Value::Single(*value),
+            UasmValue::Double(value) if *ty == UasmType::Single => Value::Single(*value as f32),
+            UasmValue::Double(value) => Value::Double(*value),
+            UasmValue::String(value) => Value::String(value.clone()),
+            UasmValue::Address(label) => anyhow::bail!("Unresolved address: {}", label),
+        };
+
+        Ok(value)
+    }
+
+    fn as_array(&self) -> anyhow::Result<&Rc<RefCell<Vec<Value>>>> {
+        match self {
+            Value::Array(array) => Ok(array),
+            Value::Null => Err(exception("NullReferenceException")),
+            value => anyhow::bail!("Not an array: {:?}", value),
+        }
+    }
+
+    fn as_index(&self) -> anyhow::Result<i128> {
+        match self {
+            Value::Int32(index) => Ok((*index).into()),
+            value => anyhow::bail!("Not an index: {:?}", value),
+        }
+    }
+
+    fn as_str(&self) -> anyhow::Result<&str> {
+        match self {
+            Value::String(string) => Ok(string),
+            Value::Null => Err(exception("NullReferenceException")),
+            value => anyhow::bail!("Not a string: {:?}", value),
+        }
+    }
+
+    /// The text of the value, as `ToString` gives it.
+    fn text(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Boolean(true) => "True".into(),
+            Value::Boolean(false) => "False".into(),
+            Value::String(string) => string.clone(),
+            Value::Single(value) => value.to_string(),
+            Value::Double(value) => value.to_string(),
+            value => match value.integer() {
+                Some(integer) => integer.to_string(),
+                None => format!("{:?}", value),
+            },
+        }
+    }
+}
+
+/// The error of an extern throwing the .NET exception `name`.
+fn exception(name: &str) -> anyhow::Error {
+    anyhow::anyhow!("System.{}", name)
+}
+
+/// Whether two references are the same object, as `object.ReferenceEquals` tells.
+fn same_object(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (Value::Null, Value::Null) | (Value::Utf8, Value::Utf8) | (Value::This, Value::This) => {
+            true
+        }
+        (Value::Array(lhs), Value::Array(rhs)) => Rc::ptr_eq(lhs, rhs),
+        // the literals of a program are interned
+        (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+        // a value boxed twice gives two objects
+        _ => false,
+    }
+}
+
+/// The integers of two operands, which must both be integral.
+fn integers(args: &[Value]) -> Option<(i128, i128)> {
+    Some((args[0].integer()?, args.get(1)?.integer()?))
+}
+
+fn is_signed(ty: &UasmType) -> bool {
+    matches!(
+        ty,
+        UasmType::SByte | UasmType::Int16 | UasmType::Int32 | UasmType::Int64
+    )
+}
+
+fn width(ty: &UasmType) -> u32 {
+    match ty {
+        UasmType::Byte | UasmType::SByte => 8,
+        UasmType::Char | UasmType::Int16 | UasmType::UInt16 => 16,
+        UasmType::Int32 | UasmType::UInt32 => 32,
+        _ => 64,
+    }
+}
+
+/// Run the operator `op` of the class `ty`, e.g. `Addition` for `SystemInt32.__op_Addition__...`.
+fn operator(ty: &UasmType, op: &str, args: &[Value], ret: &UasmType) -> anyhow::Result<Value> {
+    let comparison: Option<fn(Option<Ordering>) -> bool> = match op {
+        "Equality" => Some(|ordering| ordering == Some(Ordering::Equal)),
+        "Inequality" => Some(|ordering| ordering != Some(Ordering::Equal)),
+        "LessThan" => Some(|ordering| ordering == Some(Ordering::Less)),
+        "GreaterThan" => Some(|ordering| ordering == Some(Ordering::Greater)),
+        "LessThanOrEqual" => Some(|ordering| ordering.is_some_and(Ordering::is_le)),
+        "GreaterThanOrEqual" => Some(|ordering| ordering.is_some_and(Ordering::is_ge)),
+        _ => None,
+    };
+
+    if let Some(comparison) = comparison {
+        let ordering = match (&args[0], &args[1]) {
+            (Value::Boolean(lhs), Value::Boolean(rhs)) => Some(lhs.cmp(rhs)),
+            (lhs, rhs) => match (integers(args), lhs.float(), rhs.float()) {
+                (Some((lhs, rhs)), _, _) => Some(lhs.cmp(&rhs)),
+                (_, Some(lhs), Some(rhs)) => lhs.partial_cmp(&rhs),
+                _ if op == "Equality" || op == "Inequality" => {
+                    let equal = match ty {
+                        UasmType::String => lhs == rhs,
+                        _ => same_object(lhs, rhs),
+                    };
+                    equal.then_some(Ordering::Equal)
+                }
+                _ => anyhow::bail!("Can't compare {:?} and {:?}", lhs, rhs),
+            },
+        };
+
+        return Ok(Value::Boolean(comparison(ordering)));
+    }
+
+    match ty {
+        UasmType::Boolean => {
+            let (Value::Boolean(lhs), rhs) = (&args[0], args.get(1)) else {
+                anyhow::bail!("Not a boolean: {:?}", args[0])
+            };
+            let rhs = match rhs {
+                Some(Value::Boolean(rhs)) => *rhs,
+                _ => false,
+            };
+            let value = match op {
+                "LogicalAnd" | "ConditionalAnd" => *lhs && rhs,
+                "LogicalOr" | "ConditionalOr" => *lhs || rhs,
+                "LogicalXor" => *lhs ^ rhs,
+                "UnaryNegation" => !*lhs,
+                _ => anyhow::bail!("Unknown operator of {}: {}", ty, op),
+            };
+
+            Ok(Value::Boolean(value))
+        }
+        UasmType::Single | UasmType::Double => {
+            let lhs = args[0]
+                .float()
+                .ok_or_else(|| anyhow::anyhow!("Not a float: {:?}", args[0]))?;
+            let rhs = args.get(1).and_then(Value::float).unwrap_or_default();
+
+            let value = match op {
+                "Addition" => lhs + rhs,
+                "Subtraction" => lhs - rhs,
+                "Multiplication" => lhs * rhs,
+                "Division" => lhs / rhs,
+                "Modulus" => lhs % rhs,
+                "UnaryMinus" => -lhs,
+                _ => anyhow::bail!("Unknown operator of {}: {}", ty, op),
+            };
+
+            // rounding the result in `double` and then in `float` gives the result in `float`,
+            // as `double` has more than twice the precision of `float`
+            Ok(match ty {
+                UasmType::Single => Value::Single(value as f32),
+                _ => Value::Double(value),
+            })
+        }
+        _ => {
+            let lhs = args[0]
+                .integer()
+                .ok_or_else(|| anyhow::anyhow!("Not an integer: {:?}", args[0]))?;
+            let rhs = args.get(1).and_then(Value::integer).unwrap_or_default();
+            let bits = width(ty);
+
+            let value = match op {
+                "Addition" => lhs.wrapping_add(rhs),
+                "Subtraction" => lhs.wrapping_sub(rhs),
+                "Multiplication" => lhs.wrapping_mul(rhs),
+                "Division" | "Modulus" => {
+                    if rhs == 0 {
+                        return Err(exception("DivideByZeroException"));
+                    }
+                    // `MIN / -1` overflows, and so does `MIN % -1` on .NET
+                    if is_signed(ty) && rhs == -1 && lhs == -(1 << (bits - 1)) {
+                        return Err(exception("OverflowException"));
+                    }
+                    if op == "Division" {
+                        lhs / rhs
+                    } else {
+                        lhs % rhs
+                    }
+                }
+                "LogicalAnd" => lhs & rhs,
+                "LogicalOr" => lhs | rhs,
+                "LogicalXor" => lhs ^ rhs,
+                "OnesComplement" => !lhs,
+                "UnaryMinus" => -lhs,
+                // the count is masked by the width of the type
+                "LeftShift" => lhs << (rhs as u32 & (bits - 1)),
+                "RightShift" => lhs >> (rhs as u32 & (bits - 1)),
+                _ => anyhow::bail!("Unknown operator of {}: {}", ty, op),
+            };
+
+            Value::wrapping(ret, value).ok_or_else(|| anyhow::anyhow!("Not an integer: {}", ret))
+        }
+    }
+}
+
+/// Round to the nearest integer, ties to even, as `System.Math.Round` does.
+fn round_to_even(value: f64) -> f64 {
+    value.round_ties_even()
+}
+
+/// Convert `value` into `to` as `System.Convert.To{to}` does.
+fn convert(value: &Value, to: &UasmType) -> anyhow::Result<Value> {
+    let overflow = || exception("OverflowException");
+
+    let value = match (value, to) {
+        // `Convert.ToInt32((object)null)` gives `0`
+        (Value::Null, to) => Value::default_of(to),
+        (Value::Boolean(value), UasmType::Boolean) => Value::Boolean(*value),
+        (Value::Boolean(value), to) => convert(&Value::Int32((*value).into()), to)?,
+        (value, UasmType::String) => Value::String(value.text()),
+        (Value::String(_), _) => anyhow::bail!("Can't parse a string in the test VM"),
+        (value, UasmType::Boolean) => match (value.integer(), value.float()) {
+            (Some(integer), _) => Value::Boolean(integer != 0),
+            (_, Some(float)) => Value::Boolean(float != 0.0),
+            _ => anyhow::bail!("Can't convert {:?} into {}", value, to),
+        },
+        (value, UasmType::Single | UasmType::Double) => {
+            let single = match (value.integer(), value) {
+                // rounded once, straight from the integer
+                (Some(integer), _) => integer as f32,
+                (_, Value::Single(value)) => *value,
+                (_, Value::Double(value)) => *value as f32,
+                _ => anyhow::bail!("Can't convert {:?} into {}", value, to),
+            };
+            let double = match (value.integer(), value.float()) {
+                (Some(integer), _) => integer as f64,
+                (_, Some(float)) => float,
+                _ => unreachable!("checked above"),
+            };
+
+            match to {
+                UasmType::Single => Value::Single(single),
+                _ => Value::Double(double),
+            }
+        }
+        (value, to) => {
+            let integer = match (value.integer(), value.float()) {
+                (Some(integer), _) => integer,
+                (_, Some(float)) => {
+                    let rounded = round_to_even(float);
+                    // the range of every integral type is well within the one of `i128`
+                    if !rounded.is_finite() || rounded.abs() > 1e30 {
+                        return Err(overflow());
+                    }
+                    rounded as i128
+                }
+                _ => anyhow::bail!("Can't convert {:?} into {}", value, to),
+            };
+
+            Value::checked(to, integer).ok_or_else(overflow)?
+        }
+    };
+
+    Ok(value)
+}
+
+/// `System.Math.Min` and `System.Math.Max` of .NET Framework, which Unity runs.
+///
+/// A `NaN` is returned as is, and of two equal operands the second one is returned.
+fn min_max(args: &[Value], min: bool) -> anyhow::Result<Value> {
+    if let Some((lhs, rhs)) = integers(args) {
+        let first = if min { lhs < rhs } else { lhs > rhs };
+        return Ok(args[if first { 0 } else { 1 }].clone());
+    }
+
+    let (Some(lhs), Some(rhs)) = (args[0].float(), args[1].float()) else {
+        anyhow::bail!("Can't compare {:?} and {:?}", args[0], args[1])
+    };
+    let first = if min { lhs < rhs } else { lhs > rhs };
+
+    Ok(args[if first || lhs.is_nan() { 0 } else { 1 }].clone())
+}
+
+/// Run the method `method` of `System.Math`.
+fn math(method: &str, args: &[Value]) -> anyhow::Result<Value> {
+    let double = || {
+        args[0]
+            .float()
+            .ok_or_else(|| anyhow::anyhow!("Not a float: {:?}", args[0]))
+    };
+
+    let value = match method {
+        "Min" => return min_max(args, true),
+        "Max" => return min_max(args, false),
+        "Abs" => match &args[0] {
+            Value::Single(value) => Value::Single(value.abs()),
+            Value::Double(value) => Value::Double(value.abs()),
+            value => {
+                let integer = value
+                    .integer()
+                    .ok_or_else(|| anyhow::anyhow!("Not a number: {:?}", value))?;
+                let ty = match value {
+                    Value::Int32(_) => UasmType::Int32,
+                    _ => UasmType::Int64,
+                };
+                // `Math.Abs(int.MinValue)` overflows
+                Value::checked(&ty, integer.abs()).ok_or_else(|| exception("OverflowException"))?
+            }
+        },
+        "Sqrt" => Value::Double(double()?.sqrt()),
+        "Truncate" => Value::Double(double()?.trunc()),
+        "Floor" => Value::Double(double()?.floor()),
+        "Ceiling" => Value::Double(double()?.ceil()),
+        "Round" => Value::Double(round_to_even(double()?)),
+        _ => anyhow::bail!("Unknown method of SystemMath: {}", method),
+    };
+
+    Ok(value)
+}
+
+/// Decode the base64 `text`, as `System.Convert.FromBase64String` does.
+fn from_base64(text: &str) -> anyhow::Result<Vec<u8>> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut bytes = Vec::new();
+    let mut word = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().take_while(|c| *c != b'=') {
+        let digit = ALPHABET
+            .iter()
+            .position(|digit| *digit == c)
+            .ok_or_else(|| exception("FormatException"))?;
+        word = word << 6 | digit as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((word >> bits) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn bytes(value: &Value) -> anyhow::Result<Vec<u8>> {
+    value
+        .as_array()?
+        .borrow()
+        .iter()
+        .map(|byte| match byte {
+            Value::Byte(byte) => Ok(*byte),
+            byte => anyhow::bail!("Not a byte: {:?}", byte),
+        })
+        .collect()
+}
+
+fn byte_array(bytes: &[u8]) -> Value {
+    Value::array(bytes.iter().map(|byte| Value::Byte(*byte)).collect())
+}
+
+/// Get the index `index` of an array of `len` elements, which throws if it's out of range.
+fn element_index(index: &Value, len: usize) -> anyhow::Result<usize> {
+    usize::try_from(index.as_index()?)
+        .ok()
+        .filter(|index| *index < len)
+        .ok_or_else(|| exception("IndexOutOfRangeException"))
+}
+
+/// A program loaded into the VM.
+pub(crate) struct Vm {
+    heap: HashMap<UasmVarName, Value>,
+    /// the opcode at each address
+    instructions: HashMap<u32, UasmOpcode>,
+    labels: HashMap<UasmCodeLabel, u32>,
+    /// the address after the last instruction
+    end: u32,
+    /// the messages logged with `UnityEngine.Debug`
+    pub(crate) logs: Vec<String>,
+}
+
+impl Vm {
+    /// Load a linked program whose addresses are resolved (see [`Uasm::resolve_addresses`]).
+    pub(crate) fn new(uasm: &Uasm) -> anyhow::Result<Vm> {
+        let mut heap = HashMap::new();
+        if let Some(data_section) = &uasm.data_section {
+            for data in data_section.get_data() {
+                let variable = &data.variable;
+                heap.insert(
+                    variable.name.clone(),
+                    Value::from_uasm(&variable.ty, &variable.value)?,
+                );
+            }
+        }
+
+        let mut instructions = HashMap::new();
+        let mut labels = HashMap::new();
+        let mut end = 0;
+        if let Some(code_section) = &uasm.code_section {
+            for (address, label, block) in code_section.get_code().blocks_with_addresses() {
+                labels.insert(label.clone(), address);
+                for (address, instruction) in block.instructions_with_addresses(address) {
+                    instructions.insert(address, instruction.opcode.clone());
+                    end = address + instruction.opcode.size();
+                }
+                end = end.max(address);
+            }
+        }
+
+        Ok(Vm {
+            heap,
+            instructions,
+            labels,
+            end,
+            logs: Vec::new(),
+        })
+    }
+
+    pub(crate) fn get(&self, var: &UasmVarName) -> anyhow::Result<&Value> {
+        self.heap
+            .get(var)
+            .ok_or_else(|| anyhow::anyhow!("Undefined variable: {}", var))
+    }
+
+    pub(crate) fn set(&mut self, var: &UasmVarName, value: Value) -> anyhow::Result<()> {
+        let slot = self
+            .heap
+            .get_mut(var)
+            .ok_or_else(|| anyhow::anyhow!("Undefined variable: {}", var))?;
+        *slot = value;
+
+        Ok(())
+    }
+
+    pub(crate) fn label_address(&self, label: &UasmCodeLabel) -> anyhow::Result<u32> {
+        match label.address() {
+            Some(address) => Ok(address),
+            None => self
+                .labels
+                .get(label)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("Undefined label: {}", label)),
+        }
+    }
+
+    /// Run the program from `label` until it halts.
+    pub(crate) fn run(&mut self, label: &UasmCodeLabel) -> anyhow::Result<()> {
+        let mut stack = Vec::new();
+        let mut address = self.label_address(label)?;
+
+        for _ in 0..MAX_STEPS {
+            if address >= HALT_ADDRESS || address >= self.end {
+                return Ok(());
+            }
+
+            let opcode = self
+                .instructions
+                .get(&address)
+                .ok_or_else(|| anyhow::anyhow!("No instruction at {:#x}", address))?
+                .clone();
+            let mut next = address + opcode.size();
+            let pop = |stack: &mut Vec<UasmVarName>| {
+                stack
+                    .pop()
+                    .ok_or_else(|| anyhow::anyhow!("Stack underflow at {:#x}", address))
+            };
+
+            match &opcode {
+                UasmOpcode::Nop | UasmOpcode::Annotation(_) => {}
+                UasmOpcode::Push(var) => {
+                    self.get(var)?;
+                    stack.push(var.clone());
+                }
+                UasmOpcode::Pop => {
+                    pop(&mut stack)?;
+                }
+                UasmOpcode::JumpIfFalse(label) => {
+                    let condition = pop(&mut stack)?;
+                    match self.get(&condition)? {
+                        Value::Boolean(true) => {}
+                        Value::Boolean(false) => next = self.label_address(label)?,
+                        value => anyhow::bail!("Not a condition: {} = {:?}", condition, value),
+                    }
+                }
+                UasmOpcode::Jump(label) => next = self.label_address(label)?,
+                UasmOpcode::JumpIndirect(var) => {
+                    next = match self.get(var)? {
+                        Value::UInt32(address) => *address,
+                        value => anyhow::bail!("Not an address: {} = {:?}", var, value),
+                    }
+                }
+                UasmOpcode::Copy => {
+                    let dst = pop(&mut stack)?;
+                    let src = pop(&mut stack)?;
+                    let value = self.get(&src)?.clone();
+                    self.set(&dst, value)?;
+                }
+                UasmOpcode::Extern(signature) => {
+                    let result = match signature.ret {
+                        Some(_) => Some(pop(&mut stack)?),
+                        None => None,
+                    };
+                    let mut args = Vec::new();
+                    for _ in 0..signature.params.len() {
+                        args.push(self.get(&pop(&mut stack)?)?.clone());
+                    }
+                    args.reverse();
+                    let instance = match signature.instance {
+                        Some(true) => Some(self.get(&pop(&mut stack)?)?.clone()),
+                        Some(false) => None,
+                        None => anyhow::bail!("Unknown instance of {}", signature),
+                    };
+
+                    let value = self
+                        .call_extern(signature, instance, &args)
+                        .map_err(|err| anyhow::anyhow!("{} in {}", err, signature))?;
+                    if let (Some(result), Some(value)) = (result, value) {
+                        self.set(&result, value)?;
+                    }
+                }
+            }
+
+            address = next;
+        }
+
+        anyhow::bail!("The program doesn't halt")
+    }
+
+    fn call_extern(
+        &mut self,
+        signature: &ExternSignature,
+        instance: Option<Value>,
+        args: &[Value],
+    ) -> anyhow::Result<Option<Value>> {
+        let class = UasmType::from_udon_name(&signature.class);
+        let ret = signature.ret.clone().unwrap_or(UasmType::Object);
+        let this = || {
+            instance
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("Missing instance"))
+        };
+
+        if let Some(op) = signature.method.strip_prefix("op_") {
+            return operator(&class, op, args, &ret).map(Some);
+        }
+
+        let value = match (signature.class.as_str(), signature.method.as_str()) {
+            ("SystemConvert", "FromBase64String") => byte_array(&from_base64(args[0].as_str()?)?),
+            ("SystemConvert", method) if method.starts_with("To") => convert(&args[0], &ret)?,
+            ("SystemMath", method) => math(method, args)?,
+            ("SystemBitConverter", "GetBytes") => {
+                let bytes = match &args[0] {
+                    Value::Int32(value) => value.to_le_bytes().to_vec(),
+                    Value::UInt32(value) => value.to_le_bytes().to_vec(),
+                    Value::Int64(value) => value.to_le_bytes().to_vec(),
+                    Value::UInt64(value) => value.to_le_bytes().to_vec(),
+                    Value::Single(value) => value.to_le_bytes().to_vec(),
+                    Value::Double(value) => value.to_le_bytes().to_vec(),
+                    value => anyhow::bail!("Can't get the bytes of {:?}", value),
+                };
+                byte_array(&bytes)
+            }
+            ("SystemBitConverter", method) if method.starts_with("To") => {
+                let bytes = bytes(&args[0])?;
+                let start = element_index(&args[1], bytes.len())?;
+                let size = (width(&ret) / 8) as usize;
+                let bytes = bytes
+                    .get(start..start + size)
+                    .ok_or_else(|| exception("ArgumentException"))?;
+                let mut word = [0; 8];
+                word[..size].copy_from_slice(bytes);
+                let word = u64::from_le_bytes(word);
+
+                match ret {
+                    UasmType::Single => Value::Single(f32::from_bits(word as u32)),
+                    UasmType::Double => Value::Double(f64::from_bits(word)),
+                    ty => Value::wrapping(&ty, word.into())
+                        .ok_or_else(|| anyhow::anyhow!("Not an integer: {}", ty))?,
+                }
+            }
+            ("SystemArray", "Copy") => {
+                let [src, src_index, dst, dst_index, len] = args else {
+                    anyhow::bail!("Wrong arguments")
+                };
+                let (src, dst) = (src.as_array()?, dst.as_array()?);
+                let (src_index, dst_index, len) = (
+                    src_index.as_index()?,
+                    dst_index.as_index()?,
+                    len.as_index()?,
+                );
+                let in_range = |index: i128, array: &Rc<RefCell<Vec<Value>>>| {
+                    index >= 0 && len >= 0 && index + len <= array.borrow().len() as i128
+                };
+                if !in_range(src_index, src) || !in_range(dst_index, dst) {
+                    return Err(exception("ArgumentException"));
+                }
+
+                // the elements are copied as if through a temporary array
+                let elements =
+                    src.borrow()[src_index as usize..(src_index + len) as usize].to_vec();
+                dst.borrow_mut()[dst_index as usize..(dst_index + len) as usize]
+                    .clone_from_slice(&elements);
+                return Ok(None);
+            }
+            (_, "ctor") => {
+                let UasmType::Array(element) = &ret else {
+                    anyhow::bail!("Unknown constructor")
+                };
+                let len = usize::try_from(args[0].as_index()?)
+                    .map_err(|_| exception("OverflowException"))?;
+                Value::array(vec![Value::default_of(element); len])
+            }
+            (_, "Get" | "GetValue") => {
+                let array = this()?;
+                let array = array.as_array()?.borrow();
+                array[element_index(&args[0], array.len())?].clone()
+            }
+            (_, "Set" | "SetValue") => {
+                let (index, value) = match signature.method.as_str() {
+                    "Set" => (&args[0], &args[1]),
+                    _ => (&args[1], &args[0]),
+                };
+                let array = this()?;
+                let mut array = array.as_array()?.borrow_mut();
+                let index = element_index(index, array.len())?;
+                array[index] = value.clone();
+                return Ok(None);
+            }
+            (_, "get_Length") => match this()? {
+                Value::String(string) => Value::Int32(string.encode_utf16().count() as i32),
+                array => Value::Int32(array.as_array()?.borrow().len() as i32),
+            },
+            ("SystemTextEncoding", "get_UTF8") => Value::Utf8,
+            ("SystemTextEncoding", "GetString") => {
+                // the invalid sequences are replaced with U+FFFD
+                Value::String(String::from_utf8_lossy(&bytes(&args[0])?).into_owned())
+            }
+            ("SystemTextEncoding", "GetBytes") => byte_array(args[0].as_str()?.as_bytes()),
+            ("SystemString", "Concat") => {
+                Value::String(args.iter().map(Value::text).collect::<Vec<_>>().concat())
+            }
+            ("UnityEngineDebug", "Log" | "LogWarning" | "LogError") => {
+                self.logs.push(args[0].text());
+                return Ok(None);
+            }
+            _ => anyhow::bail!("Extern not implemented by the test VM"),
+        };
+
+        Ok(Some(value))
+    }
+}
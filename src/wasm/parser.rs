@@ -8,7 +8,7 @@ pub struct WasmEntry<'a> {
 }
 
 impl WasmEntry<'_> {
-    pub fn new(data: &[u8], offset: u64) -> WasmEntry {
+    pub fn new(data: &[u8], offset: u64) -> WasmEntry<'_> {
         WasmEntry { data, offset }
     }
 }
@@ -63,20 +63,42 @@ where
         Ok(payload)
     }
 
-    pub fn parse_all(&mut self) -> anyhow::Result<ParsedData<wasmparser::Payload>> {
+    /// Validate the whole module and parse it.
+    ///
+    /// The translator relies on the module being valid, e.g. on the types of the operands of each operator.
+    pub fn parse_all(&mut self) -> anyhow::Result<ParsedData<wasmparser::Payload<'_>>> {
+        self.validate()?;
+
         let mut current = ParsedData::new(self.parse()?);
         let mut next = self.parse()?;
 
         loop {
-            if let wasmparser::Payload::End(_) = next {
+            let end = matches!(next, wasmparser::Payload::End(_));
+            log::info!("Update with: {:?}", &next);
+            current = current.update(ParsedData::new(next));
+
+            // the end is kept, as the code running at startup is generated there
+            if end {
                 log::info!("End of payload");
                 break;
             }
-            log::info!("Update with: {:?}", &next);
-            current = current.update(ParsedData::new(next));
             next = self.parse()?;
         }
 
         Ok(current)
     }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let features = wasmparser::WasmFeatures {
+            // the initializers of globals are folded by `const_eval`
+            extended_const: true,
+            ..Default::default()
+        };
+
+        wasmparser::Validator::new_with_features(features)
+            .validate_all(self.wasm_entry.data)
+            .map_err(|err| anyhow::anyhow!("Invalid wasm module: {}", err))?;
+
+        Ok(())
+    }
 }
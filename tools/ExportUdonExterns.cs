@@ -0,0 +1,66 @@
+// Export the externs of the Udon node registry as the JSON read by `wasdon --externs`.
+//
+// Copy this file into an `Editor` folder of a VRChat world project and run
+// `Tools/Wasdon/Export Udon Externs`. Each extern is written as
+// `{ "fullName": "...", "instance": true|false, "exposed": true|false }`.
+
+using System.Collections.Generic;
+using System.IO;
+using System.Linq;
+using System.Text;
+using UnityEditor;
+using VRC.Udon.Editor;
+using VRC.Udon.Graph;
+
+public static class ExportUdonExterns
+{
+    [MenuItem("Tools/Wasdon/Export Udon Externs")]
+    public static void Export()
+    {
+        var path = EditorUtility.SaveFilePanel("Export Udon Externs", "", "externs", "json");
+        if (string.IsNullOrEmpty(path))
+        {
+            return;
+        }
+
+        var definitions = UdonEditorManager.Instance.GetNodeDefinitions()
+            // the other nodes (events, variables, constants, flow control...) are not externs
+            .Where(definition => definition.fullName.Contains(".__"))
+            .GroupBy(definition => definition.fullName)
+            .Select(group => group.First())
+            .OrderBy(definition => definition.fullName, System.StringComparer.Ordinal)
+            .ToList();
+
+        var json = new StringBuilder();
+        json.Append("[\n");
+        for (var index = 0; index < definitions.Count; index++)
+        {
+            var definition = definitions[index];
+            json.Append("  { \"fullName\": \"");
+            json.Append(Escape(definition.fullName));
+            json.Append("\", \"instance\": ");
+            json.Append(IsInstance(definition) ? "true" : "false");
+            // the registry only holds the externs which can be used in VRChat worlds
+            json.Append(", \"exposed\": true }");
+            json.Append(index + 1 < definitions.Count ? ",\n" : "\n");
+        }
+        json.Append("]\n");
+
+        File.WriteAllText(path, json.ToString());
+        UnityEngine.Debug.Log($"Exported {definitions.Count} externs to {path}");
+    }
+
+    // An instance extern takes the instance as its first parameter, named `instance`.
+    private static bool IsInstance(UdonNodeDefinition definition)
+    {
+        var parameters = definition.parameters ?? new List<UdonNodeParameter>();
+        return parameters.Count > 0
+            && parameters[0].parameterType == UdonNodeParameter.ParameterType.IN
+            && parameters[0].name == "instance";
+    }
+
+    private static string Escape(string value)
+    {
+        return value.Replace("\\", "\\\\").Replace("\"", "\\\"");
+    }
+}